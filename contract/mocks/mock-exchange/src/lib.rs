@@ -0,0 +1,135 @@
+// Ref-style exchange for local swap tests. Tokens are deposited with an empty-msg
+// ft_transfer_call, swapped between deposits at a fixed per-pool rate, then withdrawn.
+// Payouts come from the exchange's own token balance, so tests fund it before swapping.
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, json_types::U128, log, near, require, serde::{Deserialize, Serialize},
+    store::LookupMap, AccountId, Gas, NearToken, PanicOnDefault, Promise, PromiseOrValue,
+};
+
+#[allow(dead_code)]
+#[ext_contract(ext_ft)]
+trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapAction {
+    pool_id: u64,
+    token_in: AccountId,
+    token_out: AccountId,
+    amount_in: Option<U128>,
+    min_amount_out: U128,
+}
+
+#[near(serializers = [borsh])]
+struct Rate {
+    numerator: u128,
+    denominator: u128,
+}
+
+#[near(contract_state)]
+#[derive(PanicOnDefault)]
+pub struct MockExchange {
+    rates: LookupMap<u64, Rate>,
+    deposits: LookupMap<(AccountId, AccountId), u128>,
+}
+
+#[near]
+impl MockExchange {
+    #[init]
+    pub fn new() -> Self {
+        Self { rates: LookupMap::new(b"r"), deposits: LookupMap::new(b"d") }
+    }
+
+    // amount_out = amount_in * numerator / denominator
+    pub fn set_rate(&mut self, pool_id: u64, numerator: U128, denominator: U128) {
+        require!(denominator.0 > 0, "Denominator must be positive");
+        self.rates.insert(pool_id, Rate { numerator: numerator.0, denominator: denominator.0 });
+    }
+
+    pub fn get_return(&self, pool_id: u64, amount_in: U128) -> U128 {
+        let rate = self.rates.get(&pool_id).expect("E404: pool not found");
+        U128(amount_in.0 * rate.numerator / rate.denominator)
+    }
+
+    pub fn get_deposit(&self, account_id: AccountId, token_id: AccountId) -> U128 {
+        U128(self.deposits.get(&(account_id, token_id)).copied().unwrap_or(0))
+    }
+
+    // Deposits only; instant swaps through msg are not supported
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        require!(msg.is_empty(), "Mock exchange only accepts deposits");
+        let token_id = env::predecessor_account_id();
+        self.credit(&sender_id, &token_id, amount.0);
+        PromiseOrValue::Value(U128(0))
+    }
+
+    // Swaps between the caller's deposits and returns the amount credited
+    #[payable]
+    pub fn swap(&mut self, actions: Vec<SwapAction>, referral_id: Option<AccountId>) -> U128 {
+        assert_one_yocto();
+        let _ = referral_id;
+        require!(actions.len() == 1, "Mock exchange supports single-hop swaps only");
+        let action = &actions[0];
+        let account_id = env::predecessor_account_id();
+
+        let amount_in = action.amount_in.expect("E35: amount_in required").0;
+        let amount_out = self.get_return(action.pool_id, U128(amount_in)).0;
+        require!(amount_out >= action.min_amount_out.0, "E68: slippage error");
+        self.debit(&account_id, &action.token_in, amount_in);
+        self.credit(&account_id, &action.token_out, amount_out);
+
+        log!("Swapped {} {} for {} {}", amount_in, action.token_in, amount_out, action.token_out);
+        U128(amount_out)
+    }
+
+    // Debited up front and re-credited if the transfer fails
+    #[payable]
+    pub fn withdraw(&mut self, token_id: AccountId, amount: U128, unregister: Option<bool>) -> Promise {
+        assert_one_yocto();
+        let _ = unregister;
+        let account_id = env::predecessor_account_id();
+        self.debit(&account_id, &token_id, amount.0);
+
+        ext_ft::ext(token_id.clone())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(Gas::from_tgas(10))
+            .ft_transfer(account_id.clone(), amount, None)
+            .then(Self::ext(env::current_account_id()).with_static_gas(Gas::from_tgas(10)).on_withdraw(
+                account_id,
+                token_id,
+                amount,
+            ))
+    }
+
+    // Returns the amount withdrawn, or 0 when the transfer failed and the deposit was restored
+    #[private]
+    pub fn on_withdraw(
+        &mut self,
+        account_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+        #[callback_result] transferred: Result<(), near_sdk::PromiseError>,
+    ) -> U128 {
+        if transferred.is_err() {
+            self.credit(&account_id, &token_id, amount.0);
+            log!("Withdrawal of {} {} failed; deposit restored", amount.0, token_id);
+            return U128(0);
+        }
+        amount
+    }
+
+    fn credit(&mut self, account_id: &AccountId, token_id: &AccountId, amount: u128) {
+        let key = (account_id.clone(), token_id.clone());
+        let balance = self.deposits.get(&key).copied().unwrap_or(0);
+        self.deposits.insert(key, balance + amount);
+    }
+
+    fn debit(&mut self, account_id: &AccountId, token_id: &AccountId, amount: u128) {
+        let key = (account_id.clone(), token_id.clone());
+        let balance = self.deposits.get(&key).copied().unwrap_or(0);
+        require!(balance >= amount, "E22: not enough tokens in deposit");
+        self.deposits.insert(key, balance - amount);
+    }
+}
@@ -0,0 +1,108 @@
+// Minimal NEP-141 token for local swap tests. Storage registration is a no-op.
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, json_types::U128, log, near, require, store::LookupMap, AccountId, Gas,
+    PanicOnDefault, PromiseOrValue, PromiseResult,
+};
+
+#[allow(dead_code)]
+#[ext_contract(ext_receiver)]
+trait FungibleTokenReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128>;
+}
+
+#[near(contract_state)]
+#[derive(PanicOnDefault)]
+pub struct MockFungibleToken {
+    balances: LookupMap<AccountId, u128>,
+    total_supply: u128,
+}
+
+#[near]
+impl MockFungibleToken {
+    #[init]
+    pub fn new(owner_id: AccountId, total_supply: U128) -> Self {
+        let mut balances = LookupMap::new(b"b");
+        balances.insert(owner_id, total_supply.0);
+        Self { balances, total_supply: total_supply.0 }
+    }
+
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>) {
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        if !self.balances.contains_key(&account_id) {
+            self.balances.insert(account_id, 0);
+        }
+    }
+
+    #[payable]
+    pub fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        log_memo(memo);
+        self.internal_transfer(&env::predecessor_account_id(), &receiver_id, amount.0);
+    }
+
+    #[payable]
+    pub fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        log_memo(memo);
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer(&sender_id, &receiver_id, amount.0);
+
+        ext_receiver::ext(receiver_id.clone())
+            .with_static_gas(Gas::from_tgas(30))
+            .ft_on_transfer(sender_id.clone(), amount, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Gas::from_tgas(10))
+                    .ft_resolve_transfer(sender_id, receiver_id, amount),
+            )
+            .into()
+    }
+
+    #[private]
+    pub fn ft_resolve_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> U128 {
+        let unused = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map_or(amount.0, |unused| std::cmp::min(amount.0, unused.0)),
+            PromiseResult::Failed => amount.0,
+        };
+        let refund = std::cmp::min(unused, self.balance_of(&receiver_id));
+        if refund > 0 {
+            self.internal_transfer(&receiver_id, &sender_id, refund);
+        }
+        U128(amount.0 - refund)
+    }
+
+    pub fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        U128(self.balance_of(&account_id))
+    }
+
+    pub fn ft_total_supply(&self) -> U128 {
+        U128(self.total_supply)
+    }
+
+    fn balance_of(&self, account_id: &AccountId) -> u128 {
+        self.balances.get(account_id).copied().unwrap_or(0)
+    }
+
+    fn internal_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) {
+        let sender_balance = self.balance_of(sender_id);
+        require!(sender_balance >= amount, "Not enough balance");
+        require!(self.balances.contains_key(receiver_id), "Receiver is not registered");
+        self.balances.insert(sender_id.clone(), sender_balance - amount);
+        let receiver_balance = self.balance_of(receiver_id);
+        self.balances.insert(receiver_id.clone(), receiver_balance + amount);
+    }
+}
+
+fn log_memo(memo: Option<String>) {
+    if let Some(memo) = memo {
+        log!("Memo: {}", memo);
+    }
+}
@@ -0,0 +1,257 @@
+use near_sdk::{
+    env, log, near, require,
+    serde::{Deserialize, Serialize},
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset};
+
+// Safest first; assets missing from the registry are treated as long-tail
+pub const RISK_TIERS: [&str; 4] = ["stable", "blue_chip", "large_cap", "long_tail"];
+const DEPEGGED_SAFETY: f64 = 10.0;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetMetadata {
+    pub symbol: String,
+    pub risk_tier: String, // one of RISK_TIERS
+    pub volatility_pct: Option<String>, // annualized estimate, e.g. "65.0"
+    pub depegged: bool, // only meaningful for stable assets
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    pub fn set_asset_metadata(&mut self, metadata: AssetMetadata) {
+        self.require_owner();
+        require!(RISK_TIERS.contains(&metadata.risk_tier.as_str()), "Unknown risk tier");
+        if let Some(volatility) = &metadata.volatility_pct {
+            require!(
+                volatility.parse::<f64>().is_ok_and(|v| v >= 0.0),
+                "Volatility must be a non-negative number"
+            );
+        }
+        self.asset_metadata.insert(metadata.symbol.clone(), metadata);
+    }
+
+    // Depeg flags move faster than governance, so trusted workers may set them too
+    pub fn set_asset_depegged(&mut self, symbol: String, depegged: bool) {
+        if env::predecessor_account_id() != self.owner_id {
+            self.require_trusted_worker();
+        }
+        let mut metadata = self.asset_metadata.get(&symbol).cloned().expect("Asset not in registry");
+        require!(metadata.risk_tier == "stable", "Only stable assets can be flagged as depegged");
+        metadata.depegged = depegged;
+        self.asset_metadata.insert(symbol.clone(), metadata);
+        log!("Asset {} depegged: {}", symbol, depegged);
+    }
+
+    pub fn get_asset_metadata(&self, symbol: String) -> AssetMetadata {
+        self.asset_info(&symbol)
+    }
+
+    pub fn get_asset_registry(&self, from_index: u64, limit: u64) -> Vec<AssetMetadata> {
+        self.asset_metadata
+            .values()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    // Registry helpers
+    pub(crate) fn asset_info(&self, symbol: &str) -> AssetMetadata {
+        self.asset_metadata.get(symbol).cloned().unwrap_or_else(|| AssetMetadata {
+            symbol: symbol.to_string(),
+            risk_tier: "long_tail".to_string(),
+            volatility_pct: None,
+            depegged: false,
+        })
+    }
+
+    // 0-100, higher is safer: the tier's base, lowered by high volatility or a depeg. Realized
+    // volatility from price history takes precedence over the registry's estimate.
+    pub(crate) fn asset_safety(&self, symbol: &str) -> f64 {
+        let info = self.asset_info(symbol);
+        if info.depegged {
+            return DEPEGGED_SAFETY;
+        }
+        let base: f64 = match info.risk_tier.as_str() {
+            "stable" => 100.0,
+            "blue_chip" => 60.0,
+            "large_cap" => 45.0,
+            _ => 30.0,
+        };
+        let annual_volatility = self
+            .realized_volatility(symbol)
+            .map(|daily| daily * 365f64.sqrt() * 100.0)
+            .or_else(|| info.volatility_pct.and_then(|v| v.parse::<f64>().ok()));
+        match annual_volatility {
+            Some(volatility) => base.min((100.0 - volatility).max(0.0)),
+            None => base,
+        }
+    }
+
+    // Share of portfolio value (0-100) held in assets of `tier`
+    pub(crate) fn tier_share(&self, portfolio: &[PortfolioAsset], tier: &str) -> f64 {
+        let mut total = 0.0;
+        let mut in_tier = 0.0;
+        for asset in portfolio {
+            let value: f64 = asset.value_usd.parse().unwrap_or(0.0);
+            total += value;
+            if self.asset_info(&asset.token_symbol).risk_tier == tier {
+                in_tier += value;
+            }
+        }
+        if total > 0.0 {
+            in_tier / total * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    // First stablecoin still holding its peg, for parking proceeds
+    pub(crate) fn preferred_stable(&self) -> String {
+        self.asset_metadata
+            .values()
+            .find(|metadata| metadata.risk_tier == "stable" && !metadata.depegged)
+            .map_or_else(|| "USDC".to_string(), |metadata| metadata.symbol.clone())
+    }
+
+    // A depegged allocation is swapped for the first healthy asset of the same tier, if any
+    pub(crate) fn replace_depegged(&self, mut allocation: PortfolioAsset) -> PortfolioAsset {
+        let info = self.asset_info(&allocation.token_symbol);
+        if !info.depegged {
+            return allocation;
+        }
+        if let Some(substitute) = self
+            .asset_metadata
+            .values()
+            .find(|candidate| candidate.risk_tier == info.risk_tier && !candidate.depegged)
+        {
+            allocation.token_symbol = substitute.symbol.clone();
+            allocation.token_address = substitute.symbol.to_lowercase();
+        }
+        allocation
+    }
+}
+
+pub(crate) fn default_asset_registry() -> Vec<AssetMetadata> {
+    let tiers: [(&str, &[&str]); 4] = [
+        ("stable", &["USDC", "USDT", "DAI"]),
+        ("blue_chip", &["BTC", "ETH", "WBTC", "WETH"]),
+        ("large_cap", &["NEAR", "LINK", "UNI", "AAVE"]),
+        ("long_tail", &["COMP"]),
+    ];
+    tiers
+        .iter()
+        .flat_map(|(tier, symbols)| {
+            symbols.iter().map(move |symbol| AssetMetadata {
+                symbol: symbol.to_string(),
+                risk_tier: tier.to_string(),
+                volatility_pct: None,
+                depegged: false,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn holding(symbol: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: value_usd.to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    #[test]
+    fn registry_starts_with_the_default_tiers() {
+        let contract = setup();
+        let tiers: Vec<(String, String)> = ["USDC", "ETH", "NEAR", "COMP"]
+            .iter()
+            .map(|symbol| {
+                let info = contract.get_asset_metadata(symbol.to_string());
+                (info.symbol, info.risk_tier)
+            })
+            .collect();
+        assert_eq!(
+            tiers,
+            [("USDC", "stable"), ("ETH", "blue_chip"), ("NEAR", "large_cap"), ("COMP", "long_tail")]
+                .map(|(symbol, tier)| (symbol.to_string(), tier.to_string()))
+        );
+        assert_eq!(contract.get_asset_registry(0, 100).len(), default_asset_registry().len());
+        assert!(default_asset_registry().iter().all(|metadata| contract.supported_assets.contains(&metadata.symbol)));
+    }
+
+    #[test]
+    fn unknown_assets_default_to_long_tail() {
+        let contract = setup();
+        let info = contract.get_asset_metadata("PEPE".to_string());
+        assert_eq!((info.risk_tier.as_str(), info.depegged, info.volatility_pct), ("long_tail", false, None));
+        assert_eq!(contract.asset_safety("PEPE"), 30.0);
+    }
+
+    #[test]
+    fn safety_follows_the_tier_capped_by_volatility() {
+        let mut contract = setup();
+        let safety: Vec<f64> = ["USDC", "ETH", "NEAR", "COMP"].iter().map(|symbol| contract.asset_safety(symbol)).collect();
+        assert_eq!(safety, [100.0, 60.0, 45.0, 30.0]);
+
+        call_as(OWNER);
+        contract.set_asset_metadata(AssetMetadata {
+            symbol: "ETH".to_string(),
+            risk_tier: "blue_chip".to_string(),
+            volatility_pct: Some("65".to_string()),
+            depegged: false,
+        });
+        assert_eq!(contract.asset_safety("ETH"), 35.0);
+    }
+
+    #[test]
+    fn depegged_stables_score_low_and_are_replaced() {
+        let mut contract = setup();
+        let portfolio = [holding("USDC", "500"), holding("ETH", "500")];
+        let user = account("alice.near");
+        let pegged = contract.calculate_portfolio_health(&portfolio, &user);
+
+        call_as(OWNER);
+        contract.set_asset_depegged("USDC".to_string(), true);
+        assert_eq!(contract.asset_safety("USDC"), DEPEGGED_SAFETY);
+        let depegged = contract.calculate_portfolio_health(&portfolio, &user);
+        assert!(depegged.score < pegged.score);
+        assert!(depegged
+            .recommendations
+            .contains(&"USDC has lost its peg; consider moving to another stablecoin".to_string()));
+        assert!(!pegged.recommendations.iter().any(|r| r.contains("lost its peg")));
+
+        // Proceeds are parked in, and allocations moved to, the next healthy stablecoin
+        assert_eq!(contract.preferred_stable(), "USDT");
+        let replaced = contract.replace_depegged(holding("USDC", "100"));
+        assert_eq!((replaced.token_symbol.as_str(), replaced.token_address.as_str()), ("USDT", "usdt"));
+        assert_eq!(contract.replace_depegged(holding("ETH", "100")).token_symbol, "ETH");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only stable assets can be flagged as depegged")]
+    fn only_stables_can_depeg() {
+        let mut contract = setup();
+        call_as(OWNER);
+        contract.set_asset_depegged("ETH".to_string(), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Worker not registered or not trusted")]
+    fn strangers_cannot_flag_a_depeg() {
+        let mut contract = setup();
+        call_as("mallory.near");
+        contract.set_asset_depegged("USDC".to_string(), true);
+    }
+}
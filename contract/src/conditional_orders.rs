@@ -0,0 +1,510 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{
+    apply_target_deltas, vault::parse_micro_usd, AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset, TargetDelta,
+};
+
+const ORDER_KINDS: [&str; 3] = ["stop_loss", "take_profit", "limit_buy"];
+// Orders triggered by one price update; the rest trigger on the next update
+const MAX_TRIGGERS_PER_UPDATE: usize = 20;
+// Upper bound on the bytes one triggered order adds (intent, trade and history entry)
+const TRIGGERED_ORDER_BYTES: u64 = 2_000;
+const MAX_TRIGGER_HISTORY: usize = 50;
+const MAX_ACTIVE_ORDERS_PER_USER: u32 = 20;
+
+// Active orders for one asset as (trigger price in micro-USD, order id), sorted so the orders a
+// price move reaches first are at the front
+#[derive(BorshSerialize, BorshDeserialize, Clone, Default)]
+pub struct AssetOrderBook {
+    pub falling: Vec<(u128, u64)>, // stop_loss and limit_buy, highest trigger first
+    pub rising: Vec<(u128, u64)>,  // take_profit, lowest trigger first
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConditionalOrderInput {
+    pub kind: String, // "stop_loss", "take_profit", "limit_buy"
+    pub asset: String,
+    pub quote_asset: String, // sold for limit_buy, received for stop_loss and take_profit
+    pub chain: String,
+    pub amount: String, // of asset when selling, of quote_asset for limit_buy
+    pub trigger_price_usd: String,
+    pub max_slippage_bps: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConditionalOrder {
+    pub id: u64,
+    pub user_id: String,
+    pub kind: String,
+    pub asset: String,
+    pub quote_asset: String,
+    pub chain: String,
+    pub amount: String,
+    pub trigger_price_usd: String,
+    pub max_slippage_bps: Option<u16>,
+    pub status: String, // "active", "triggered", "failed", "cancelled"
+    pub created_at: u64,
+    pub intent_id: Option<u64>, // set once triggered
+    pub trade_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderTrigger {
+    pub order_id: u64,
+    pub price_usd: String,
+    pub triggered_at: u64,
+    pub trade_id: Option<u64>,
+    pub outcome: String, // "trade_created" or the reason no trade was created
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Conditional order management
+    #[payable]
+    pub fn create_conditional_order(&mut self, order: ConditionalOrderInput) -> u64 {
+        let user_id = env::predecessor_account_id();
+        require!(ORDER_KINDS.contains(&order.kind.as_str()), "Unknown order kind");
+        require!(self.supported_assets.contains(&order.asset), "Asset not supported");
+        require!(self.supported_assets.contains(&order.quote_asset), "Quote asset not supported");
+        require!(order.asset != order.quote_asset, "Asset and quote asset must differ");
+        require!(self.supported_chains.contains(&order.chain), "Chain not supported");
+        for value in [&order.amount, &order.trigger_price_usd] {
+            require!(value.parse::<f64>().is_ok_and(|v| v > 0.0), "Amount and trigger price must be positive numbers");
+        }
+        let trigger = parse_micro_usd(&order.trigger_price_usd)
+            .filter(|trigger| *trigger > 0)
+            .expect("Trigger price must be a decimal of at least $0.000001");
+        let active = self.active_order_counts.get(&user_id).copied().unwrap_or(0);
+        require!(
+            active < MAX_ACTIVE_ORDERS_PER_USER,
+            format!("At most {} conditional orders can be active at once", MAX_ACTIVE_ORDERS_PER_USER)
+        );
+        if let Some(bps) = order.max_slippage_bps {
+            require!(bps <= 10_000, "Slippage must be at most 10000 bps");
+        }
+
+        let initial_storage = self.begin_storage_charge(&user_id);
+        if !self.users.contains(&user_id) {
+            self.internal_register_user(user_id.clone());
+        }
+
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+        let mut book = self.active_orders_by_asset.get(&order.asset).cloned().unwrap_or_default();
+        if order.kind == "take_profit" {
+            let index = book.rising.partition_point(|(price, _)| *price <= trigger);
+            book.rising.insert(index, (trigger, order_id));
+        } else {
+            let index = book.falling.partition_point(|(price, _)| *price >= trigger);
+            book.falling.insert(index, (trigger, order_id));
+        }
+        self.active_orders_by_asset.insert(order.asset.clone(), book);
+        self.active_order_counts.insert(user_id.clone(), active + 1);
+        self.conditional_orders.insert(order_id, ConditionalOrder {
+            id: order_id,
+            user_id: user_id.to_string(),
+            kind: order.kind,
+            asset: order.asset,
+            quote_asset: order.quote_asset,
+            chain: order.chain,
+            amount: order.amount,
+            trigger_price_usd: order.trigger_price_usd,
+            max_slippage_bps: order.max_slippage_bps,
+            status: "active".to_string(),
+            created_at: block_timestamp(),
+            intent_id: None,
+            trade_id: None,
+        });
+        let mut ids = self.user_orders.get(&user_id).cloned().unwrap_or_default();
+        ids.push(order_id);
+        self.user_orders.insert(user_id.clone(), ids);
+        self.settle_storage_charge(&user_id, initial_storage);
+
+        log!("Conditional order {} created by {}", order_id, user_id);
+        order_id
+    }
+
+    #[payable]
+    pub fn cancel_conditional_order(&mut self, order_id: u64) {
+        let mut order = self.conditional_orders.get(&order_id).cloned().expect("Order not found");
+        let user_id = env::predecessor_account_id();
+        require!(user_id.as_str() == order.user_id, "Only the order creator can cancel it");
+        require!(order.status == "active", "Order is no longer active");
+        let initial_storage = self.begin_storage_charge(&user_id);
+
+        order.status = "cancelled".to_string();
+        self.remove_active_order(&order.asset, order_id);
+        self.release_active_order(&user_id);
+        self.conditional_orders.insert(order_id, order);
+        self.settle_storage_charge(&user_id, initial_storage);
+        log!("Conditional order {} cancelled", order_id);
+    }
+
+    // Conditional order views
+    pub fn get_conditional_order(&self, order_id: u64) -> Option<ConditionalOrder> {
+        self.conditional_orders.get(&order_id).cloned()
+    }
+
+    pub fn get_user_conditional_orders(&self, user_id: String) -> Vec<ConditionalOrder> {
+        let user_id: AccountId = user_id.parse().unwrap();
+        self.user_orders
+            .get(&user_id)
+            .map(|ids| ids.iter().filter_map(|id| self.conditional_orders.get(id).cloned()).collect())
+            .unwrap_or_default()
+    }
+
+    // Falling-price orders first, each side in the order a price move triggers them
+    pub fn get_active_orders_for_asset(&self, asset: String) -> Vec<ConditionalOrder> {
+        self.active_orders_by_asset
+            .get(&asset)
+            .map(|book| {
+                book.falling
+                    .iter()
+                    .chain(&book.rising)
+                    .filter_map(|(_, id)| self.conditional_orders.get(id).cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Most recent last; only the latest MAX_TRIGGER_HISTORY entries are kept
+    pub fn get_order_trigger_history(&self, user_id: String) -> Vec<OrderTrigger> {
+        let user_id: AccountId = user_id.parse().unwrap();
+        self.order_triggers.get(&user_id).cloned().unwrap_or_default()
+    }
+
+    // Conditional order helpers
+    // Called after every price update for `asset`. Triggered orders form a prefix of each side
+    // of the book, so only those entries are read.
+    pub(crate) fn evaluate_conditional_orders(&mut self, asset: &str, price_usd: &str) {
+        let price = match parse_micro_usd(price_usd) {
+            Some(price) if price > 0 => price,
+            _ => return,
+        };
+        let mut book = match self.active_orders_by_asset.get(asset) {
+            Some(book) => book.clone(),
+            None => return,
+        };
+        let falling = book.falling.partition_point(|(trigger, _)| price <= *trigger).min(MAX_TRIGGERS_PER_UPDATE);
+        let rising = book.rising.partition_point(|(trigger, _)| price >= *trigger).min(MAX_TRIGGERS_PER_UPDATE - falling);
+        if falling + rising == 0 {
+            return;
+        }
+        let triggered: Vec<u64> = book.falling.drain(..falling).chain(book.rising.drain(..rising)).map(|(_, id)| id).collect();
+        if book.falling.is_empty() && book.rising.is_empty() {
+            self.active_orders_by_asset.remove(asset);
+        } else {
+            self.active_orders_by_asset.insert(asset.to_string(), book);
+        }
+
+        for order_id in triggered {
+            let order = self.conditional_orders.get(&order_id).cloned().unwrap();
+            self.trigger_order(order, price_usd);
+        }
+    }
+
+    fn trigger_order(&mut self, mut order: ConditionalOrder, price_usd: &str) {
+        let user_id: AccountId = order.user_id.parse().unwrap();
+        self.release_active_order(&user_id);
+
+        let (trade_id, outcome) = if self.can_cover_storage(&user_id, TRIGGERED_ORDER_BYTES) {
+            let initial_storage = env::storage_usage();
+            let result = self.create_order_trade(&mut order, &user_id, price_usd);
+            self.settle_storage_charge(&user_id, initial_storage);
+            match result {
+                Ok(trade_id) => (Some(trade_id), "trade_created".to_string()),
+                Err(reason) => (None, reason),
+            }
+        } else {
+            (None, "Insufficient storage balance to create the trade".to_string())
+        };
+
+        order.status = if trade_id.is_some() { "triggered" } else { "failed" }.to_string();
+        order.trade_id = trade_id;
+        log!("Conditional order {} triggered at ${}: {}", order.id, price_usd, outcome);
+
+        let mut history = self.order_triggers.get(&user_id).cloned().unwrap_or_default();
+        history.push(OrderTrigger {
+            order_id: order.id,
+            price_usd: price_usd.to_string(),
+            triggered_at: block_timestamp(),
+            trade_id,
+            outcome,
+        });
+        if history.len() > MAX_TRIGGER_HISTORY {
+            history.remove(0);
+        }
+        self.order_triggers.insert(user_id, history);
+        self.conditional_orders.insert(order.id, order);
+    }
+
+    // The order itself is the user's approval of its plan. The plan still goes through the
+    // allocation, health floor and execution limits that gate analyzed intents.
+    fn create_order_trade(&mut self, order: &mut ConditionalOrder, user_id: &AccountId, price_usd: &str) -> Result<u64, String> {
+        let (from_asset, to_asset) = if order.kind == "limit_buy" {
+            (order.quote_asset.clone(), order.asset.clone())
+        } else {
+            (order.asset.clone(), order.quote_asset.clone())
+        };
+
+        let intent_text = format!(
+            "{} {} {} for {} at ${}",
+            order.kind, order.amount, from_asset, to_asset, price_usd
+        );
+        let intent_id = self.internal_submit_intent(user_id.clone(), intent_text, None);
+        order.intent_id = Some(intent_id);
+        let mut intent = self.intents.get(&intent_id).cloned().unwrap();
+        intent.classification = "conditional_order".to_string();
+        intent.confidence_score = 100;
+        intent.ai_analysis = format!("Conditional order {} triggered at ${}", order.id, price_usd);
+        intent.max_slippage_bps = order.max_slippage_bps;
+
+        let portfolio = self.user_portfolios.get(user_id).cloned().unwrap_or_default();
+        let planned = order_target_allocations(&portfolio, &from_asset, &to_asset, &order.chain, &order.amount, |symbol| {
+            self.price_of(symbol)
+        });
+        match planned {
+            Some(allocations) => {
+                intent.target_allocations = allocations;
+                intent.execution_steps = self.generate_execution_steps(&intent.target_allocations);
+                self.complete_intent_analysis(&mut intent);
+            }
+            None => {
+                intent.status = "rejected".to_string();
+                intent.rejection_reason = Some(format!("Portfolio holds no {} on {} to sell", from_asset, order.chain));
+            }
+        }
+        if intent.status == "rejected" {
+            let reason = intent.rejection_reason.clone().unwrap_or_default();
+            self.intents.insert(intent_id, intent);
+            return Err(reason);
+        }
+
+        intent.approved_plan_hash = Some(self.intent_plan_hash(&intent));
+        intent.status = "ready".to_string();
+        let trade_id = self.next_trade_id;
+        self.internal_execute_rebalance(intent);
+
+        let intent = self.intents.get(&intent_id).unwrap();
+        match intent.status.as_str() {
+            "executing" => Ok(trade_id),
+            _ => Err(intent.rejection_reason.clone().unwrap_or_else(|| format!("Intent is {}", intent.status))),
+        }
+    }
+
+    fn remove_active_order(&mut self, asset: &str, order_id: u64) {
+        let mut book = self.active_orders_by_asset.get(asset).cloned().unwrap_or_default();
+        book.falling.retain(|(_, id)| *id != order_id);
+        book.rising.retain(|(_, id)| *id != order_id);
+        if book.falling.is_empty() && book.rising.is_empty() {
+            self.active_orders_by_asset.remove(asset);
+        } else {
+            self.active_orders_by_asset.insert(asset.to_string(), book);
+        }
+    }
+
+    fn release_active_order(&mut self, user_id: &AccountId) {
+        let active = self.active_order_counts.get(user_id).copied().unwrap_or(0);
+        if active <= 1 {
+            self.active_order_counts.remove(user_id);
+        } else {
+            self.active_order_counts.insert(user_id.clone(), active - 1);
+        }
+    }
+}
+
+// The portfolio after selling `amount` of from_asset held on `chain` into to_asset on the same
+// chain, valued at the portfolio's own prices; None when nothing is held there
+fn order_target_allocations(
+    portfolio: &[PortfolioAsset],
+    from_asset: &str,
+    to_asset: &str,
+    chain: &str,
+    amount: &str,
+    price: impl Fn(&str) -> Option<f64>,
+) -> Option<Vec<PortfolioAsset>> {
+    let parse = |value: &str| value.parse::<f64>().unwrap_or(0.0);
+    let mut allocations = portfolio.to_vec();
+    let held = allocations
+        .iter_mut()
+        .find(|a| a.token_symbol == from_asset && a.chain == chain)
+        .filter(|a| parse(&a.balance) > 0.0)?;
+    let (balance, value) = (parse(&held.balance), parse(&held.value_usd));
+    let sold = parse(amount).min(balance);
+    let moved_usd = value * sold / balance;
+    held.balance = format!("{:.8}", balance - sold);
+    held.value_usd = format!("{:.2}", value - moved_usd);
+
+    let current_usd: f64 = allocations
+        .iter()
+        .filter(|a| a.token_symbol == to_asset && a.chain == chain)
+        .map(|a| parse(&a.value_usd))
+        .sum();
+    let buy = TargetDelta {
+        asset: to_asset.to_string(),
+        chain: Some(chain.to_string()),
+        current_usd: format!("{:.2}", current_usd),
+        target_usd: format!("{:.2}", current_usd + moved_usd),
+        delta_usd: format!("{:.2}", moved_usd),
+    };
+    Some(apply_target_deltas(&allocations, &[buy], price))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::*, RiskLimits, UserPreferences};
+
+    const USER: &str = "alice.near";
+
+    fn holding(symbol: &str, balance: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: balance.to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    // alice holds 1 ETH, 0.05 BTC and 1000 USDC on ethereum
+    fn orders_setup() -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        contract.set_user_portfolio(vec![
+            holding("ETH", "1", "2800"),
+            holding("BTC", "0.05", "2100"),
+            holding("USDC", "1000", "1000"),
+        ]);
+        contract
+    }
+
+    fn order(kind: &str, asset: &str, quote_asset: &str, amount: &str, trigger_price_usd: &str) -> ConditionalOrderInput {
+        ConditionalOrderInput {
+            kind: kind.to_string(),
+            asset: asset.to_string(),
+            quote_asset: quote_asset.to_string(),
+            chain: "ethereum".to_string(),
+            amount: amount.to_string(),
+            trigger_price_usd: trigger_price_usd.to_string(),
+            max_slippage_bps: None,
+        }
+    }
+
+    fn set_price(contract: &mut AIPortfolioRebalancer, asset: &str, price_usd: &str) {
+        call_as(OWNER);
+        contract.update_asset_price(asset.to_string(), price_usd.to_string());
+        call_as(USER);
+    }
+
+    fn last_outcome(contract: &AIPortfolioRebalancer) -> String {
+        contract.get_order_trigger_history(USER.to_string()).last().unwrap().outcome.clone()
+    }
+
+    #[test]
+    fn only_crossed_orders_trigger() {
+        let mut contract = orders_setup();
+        let low_stop = contract.create_conditional_order(order("stop_loss", "ETH", "USDC", "0.1", "2500"));
+        let high_stop = contract.create_conditional_order(order("stop_loss", "ETH", "USDC", "0.1", "2700"));
+        let take_profit = contract.create_conditional_order(order("take_profit", "ETH", "USDC", "0.1", "3500"));
+        let limit_buy = contract.create_conditional_order(order("limit_buy", "ETH", "USDC", "100", "2600"));
+
+        set_price(&mut contract, "ETH", "2650");
+        let triggered = contract.get_conditional_order(high_stop).unwrap();
+        assert_eq!(triggered.status, "triggered");
+        let remaining: Vec<u64> = contract.get_active_orders_for_asset("ETH".to_string()).iter().map(|o| o.id).collect();
+        assert_eq!(remaining, vec![limit_buy, low_stop, take_profit]);
+
+        let trade = contract.get_trade(triggered.trade_id.unwrap()).unwrap();
+        assert_eq!((trade.from_asset.as_str(), trade.to_asset.as_str()), ("ETH", "USDC"));
+        assert_eq!(trade.amount, "0.10000000");
+        assert_eq!(contract.get_intent(triggered.intent_id.unwrap()).unwrap().status, "executing");
+
+        set_price(&mut contract, "ETH", "3600");
+        assert_eq!(contract.get_conditional_order(take_profit).unwrap().status, "triggered");
+        assert_eq!(contract.get_active_orders_for_asset("ETH".to_string()).len(), 2);
+    }
+
+    #[test]
+    fn triggered_orders_respect_risk_limits() {
+        let mut contract = orders_setup();
+        contract.set_user_risk_limits(RiskLimits {
+            max_trade_usd: None,
+            max_daily_volume_usd: None,
+            max_asset_allocation_pct: Some("50".to_string()),
+            max_chain_allocation_pct: None,
+        });
+        let order_id = contract.create_conditional_order(order("stop_loss", "BTC", "USDC", "0.05", "41000"));
+
+        set_price(&mut contract, "BTC", "40000");
+        let order = contract.get_conditional_order(order_id).unwrap();
+        assert_eq!(order.status, "failed");
+        assert_eq!(order.trade_id, None);
+        assert!(last_outcome(&contract).starts_with("Allocation of 52.54% to USDC exceeds the per-asset cap"));
+        assert_eq!(contract.get_intent(order.intent_id.unwrap()).unwrap().status, "rejected");
+    }
+
+    #[test]
+    fn triggered_orders_respect_the_health_floor() {
+        let mut contract = orders_setup();
+        contract.set_user_preferences(UserPreferences {
+            risk_tolerance: "medium".to_string(),
+            investment_horizon: "medium".to_string(),
+            preferred_chains: vec!["ethereum".to_string()],
+            excluded_assets: Vec::new(),
+            rebalance_threshold: "5.0".to_string(),
+            auto_rebalance: false,
+            max_slippage_bps: 100,
+            min_health_score: Some(100),
+        });
+        let order_id = contract.create_conditional_order(order("limit_buy", "ETH", "BTC", "0.05", "2700"));
+
+        set_price(&mut contract, "ETH", "2650");
+        assert_eq!(contract.get_conditional_order(order_id).unwrap().status, "failed");
+        assert!(last_outcome(&contract).starts_with("Plan lowers portfolio health"));
+    }
+
+    #[test]
+    fn orders_without_holdings_fail() {
+        let mut contract = orders_setup();
+        let order_id = contract.create_conditional_order(order("stop_loss", "NEAR", "USDC", "10", "2"));
+
+        set_price(&mut contract, "NEAR", "1.5");
+        assert_eq!(contract.get_conditional_order(order_id).unwrap().status, "failed");
+        assert_eq!(last_outcome(&contract), "Portfolio holds no NEAR on ethereum to sell");
+    }
+
+    #[test]
+    #[should_panic(expected = "At most 20 conditional orders can be active at once")]
+    fn active_orders_are_capped_per_user() {
+        let mut contract = orders_setup();
+        for _ in 0..=MAX_ACTIVE_ORDERS_PER_USER {
+            contract.create_conditional_order(order("stop_loss", "ETH", "USDC", "0.01", "2000"));
+        }
+    }
+
+    #[test]
+    fn cancelled_and_triggered_orders_free_their_slot() {
+        let mut contract = orders_setup();
+        let ids: Vec<u64> = (0..MAX_ACTIVE_ORDERS_PER_USER)
+            .map(|_| contract.create_conditional_order(order("stop_loss", "ETH", "USDC", "0.01", "2000")))
+            .collect();
+        contract.cancel_conditional_order(ids[0]);
+        contract.create_conditional_order(order("take_profit", "ETH", "USDC", "0.01", "3000"));
+
+        set_price(&mut contract, "ETH", "3100");
+        contract.create_conditional_order(order("take_profit", "ETH", "USDC", "0.01", "3200"));
+        assert_eq!(contract.get_active_orders_for_asset("ETH".to_string()).len(), MAX_ACTIVE_ORDERS_PER_USER as usize);
+    }
+}
@@ -0,0 +1,319 @@
+use near_sdk::{
+    serde::{Deserialize, Serialize},
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, ParsedIntent, PortfolioAsset, UserPreferences};
+
+// Factor weights out of 100
+const COVERAGE_WEIGHT: u8 = 35;
+const SUPPORT_WEIGHT: u8 = 25;
+const FEASIBILITY_WEIGHT: u8 = 25;
+const PREFERENCES_WEIGHT: u8 = 15;
+const PENALTY_PER_VIOLATION: u32 = 25;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConfidenceFactor {
+    pub name: String, // "parse_coverage", "supported_references", "balance_feasibility", "preference_compliance"
+    pub score: u8, // 0-100
+    pub weight: u8,
+    pub detail: String,
+}
+
+// Inputs the analysis already computed, scored without touching state
+pub(crate) struct ConfidenceInputs<'a> {
+    pub parsed: &'a ParsedIntent,
+    pub classification: &'a str,
+    pub portfolio: &'a [PortfolioAsset],
+    pub target_allocations: &'a [PortfolioAsset],
+    pub infeasible_actions: usize, // actions the resolver capped or skipped
+    pub preferences: Option<&'a UserPreferences>,
+}
+
+impl AIPortfolioRebalancer {
+    // Weighted average of the factors; the factors are returned so callers can show why
+    pub(crate) fn score_confidence(&self, inputs: &ConfidenceInputs) -> (u8, Vec<ConfidenceFactor>) {
+        let factors = vec![
+            parse_coverage(inputs.parsed),
+            self.supported_references(inputs),
+            balance_feasibility(inputs),
+            preference_compliance(inputs),
+        ];
+        let total_weight: u32 = factors.iter().map(|f| f.weight as u32).sum();
+        let weighted: u32 = factors.iter().map(|f| f.score as u32 * f.weight as u32).sum();
+        ((weighted / total_weight.max(1)) as u8, factors)
+    }
+
+    fn supported_references(&self, inputs: &ConfidenceInputs) -> ConfidenceFactor {
+        let mut assets: Vec<&str> = Vec::new();
+        let mut chains: Vec<&str> = Vec::new();
+        for action in &inputs.parsed.actions {
+            assets.push(&action.asset);
+            assets.extend(action.counter_asset.as_deref());
+            chains.extend(action.chain.as_deref());
+        }
+        for allocation in inputs.target_allocations {
+            assets.push(&allocation.token_symbol);
+            chains.push(&allocation.chain);
+        }
+        assets.sort_unstable();
+        assets.dedup();
+        chains.sort_unstable();
+        chains.dedup();
+
+        let unsupported: Vec<&str> = assets
+            .iter()
+            .filter(|asset| !self.supported_assets.contains(**asset))
+            .chain(chains.iter().filter(|chain| !self.supported_chains.contains(**chain)))
+            .copied()
+            .collect();
+        let references = assets.len() + chains.len();
+        let score = ((references - unsupported.len()) * 100).checked_div(references).unwrap_or(100);
+        ConfidenceFactor {
+            name: "supported_references".to_string(),
+            score: score as u8,
+            weight: SUPPORT_WEIGHT,
+            detail: if unsupported.is_empty() {
+                format!("All {} referenced assets and chains are supported", references)
+            } else {
+                format!("Unsupported: {}", unsupported.join(", "))
+            },
+        }
+    }
+}
+
+fn parse_coverage(parsed: &ParsedIntent) -> ConfidenceFactor {
+    let score = (parsed.recognized_tokens * 100).checked_div(parsed.total_tokens).unwrap_or(0);
+    ConfidenceFactor {
+        name: "parse_coverage".to_string(),
+        score: score as u8,
+        weight: COVERAGE_WEIGHT,
+        detail: if parsed.unrecognized.is_empty() {
+            format!("Understood all {} words", parsed.total_tokens)
+        } else {
+            format!(
+                "Understood {} of {} words; not understood: {}",
+                parsed.recognized_tokens,
+                parsed.total_tokens,
+                parsed.unrecognized.join(", ")
+            )
+        },
+    }
+}
+
+// Structured intents: share of actions the portfolio can fund in full. Strategy templates: how
+// much of the template's value the portfolio covers.
+fn balance_feasibility(inputs: &ConfidenceInputs) -> ConfidenceFactor {
+    let value = |assets: &[PortfolioAsset]| assets.iter().map(|a| a.value_usd.parse::<f64>().unwrap_or(0.0)).sum::<f64>();
+    let held = value(inputs.portfolio);
+
+    let (score, detail) = if held <= 0.0 {
+        (0, "No portfolio holdings to rebalance".to_string())
+    } else if !inputs.parsed.actions.is_empty() {
+        let actions = inputs.parsed.actions.len();
+        let funded = actions - inputs.infeasible_actions.min(actions);
+        (funded * 100 / actions, format!("{} of {} actions can be funded in full", funded, actions))
+    } else {
+        let target = value(inputs.target_allocations);
+        let ratio = if target > 0.0 { (held / target).min(1.0) } else { 1.0 };
+        (
+            (ratio * 100.0) as usize,
+            format!("Portfolio of ${:.2} covers {:.0}% of the ${:.2} target", held, ratio * 100.0, target),
+        )
+    };
+    ConfidenceFactor {
+        name: "balance_feasibility".to_string(),
+        score: score as u8,
+        weight: FEASIBILITY_WEIGHT,
+        detail,
+    }
+}
+
+fn preference_compliance(inputs: &ConfidenceInputs) -> ConfidenceFactor {
+    let mut violations: Vec<String> = Vec::new();
+    let mut violate = |violation: String| {
+        if !violations.contains(&violation) {
+            violations.push(violation);
+        }
+    };
+    if let Some(preferences) = inputs.preferences {
+        for allocation in inputs.target_allocations {
+            if preferences.excluded_assets.iter().any(|a| a.eq_ignore_ascii_case(&allocation.token_symbol)) {
+                violate(format!("{} is excluded", allocation.token_symbol));
+            }
+            if !preferences.preferred_chains.is_empty()
+                && !preferences.preferred_chains.iter().any(|c| c.eq_ignore_ascii_case(&allocation.chain))
+            {
+                violate(format!("{} is not a preferred chain", allocation.chain));
+            }
+        }
+        match (preferences.risk_tolerance.as_str(), inputs.classification) {
+            ("low", "aggressive") => violate("aggressive plan for low risk tolerance".to_string()),
+            ("high", "conservative") => violate("conservative plan for high risk tolerance".to_string()),
+            _ => {}
+        }
+    }
+
+    let score = 100u32.saturating_sub(PENALTY_PER_VIOLATION * violations.len() as u32);
+    ConfidenceFactor {
+        name: "preference_compliance".to_string(),
+        score: score as u8,
+        weight: PREFERENCES_WEIGHT,
+        detail: if violations.is_empty() {
+            "Plan respects the user's preferences".to_string()
+        } else {
+            violations.join("; ")
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::IntentAction;
+
+    fn parsed(actions: Vec<IntentAction>, unrecognized: &[&str], recognized_tokens: u32, total_tokens: u32) -> ParsedIntent {
+        ParsedIntent {
+            actions,
+            strategy: None,
+            negated_strategies: Vec::new(),
+            excluded_assets: Vec::new(),
+            unrecognized: unrecognized.iter().map(|word| word.to_string()).collect(),
+            recognized_tokens,
+            total_tokens,
+        }
+    }
+
+    fn action(asset: &str, counter_asset: Option<&str>, chain: Option<&str>) -> IntentAction {
+        IntentAction {
+            verb: "move".to_string(),
+            asset: asset.to_string(),
+            counter_asset: counter_asset.map(str::to_string),
+            chain: chain.map(str::to_string),
+            quantity: None,
+        }
+    }
+
+    fn asset(symbol: &str, chain: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: "0".to_string(),
+            chain: chain.to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    fn preferences(risk_tolerance: &str, excluded_assets: &[&str], preferred_chains: &[&str]) -> UserPreferences {
+        UserPreferences {
+            risk_tolerance: risk_tolerance.to_string(),
+            investment_horizon: "medium".to_string(),
+            preferred_chains: preferred_chains.iter().map(|chain| chain.to_string()).collect(),
+            excluded_assets: excluded_assets.iter().map(|asset| asset.to_string()).collect(),
+            rebalance_threshold: "5.0".to_string(),
+            auto_rebalance: false,
+            max_slippage_bps: 100,
+            min_health_score: None,
+        }
+    }
+
+    fn inputs<'a>(
+        parsed: &'a ParsedIntent,
+        portfolio: &'a [PortfolioAsset],
+        target_allocations: &'a [PortfolioAsset],
+        preferences: Option<&'a UserPreferences>,
+    ) -> ConfidenceInputs<'a> {
+        ConfidenceInputs {
+            parsed,
+            classification: "structured",
+            portfolio,
+            target_allocations,
+            infeasible_actions: 0,
+            preferences,
+        }
+    }
+
+    #[test]
+    fn parse_coverage_is_the_share_of_understood_words() {
+        let full = parse_coverage(&parsed(Vec::new(), &[], 4, 4));
+        assert_eq!((full.score, full.detail.as_str()), (100, "Understood all 4 words"));
+
+        let partial = parse_coverage(&parsed(Vec::new(), &["moon", "lambo"], 3, 5));
+        assert_eq!(partial.score, 60);
+        assert_eq!(partial.detail, "Understood 3 of 5 words; not understood: moon, lambo");
+
+        assert_eq!(parse_coverage(&parsed(Vec::new(), &[], 0, 0)).score, 0);
+    }
+
+    #[test]
+    fn support_counts_each_distinct_asset_and_chain_once() {
+        let contract = setup();
+        let intent = parsed(vec![action("ETH", Some("USDC"), Some("near")), action("ETH", Some("PEPE"), None)], &[], 6, 6);
+        let targets = [asset("USDC", "near", "500"), asset("ETH", "solana", "500")];
+        // ETH, PEPE, USDC and near, solana: PEPE and solana are unsupported
+        let factor = contract.supported_references(&inputs(&intent, &[], &targets, None));
+        assert_eq!(factor.score, 60);
+        assert_eq!(factor.detail, "Unsupported: PEPE, solana");
+
+        let nothing = contract.supported_references(&inputs(&parsed(Vec::new(), &[], 0, 0), &[], &[], None));
+        assert_eq!((nothing.score, nothing.detail.as_str()), (100, "All 0 referenced assets and chains are supported"));
+    }
+
+    #[test]
+    fn balance_feasibility_scores_funded_actions_or_template_coverage() {
+        let portfolio = [asset("ETH", "ethereum", "600")];
+        let intent = parsed(vec![action("ETH", Some("USDC"), None), action("BTC", Some("USDC"), None)], &[], 6, 6);
+        let mut structured = inputs(&intent, &portfolio, &[], None);
+        structured.infeasible_actions = 1;
+        let factor = balance_feasibility(&structured);
+        assert_eq!((factor.score, factor.detail.as_str()), (50, "1 of 2 actions can be funded in full"));
+
+        let template = parsed(Vec::new(), &[], 1, 1);
+        let targets = [asset("USDC", "ethereum", "800"), asset("ETH", "ethereum", "400")];
+        let factor = balance_feasibility(&inputs(&template, &portfolio, &targets, None));
+        assert_eq!(factor.score, 50);
+        assert_eq!(factor.detail, "Portfolio of $600.00 covers 50% of the $1200.00 target");
+
+        assert_eq!(balance_feasibility(&inputs(&template, &[], &targets, None)).score, 0);
+    }
+
+    #[test]
+    fn each_distinct_preference_violation_costs_a_quarter() {
+        let intent = parsed(Vec::new(), &[], 1, 1);
+        let targets = [asset("DOGE", "bsc", "100"), asset("DOGE", "bsc", "100"), asset("ETH", "ethereum", "100")];
+
+        let none = preference_compliance(&inputs(&intent, &[], &targets, None));
+        assert_eq!((none.score, none.detail.as_str()), (100, "Plan respects the user's preferences"));
+
+        let prefs = preferences("low", &["doge"], &["ethereum"]);
+        let mut aggressive = inputs(&intent, &[], &targets, Some(&prefs));
+        aggressive.classification = "aggressive";
+        let factor = preference_compliance(&aggressive);
+        assert_eq!(factor.score, 25);
+        assert_eq!(
+            factor.detail,
+            "DOGE is excluded; bsc is not a preferred chain; aggressive plan for low risk tolerance"
+        );
+    }
+
+    #[test]
+    fn confidence_is_the_weighted_average_of_the_factors() {
+        let contract = setup();
+        let intent = parsed(vec![action("ETH", Some("USDC"), None)], &["moon"], 3, 4);
+        let portfolio = [asset("ETH", "ethereum", "1000")];
+        let targets = [asset("USDC", "ethereum", "1000")];
+        let (score, factors) = contract.score_confidence(&inputs(&intent, &portfolio, &targets, None));
+
+        let scores: Vec<(&str, u8)> = factors.iter().map(|f| (f.name.as_str(), f.score)).collect();
+        assert_eq!(
+            scores,
+            [("parse_coverage", 75), ("supported_references", 100), ("balance_feasibility", 100), ("preference_compliance", 100)]
+        );
+        // (75 * 35 + 100 * 65) / 100
+        assert_eq!(score, 91);
+    }
+}
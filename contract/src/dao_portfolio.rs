@@ -0,0 +1,261 @@
+use near_sdk::{
+    env, log, near, require,
+    serde_json::Value,
+    AccountId, Gas, Promise, PromiseError,
+};
+
+use crate::{sputnik_dao, AIPortfolioRebalancer, AIPortfolioRebalancerExt, DaoProposalView, RebalanceIntent};
+
+const GET_PROPOSAL_GAS: Gas = Gas::from_tgas(10);
+const GET_POLICY_GAS: Gas = Gas::from_tgas(10);
+const DAO_PROPOSAL_CALLBACK_GAS: Gas = Gas::from_tgas(100);
+const DAO_POLICY_CALLBACK_GAS: Gas = Gas::from_tgas(20);
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Called by a Sputnik DAO (through a FunctionCall proposal) to own a portfolio. The
+    // caller's get_policy must answer with a role policy before it is registered; the
+    // attached deposit is credited to its storage balance either way.
+    #[payable]
+    pub fn register_dao_portfolio(&mut self) -> Promise {
+        let dao_id = env::predecessor_account_id();
+        let initial_storage = self.begin_storage_charge(&dao_id);
+        self.settle_storage_charge(&dao_id, initial_storage);
+
+        sputnik_dao::ext(dao_id.clone())
+            .with_static_gas(GET_POLICY_GAS)
+            .get_policy()
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(DAO_POLICY_CALLBACK_GAS)
+                    .on_dao_portfolio_policy(dao_id),
+            )
+    }
+
+    #[private]
+    pub fn on_dao_portfolio_policy(
+        &mut self,
+        dao_id: AccountId,
+        #[callback_result] policy: Result<Value, PromiseError>,
+    ) -> String {
+        let is_dao = matches!(&policy, Ok(policy) if policy.get("roles").is_some_and(Value::is_array));
+        if !is_dao {
+            log!("{} did not return a DAO policy; portfolio not registered", dao_id);
+            return format!("{} is not a Sputnik DAO", dao_id);
+        }
+
+        let initial_storage = self.begin_storage_charge(&dao_id);
+        if !self.users.contains(&dao_id) {
+            self.internal_register_user(dao_id.clone());
+        }
+        self.dao_portfolios.insert(dao_id.clone());
+        self.settle_storage_charge(&dao_id, initial_storage);
+
+        log!("DAO portfolio registered: {}", dao_id);
+        format!("DAO {} registered as portfolio owner", dao_id)
+    }
+
+    pub fn is_dao_portfolio(&self, account_id: String) -> bool {
+        let account_id: AccountId = account_id.parse().unwrap();
+        self.dao_portfolios.contains(&account_id)
+    }
+
+    pub fn get_dao_portfolios(&self) -> Vec<String> {
+        self.dao_portfolios.iter().map(|id| id.to_string()).collect()
+    }
+
+    // DAO proposals approving an intent must quote this in their description
+    pub fn dao_intent_hash(&self, intent_text: String) -> String {
+        intent_hash(&intent_text)
+    }
+
+    // Intents for a DAO portfolio are only accepted once the linked proposal has passed and
+    // its description carries the intent's hash (see dao_intent_hash). The caller pays for
+    // the intent's storage.
+    #[payable]
+    pub fn submit_dao_intent(&mut self, dao_id: String, intent_text: String, proposal_id: u64) -> Promise {
+        let caller_id = env::predecessor_account_id();
+        let dao_account_id: AccountId = dao_id.parse().unwrap();
+        require!(self.dao_portfolios.contains(&dao_account_id), "DAO portfolio not registered");
+        require!(
+            !self.used_dao_proposals.contains(&(dao_account_id.clone(), proposal_id)),
+            "DAO proposal already linked to an intent"
+        );
+        let initial_storage = self.begin_storage_charge(&caller_id);
+
+        let intent_id = self.internal_submit_intent(dao_account_id.clone(), intent_text, None);
+        let mut intent = self.intents.get(&intent_id).unwrap().clone();
+        intent.status = "pending_dao_approval".to_string();
+        intent.ai_analysis = format!("Waiting for DAO proposal {} approval", proposal_id);
+        intent.dao_proposal_id = Some(proposal_id);
+        self.intents.insert(intent_id, intent);
+        self.used_dao_proposals.insert((dao_account_id.clone(), proposal_id));
+        self.settle_storage_charge(&caller_id, initial_storage);
+
+        sputnik_dao::ext(dao_account_id)
+            .with_static_gas(GET_PROPOSAL_GAS)
+            .get_proposal(proposal_id)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(DAO_PROPOSAL_CALLBACK_GAS)
+                    .on_dao_intent_proposal(intent_id),
+            )
+    }
+
+    #[private]
+    pub fn on_dao_intent_proposal(
+        &mut self,
+        intent_id: u64,
+        #[callback_result] proposal: Result<DaoProposalView, PromiseError>,
+    ) -> bool {
+        let mut intent = self.intents.get(&intent_id).expect("Intent not found").clone();
+        let proposal_id = intent.dao_proposal_id.unwrap();
+        let approved = Self::dao_proposal_approved(&proposal, &intent.intent_text);
+
+        if approved {
+            intent.status = "analyzing".to_string();
+            intent.ai_analysis = "Processing intent with AI...".to_string();
+        } else {
+            let dao_account_id: AccountId = intent.user_id.parse().unwrap();
+            self.used_dao_proposals.remove(&(dao_account_id, proposal_id));
+            intent.status = "failed".to_string();
+            intent.ai_analysis = format!("DAO proposal {} is not an approved proposal for this intent", proposal_id);
+        }
+
+        log!("DAO intent {} proposal {} approved: {}", intent_id, proposal_id, approved);
+        self.intents.insert(intent_id, intent);
+        approved
+    }
+
+    #[private]
+    pub fn on_dao_execution_proposal(
+        &mut self,
+        intent_id: u64,
+        #[callback_result] proposal: Result<DaoProposalView, PromiseError>,
+    ) -> String {
+        let intent = self.intents.get(&intent_id).expect("Intent not found").clone();
+        if intent.status != "ready" {
+            return "Intent not ready for execution".to_string();
+        }
+
+        if !Self::dao_proposal_approved(&proposal, &intent.intent_text) {
+            return format!(
+                "DAO proposal {} is not approved; rebalance not executed",
+                intent.dao_proposal_id.unwrap_or(0)
+            );
+        }
+
+        let dao_account_id: AccountId = intent.user_id.parse().unwrap();
+        let initial_storage = self.begin_storage_charge(&dao_account_id);
+        let result = self.internal_execute_rebalance(intent);
+        self.settle_storage_charge(&dao_account_id, initial_storage);
+        result
+    }
+
+    // DAO portfolio helpers
+    pub(crate) fn verify_dao_proposal_for_execution(&self, intent: &RebalanceIntent) -> Promise {
+        let dao_account_id: AccountId = intent.user_id.parse().unwrap();
+        let proposal_id = intent.dao_proposal_id.expect("DAO intent has no linked proposal");
+
+        sputnik_dao::ext(dao_account_id)
+            .with_static_gas(GET_PROPOSAL_GAS)
+            .get_proposal(proposal_id)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(DAO_PROPOSAL_CALLBACK_GAS)
+                    .on_dao_execution_proposal(intent.id),
+            )
+    }
+
+    fn dao_proposal_approved(proposal: &Result<DaoProposalView, PromiseError>, intent_text: &str) -> bool {
+        let hash = intent_hash(intent_text);
+        matches!(proposal, Ok(p) if p.status == "Approved" && p.description.contains(&hash))
+    }
+}
+
+// sha256 hex of the intent text, so a proposal approves exactly one instruction
+fn intent_hash(intent_text: &str) -> String {
+    hex::encode(env::sha256(intent_text.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{serde_json::json, NearToken};
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const DAO: &str = "treasury.sputnik-dao.near";
+    const INTENT: &str = "move 10% of usdc into near";
+
+    fn setup_dao_portfolio() -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        call_with_deposit(DAO, NearToken::from_near(1));
+        contract.register_dao_portfolio();
+        call_as(CONTRACT);
+        contract.on_dao_portfolio_policy(account(DAO), Ok(json!({ "roles": [], "default_vote_policy": {} })));
+        contract
+    }
+
+    fn proposal(status: &str, description: &str) -> Result<DaoProposalView, PromiseError> {
+        Ok(DaoProposalView {
+            id: 3,
+            proposer: "member.near".to_string(),
+            description: description.to_string(),
+            kind: json!("Vote"),
+            status: status.to_string(),
+            vote_counts: HashMap::new(),
+        })
+    }
+
+    fn submit(contract: &mut AIPortfolioRebalancer) -> u64 {
+        register(contract, "member.near");
+        call_as("member.near");
+        contract.submit_dao_intent(DAO.to_string(), INTENT.to_string(), 3);
+        contract.get_user_intents(DAO.to_string()).last().copied().unwrap()
+    }
+
+    #[test]
+    fn only_accounts_with_a_dao_policy_register() {
+        let mut contract = setup();
+        call_with_deposit("alice.near", NearToken::from_near(1));
+        contract.register_dao_portfolio();
+        call_as(CONTRACT);
+        contract.on_dao_portfolio_policy(account("alice.near"), Err(PromiseError::Failed));
+        assert!(!contract.is_dao_portfolio("alice.near".to_string()));
+        // The deposit stays withdrawable as a storage balance
+        assert!(contract.storage_balance_of(account("alice.near")).is_some());
+
+        let contract = setup_dao_portfolio();
+        assert!(contract.is_dao_portfolio(DAO.to_string()));
+        assert!(contract.get_storage_bytes_used(DAO.to_string()) > 0);
+    }
+
+    #[test]
+    fn approved_proposal_must_quote_the_intent_hash() {
+        let mut contract = setup_dao_portfolio();
+        let intent_id = submit(&mut contract);
+        call_as(CONTRACT);
+        assert!(!contract.on_dao_intent_proposal(intent_id, proposal("Approved", "Rebalance the treasury")));
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "failed");
+
+        // The proposal id is freed, so it can be linked again once it quotes the hash
+        call_as("member.near");
+        contract.submit_dao_intent(DAO.to_string(), INTENT.to_string(), 3);
+        let intent_id = contract.get_user_intents(DAO.to_string()).last().copied().unwrap();
+        let description = format!("Rebalance per intent {}", contract.dao_intent_hash(INTENT.to_string()));
+        call_as(CONTRACT);
+        assert!(contract.on_dao_intent_proposal(intent_id, proposal("Approved", &description)));
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "analyzing");
+    }
+
+    #[test]
+    fn unapproved_proposals_do_not_unlock_intents() {
+        let mut contract = setup_dao_portfolio();
+        let intent_id = submit(&mut contract);
+        let description = contract.dao_intent_hash(INTENT.to_string());
+        call_as(CONTRACT);
+        assert!(!contract.on_dao_intent_proposal(intent_id, proposal("InProgress", &description)));
+    }
+}
@@ -0,0 +1,682 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    ext_contract, is_promise_success, log, near, require,
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    AccountId, Gas, Promise, PromiseError, PromiseOrValue,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+use std::collections::HashMap;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt};
+
+const GET_PROPOSAL_GAS: Gas = Gas::from_tgas(10);
+// A vote that reaches the threshold executes the proposal inside act_proposal
+const ACT_PROPOSAL_GAS: Gas = Gas::from_tgas(150);
+const DAO_VOTE_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+const DAO_PROPOSAL_CHECK_GAS: Gas = Gas::from_tgas(180);
+const DAO_ACTIONS: [&str; 3] = ["VoteApprove", "VoteReject", "VoteRemove"];
+// Upper bound on the bytes one vote record adds, on top of its rationale
+const DAO_VOTE_BYTES: u64 = 800;
+const PROPOSAL_INFO_BYTES: u64 = 200;
+
+// Sputnik DAO v2 interface. The agent contract votes as itself, so a DAO adds it to a
+// role that may vote and names one delegate whose policy decides every vote it casts.
+#[allow(dead_code)]
+#[ext_contract(sputnik_dao)]
+pub trait SputnikDao {
+    fn act_proposal(&mut self, id: u64, action: String, memo: Option<String>);
+    fn get_proposal(&self, id: u64) -> DaoProposalView;
+    fn get_policy(&self) -> Value;
+}
+
+// Subset of Sputnik's ProposalOutput needed to check approval and voting policy
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DaoProposalView {
+    pub id: u64,
+    pub proposer: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub kind: Value, // e.g. {"Transfer": {"token_id": "", "receiver_id": "...", "amount": "..."}}
+    pub status: String, // "InProgress", "Approved", "Rejected", "Removed", "Expired", "Moved", "Failed"
+    #[serde(default)]
+    pub vote_counts: HashMap<String, Vec<Value>>, // role -> [approve, reject, remove]
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DaoVotingPolicy {
+    pub auto_vote: bool,
+    pub auto_approve_transfers_below: String, // yoctoNEAR, "0" disables; larger transfers are rejected
+    pub reject_upgrades_unless_council_approved: bool,
+    pub min_confidence: u8,
+    pub notes: String, // free-text guidance passed to the agent
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DaoRegistration {
+    pub dao_id: String,
+    pub policy: DaoVotingPolicy,
+    pub registered_at: u64,
+}
+
+// The proposal as read from the DAO, checked against the policy before voting
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalProposalInfo {
+    pub kind: String, // Sputnik proposal kind, e.g. "Transfer", "UpgradeSelf", "FunctionCall"
+    pub token_id: String, // transfers only; "" is NEAR
+    pub amount: String, // transfers only, in the token's units; "0" otherwise
+    pub council_approved: bool,
+}
+
+// Agent's recommended vote on an external proposal, submitted by a worker
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DaoVoteRecommendation {
+    pub dao_id: String,
+    pub proposal_id: u64,
+    pub action: String, // "VoteApprove", "VoteReject", "VoteRemove"
+    pub rationale: String,
+    pub confidence: u8,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExternalDaoVote {
+    pub id: u64,
+    pub user_id: String, // the DAO's delegate whose policy governs the vote
+    pub dao_id: String,
+    pub proposal_id: u64,
+    pub action: String, // "VoteApprove", "VoteReject", "VoteRemove"
+    pub rationale: String,
+    pub confidence: u8,
+    pub worker_id: String,
+    pub status: String, // "checking", "submitted", "succeeded", "failed", "blocked_by_policy"
+    pub proposal_info: Option<ExternalProposalInfo>,
+    pub submitted_at: u64,
+    pub resolved_at: u64,
+}
+
+// Worker's AI analysis of an external DAO proposal, kept for member audit
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProposalAnalysis {
+    pub id: u64,
+    pub dao_id: String,
+    pub proposal_id: u64,
+    pub summary: String,
+    pub risk_score: u8, // 0-100, higher is riskier
+    pub recommended_vote: String, // "VoteApprove", "VoteReject", "VoteRemove"
+    pub confidence: u8,
+    pub model_hash: String,
+    pub worker_id: String,
+    pub timestamp: u64,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Called by the DAO itself (through a FunctionCall proposal) to name the account whose
+    // policy decides the agent's votes. One delegate per DAO, so the agent casts one vote.
+    #[payable]
+    pub fn set_dao_delegate(&mut self, delegate_id: String) -> String {
+        let dao_id = env::predecessor_account_id();
+        let delegate_id: AccountId = delegate_id.parse().expect("Invalid delegate account id");
+        let initial_storage = self.begin_storage_charge(&dao_id);
+        self.dao_delegates.insert(dao_id.clone(), delegate_id.clone());
+        self.settle_storage_charge(&dao_id, initial_storage);
+
+        log!("DAO {} delegated agent voting to {}", dao_id, delegate_id);
+        format!("Agent votes for {} now follow {}'s policy", dao_id, delegate_id)
+    }
+
+    pub fn get_dao_delegate(&self, dao_id: String) -> Option<String> {
+        let dao_id: AccountId = dao_id.parse().unwrap();
+        self.dao_delegates.get(&dao_id).map(|id| id.to_string())
+    }
+
+    // DAO registration and policy
+    #[payable]
+    pub fn register_dao(&mut self, dao_id: String, policy: DaoVotingPolicy) -> String {
+        let user_id = env::predecessor_account_id();
+        let dao_account_id: AccountId = dao_id.parse().expect("Invalid DAO account id");
+        require!(
+            self.dao_delegates.get(&dao_account_id) == Some(&user_id),
+            "Only the DAO's delegate can register a voting policy for it"
+        );
+        policy.auto_approve_transfers_below.parse::<u128>().expect("Invalid transfer limit");
+        let initial_storage = self.begin_storage_charge(&user_id);
+
+        if !self.users.contains(&user_id) {
+            self.internal_register_user(user_id.clone());
+        }
+
+        let mut daos = self.user_daos.get(&user_id).cloned().unwrap_or_default();
+        daos.retain(|d| d.dao_id != dao_id);
+        daos.push(DaoRegistration {
+            dao_id: dao_id.clone(),
+            policy,
+            registered_at: block_timestamp(),
+        });
+        self.user_daos.insert(user_id.clone(), daos);
+        self.settle_storage_charge(&user_id, initial_storage);
+
+        log!("DAO {} registered by {}", dao_id, user_id);
+        format!("DAO {} registered with voting policy", dao_id)
+    }
+
+    pub fn unregister_dao(&mut self, dao_id: String) -> String {
+        let user_id = env::predecessor_account_id();
+        let initial_storage = env::storage_usage();
+        let mut daos = self.user_daos.get(&user_id).cloned().unwrap_or_default();
+        let before = daos.len();
+        daos.retain(|d| d.dao_id != dao_id);
+        require!(daos.len() < before, "DAO not registered");
+
+        self.user_daos.insert(user_id.clone(), daos);
+        self.settle_storage_charge(&user_id, initial_storage);
+        format!("DAO {} unregistered", dao_id)
+    }
+
+    pub fn get_user_daos(&self, user_id: String) -> Vec<DaoRegistration> {
+        let account_id: AccountId = user_id.parse().unwrap();
+        self.user_daos.get(&account_id).cloned().unwrap_or_default()
+    }
+
+    // Agent voting. The proposal is read from the DAO and checked against the delegate's
+    // policy in the callback, so the worker can't misdescribe it. Needs about 200 Tgas.
+    pub fn submit_dao_vote(&mut self, recommendation: DaoVoteRecommendation) -> Promise {
+        let worker_id = self.require_trusted_worker();
+        let DaoVoteRecommendation { dao_id, proposal_id, action, rationale, confidence } = recommendation;
+        require!(DAO_ACTIONS.contains(&action.as_str()), "Invalid DAO vote action");
+        let dao_account_id: AccountId = dao_id.parse().expect("Invalid DAO account id");
+
+        let user_account_id = self.dao_delegates.get(&dao_account_id).cloned().expect("DAO has no delegate");
+        let policy = self.dao_policy(&user_account_id, &dao_id).expect("DAO not registered by its delegate");
+        require!(policy.auto_vote, "Auto-voting is disabled for this DAO");
+        require!(confidence >= policy.min_confidence, "Confidence below user policy minimum");
+
+        let vote_key = (dao_id.clone(), proposal_id);
+        require!(!self.dao_vote_index.contains_key(&vote_key), "Vote already submitted for this proposal");
+
+        // The vote record is stored for the delegate, at their expense
+        require!(
+            self.can_cover_storage(&user_account_id, DAO_VOTE_BYTES + rationale.len() as u64),
+            "Delegate's storage balance can't cover the vote record"
+        );
+        let initial_storage = self.begin_storage_charge(&user_account_id);
+
+        let vote_id = self.next_dao_vote_id;
+        self.next_dao_vote_id += 1;
+
+        let vote = ExternalDaoVote {
+            id: vote_id,
+            user_id: user_account_id.to_string(),
+            dao_id: dao_id.clone(),
+            proposal_id,
+            action: action.clone(),
+            rationale,
+            confidence,
+            worker_id: worker_id.to_string(),
+            status: "checking".to_string(),
+            proposal_info: None,
+            submitted_at: block_timestamp(),
+            resolved_at: 0,
+        };
+        self.dao_votes.insert(vote_id, vote);
+        self.dao_vote_index.insert(vote_key, vote_id);
+
+        let mut user_votes = self.user_dao_votes.get(&user_account_id).cloned().unwrap_or_default();
+        user_votes.push(vote_id);
+        self.user_dao_votes.insert(user_account_id.clone(), user_votes);
+        self.settle_storage_charge(&user_account_id, initial_storage);
+
+        log!("Checking {} proposal {} before voting {}", dao_id, proposal_id, action);
+        sputnik_dao::ext(dao_account_id)
+            .with_static_gas(GET_PROPOSAL_GAS)
+            .get_proposal(proposal_id)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(DAO_PROPOSAL_CHECK_GAS)
+                    .on_dao_vote_proposal(vote_id),
+            )
+    }
+
+    #[private]
+    pub fn on_dao_vote_proposal(
+        &mut self,
+        vote_id: u64,
+        #[callback_result] proposal: Result<DaoProposalView, PromiseError>,
+    ) -> PromiseOrValue<bool> {
+        let mut vote = self.dao_votes.get(&vote_id).expect("DAO vote not found").clone();
+        let user_account_id: AccountId = vote.user_id.parse().unwrap();
+        let initial_storage = self.begin_storage_charge(&user_account_id);
+        let blocked = match &proposal {
+            Err(_) => Some(("failed", "Could not read the proposal from the DAO".to_string())),
+            Ok(proposal) if proposal.status != "InProgress" => {
+                Some(("failed", format!("Proposal is {}, not open for voting", proposal.status)))
+            }
+            Ok(proposal) => {
+                let info = proposal_info(proposal);
+                let policy = self.dao_policy(&user_account_id, &vote.dao_id);
+                // Kept for auditing when the delegate's balance still covers it
+                if self.can_cover_storage(&user_account_id, PROPOSAL_INFO_BYTES) {
+                    vote.proposal_info = Some(info.clone());
+                }
+                match policy {
+                    None => Some(("failed", "DAO is no longer registered".to_string())),
+                    Some(policy) if !policy.auto_vote => Some(("blocked_by_policy", "Auto-voting is disabled".to_string())),
+                    Some(policy) => self
+                        .evaluate_dao_policy(&policy, &info)
+                        .filter(|required| *required != vote.action)
+                        .map(|required| ("blocked_by_policy", format!("Policy requires {} for this proposal", required))),
+                }
+            }
+        };
+
+        if let Some((status, reason)) = blocked {
+            vote.status = status.to_string();
+            vote.resolved_at = block_timestamp();
+            self.dao_vote_index.remove(&(vote.dao_id.clone(), vote.proposal_id));
+            log!("DAO vote {} {}: {}", vote_id, status, reason);
+            self.dao_votes.insert(vote_id, vote);
+            self.settle_storage_charge(&user_account_id, initial_storage);
+            return PromiseOrValue::Value(false);
+        }
+
+        vote.status = "submitted".to_string();
+        let (dao_id, proposal_id, action, rationale) =
+            (vote.dao_id.clone(), vote.proposal_id, vote.action.clone(), vote.rationale.clone());
+        self.dao_votes.insert(vote_id, vote);
+        self.settle_storage_charge(&user_account_id, initial_storage);
+
+        log!("Submitting {} on {} proposal {}", action, dao_id, proposal_id);
+        PromiseOrValue::Promise(
+            sputnik_dao::ext(dao_id.parse().unwrap())
+                .with_static_gas(ACT_PROPOSAL_GAS)
+                .act_proposal(proposal_id, action, Some(rationale))
+                .then(
+                    Self::ext(env::current_account_id())
+                        .with_static_gas(DAO_VOTE_CALLBACK_GAS)
+                        .on_dao_vote_result(vote_id),
+                ),
+        )
+    }
+
+    #[private]
+    pub fn on_dao_vote_result(&mut self, vote_id: u64) -> bool {
+        let mut vote = self.dao_votes.get(&vote_id).expect("DAO vote not found").clone();
+        let succeeded = is_promise_success();
+
+        vote.status = if succeeded { "succeeded" } else { "failed" }.to_string();
+        vote.resolved_at = block_timestamp();
+
+        if !succeeded {
+            // Allow the worker to retry a vote the DAO did not accept
+            self.dao_vote_index.remove(&(vote.dao_id.clone(), vote.proposal_id));
+        }
+
+        log!("DAO vote {} {}", vote_id, vote.status);
+        self.dao_votes.insert(vote_id, vote);
+        succeeded
+    }
+
+    pub fn get_dao_vote(&self, vote_id: u64) -> Option<ExternalDaoVote> {
+        self.dao_votes.get(&vote_id).cloned()
+    }
+
+    pub fn get_user_dao_votes(&self, user_id: String, from_index: u64, limit: u64) -> Vec<ExternalDaoVote> {
+        let account_id: AccountId = user_id.parse().unwrap();
+        self.user_dao_votes
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter_map(|id| self.dao_votes.get(id).cloned())
+            .collect()
+    }
+
+    // Proposal analysis records
+    pub fn submit_proposal_analysis(&mut self, analysis: ProposalAnalysis) -> u64 {
+        let worker_id = self.require_trusted_worker();
+        analysis.dao_id.parse::<AccountId>().expect("Invalid DAO account id");
+        require!(analysis.risk_score <= 100, "Risk score must be 0-100");
+        require!(analysis.confidence <= 100, "Confidence must be 0-100");
+        require!(
+            DAO_ACTIONS.contains(&analysis.recommended_vote.as_str()),
+            "Invalid recommended vote"
+        );
+
+        let analysis_id = self.next_proposal_analysis_id;
+        self.next_proposal_analysis_id += 1;
+
+        let mut analysis = analysis;
+        analysis.id = analysis_id;
+        analysis.worker_id = worker_id.to_string();
+        analysis.timestamp = block_timestamp();
+
+        let mut dao_analyses = self.dao_proposal_analyses.get(&analysis.dao_id).cloned().unwrap_or_default();
+        dao_analyses.push(analysis_id);
+        self.dao_proposal_analyses.insert(analysis.dao_id.clone(), dao_analyses);
+
+        log!(
+            "Analysis {} stored for {} proposal {}: risk {}",
+            analysis_id, analysis.dao_id, analysis.proposal_id, analysis.risk_score
+        );
+        self.proposal_analyses.insert(analysis_id, analysis);
+        analysis_id
+    }
+
+    pub fn get_proposal_analysis(&self, dao_id: String, proposal_id: u64) -> Option<ProposalAnalysis> {
+        self.dao_proposal_analyses
+            .get(&dao_id)?
+            .iter()
+            .rev()
+            .filter_map(|id| self.proposal_analyses.get(id))
+            .find(|a| a.proposal_id == proposal_id)
+            .cloned()
+    }
+
+    pub fn get_dao_proposal_analyses(&self, dao_id: String, from_index: u64, limit: u64) -> Vec<ProposalAnalysis> {
+        self.dao_proposal_analyses
+            .get(&dao_id)
+            .cloned()
+            .unwrap_or_default()
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter_map(|id| self.proposal_analyses.get(id).cloned())
+            .collect()
+    }
+
+    pub fn get_dao_proposal_analyses_count(&self, dao_id: String) -> u64 {
+        self.dao_proposal_analyses.get(&dao_id).map_or(0, |ids| ids.len() as u64)
+    }
+
+    // DAO voting helpers
+    fn dao_policy(&self, user_id: &AccountId, dao_id: &str) -> Option<DaoVotingPolicy> {
+        self.user_daos
+            .get(user_id)
+            .and_then(|daos| daos.iter().find(|d| d.dao_id == dao_id))
+            .map(|registration| registration.policy.clone())
+    }
+
+    // Returns the action the user's policy mandates, if any rule applies. The transfer limit
+    // is in yoctoNEAR, so token transfers always count as above it.
+    fn evaluate_dao_policy(&self, policy: &DaoVotingPolicy, info: &ExternalProposalInfo) -> Option<String> {
+        let transfer_limit: u128 = policy.auto_approve_transfers_below.parse().unwrap_or(0);
+        if info.kind == "Transfer" && transfer_limit > 0 {
+            let amount: u128 = if info.token_id.is_empty() { info.amount.parse().unwrap_or(u128::MAX) } else { u128::MAX };
+            return Some(if amount < transfer_limit { "VoteApprove" } else { "VoteReject" }.to_string());
+        }
+
+        let is_upgrade = info.kind == "UpgradeSelf" || info.kind == "UpgradeRemote";
+        if is_upgrade && policy.reject_upgrades_unless_council_approved && !info.council_approved {
+            return Some("VoteReject".to_string());
+        }
+
+        None
+    }
+}
+
+// Reads the fields the policy needs out of Sputnik's proposal output. The council counts as
+// approving once its approvals outnumber its rejections.
+fn proposal_info(proposal: &DaoProposalView) -> ExternalProposalInfo {
+    let (kind, body) = match &proposal.kind {
+        Value::String(kind) => (kind.clone(), Value::Null),
+        Value::Object(map) if map.len() == 1 => {
+            let (kind, body) = map.iter().next().unwrap();
+            (kind.clone(), body.clone())
+        }
+        _ => ("Unknown".to_string(), Value::Null),
+    };
+    let field = |name: &str| body.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+    let (token_id, amount) = if kind == "Transfer" { (field("token_id"), field("amount")) } else { (String::new(), "0".to_string()) };
+
+    let count = |value: Option<&Value>| -> u128 {
+        match value {
+            Some(Value::Number(n)) => n.as_u64().unwrap_or(0) as u128,
+            Some(Value::String(s)) => s.parse().unwrap_or(0),
+            _ => 0,
+        }
+    };
+    let council_approved = proposal
+        .vote_counts
+        .get("council")
+        .is_some_and(|counts| count(counts.first()) > count(counts.get(1)));
+
+    ExternalProposalInfo { kind, token_id, amount, council_approved }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{mock::MockAction, serde_json::json, test_utils::get_created_receipts, NearToken, PromiseResult};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    const DAO: &str = "dao.sputnik-dao.near";
+
+    fn policy(transfer_limit: &str) -> DaoVotingPolicy {
+        DaoVotingPolicy {
+            auto_vote: true,
+            auto_approve_transfers_below: transfer_limit.to_string(),
+            reject_upgrades_unless_council_approved: true,
+            min_confidence: 50,
+            notes: String::new(),
+        }
+    }
+
+    // worker.near votes for DAO, following alice.near's policy
+    fn setup_dao(transfer_limit: &str) -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        contract.register_worker("codehash".to_string(), String::new(), "checksum".to_string(), Some("worker.near".to_string()));
+        register(&mut contract, "alice.near");
+        call_with_deposit(DAO, NearToken::from_millinear(100));
+        contract.set_dao_delegate("alice.near".to_string());
+        call_as("alice.near");
+        contract.register_dao(DAO.to_string(), policy(transfer_limit));
+        contract
+    }
+
+    fn recommend(action: &str) -> DaoVoteRecommendation {
+        DaoVoteRecommendation {
+            dao_id: DAO.to_string(),
+            proposal_id: 7,
+            action: action.to_string(),
+            rationale: "matches treasury policy".to_string(),
+            confidence: 90,
+        }
+    }
+
+    fn proposal(kind: Value) -> DaoProposalView {
+        DaoProposalView {
+            id: 7,
+            proposer: "bob.near".to_string(),
+            description: String::new(),
+            kind,
+            status: "InProgress".to_string(),
+            vote_counts: HashMap::new(),
+        }
+    }
+
+    fn called_methods() -> Vec<String> {
+        get_created_receipts()
+            .into_iter()
+            .flat_map(|receipt| receipt.actions)
+            .filter_map(|action| match action {
+                MockAction::FunctionCallWeight { method_name, .. } => Some(String::from_utf8(method_name).unwrap()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn workers_cannot_register_themselves() {
+        let mut contract = setup();
+        call_as("alice.near");
+        contract.register_worker("codehash".to_string(), String::new(), "checksum".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the DAO's delegate can register a voting policy for it")]
+    fn only_the_dao_delegate_registers_a_policy() {
+        let mut contract = setup_dao("1000");
+        register(&mut contract, "bob.near");
+        call_as("bob.near");
+        contract.register_dao(DAO.to_string(), policy("0"));
+    }
+
+    #[test]
+    fn vote_reads_the_proposal_before_acting() {
+        let mut contract = setup_dao("1000");
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteApprove"));
+        assert_eq!(called_methods(), vec!["get_proposal", "on_dao_vote_proposal"]);
+        assert_eq!(contract.get_dao_vote(1).unwrap().status, "checking");
+        assert!(contract.get_storage_bytes_used("alice.near".to_string()) > 0);
+
+        call_as(CONTRACT);
+        contract.on_dao_vote_proposal(1, Ok(proposal(json!({ "Transfer": { "token_id": "", "amount": "999" } }))));
+        let vote = contract.get_dao_vote(1).unwrap();
+        assert_eq!(vote.status, "submitted");
+        assert_eq!(vote.proposal_info.unwrap().amount, "999");
+        assert!(called_methods().contains(&"act_proposal".to_string()));
+    }
+
+    #[test]
+    fn policy_is_checked_against_the_dao_not_the_worker() {
+        let mut contract = setup_dao("1000");
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteApprove"));
+
+        // Token transfers are never auto-approved by a yoctoNEAR limit
+        call_as(CONTRACT);
+        contract.on_dao_vote_proposal(1, Ok(proposal(json!({ "Transfer": { "token_id": "usdc.near", "amount": "1" } }))));
+        let vote = contract.get_dao_vote(1).unwrap();
+        assert_eq!(vote.status, "blocked_by_policy");
+        assert!(!called_methods().contains(&"act_proposal".to_string()));
+
+        // The index is freed so a corrected vote can be submitted
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteReject"));
+        assert_eq!(contract.get_dao_vote(2).unwrap().status, "checking");
+    }
+
+    #[test]
+    fn closed_or_unreadable_proposals_are_not_voted_on() {
+        let mut contract = setup_dao("1000");
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteReject"));
+        call_as(CONTRACT);
+        contract.on_dao_vote_proposal(1, Err(PromiseError::Failed));
+        assert_eq!(contract.get_dao_vote(1).unwrap().status, "failed");
+
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteReject"));
+        let mut closed = proposal(json!("Vote"));
+        closed.status = "Approved".to_string();
+        call_as(CONTRACT);
+        contract.on_dao_vote_proposal(2, Ok(closed));
+        assert_eq!(contract.get_dao_vote(2).unwrap().status, "failed");
+    }
+
+    #[test]
+    fn rejected_votes_can_be_retried() {
+        let mut contract = setup_dao("1000");
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteApprove"));
+        call_as(CONTRACT);
+        contract.on_dao_vote_proposal(1, Ok(proposal(json!("Vote"))));
+
+        callback_with(PromiseResult::Failed);
+        assert!(!contract.on_dao_vote_result(1));
+        assert_eq!(contract.get_dao_vote(1).unwrap().status, "failed");
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteApprove"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Vote already submitted for this proposal")]
+    fn one_vote_per_dao_proposal() {
+        let mut contract = setup_dao("1000");
+        call_as("worker.near");
+        contract.submit_dao_vote(recommend("VoteApprove"));
+        contract.submit_dao_vote(recommend("VoteApprove"));
+    }
+
+    #[test]
+    fn upgrades_need_council_approval() {
+        let mut upgrade = proposal(json!({ "UpgradeSelf": { "hash": "abc" } }));
+        upgrade.vote_counts.insert("council".to_string(), vec![json!(1), json!(2), json!(0)]);
+        let info = proposal_info(&upgrade);
+        assert_eq!(info.kind, "UpgradeSelf");
+        assert!(!info.council_approved);
+
+        upgrade.vote_counts.insert("council".to_string(), vec![json!("3"), json!("1"), json!("0")]);
+        assert!(proposal_info(&upgrade).council_approved);
+    }
+
+    fn analysis(proposal_id: u64, risk_score: u8, recommended_vote: &str) -> ProposalAnalysis {
+        ProposalAnalysis {
+            id: 0,
+            dao_id: DAO.to_string(),
+            proposal_id,
+            summary: format!("Proposal {} summary", proposal_id),
+            risk_score,
+            recommended_vote: recommended_vote.to_string(),
+            confidence: 80,
+            model_hash: "model".to_string(),
+            worker_id: String::new(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn analyses_are_stored_per_dao_and_paginated() {
+        let mut contract = setup_dao("0");
+        call_as("worker.near");
+        for proposal_id in 0..5 {
+            contract.submit_proposal_analysis(analysis(proposal_id, 10 * proposal_id as u8, "VoteApprove"));
+        }
+        // A newer analysis of the same proposal supersedes the older one
+        contract.submit_proposal_analysis(analysis(2, 90, "VoteReject"));
+
+        assert_eq!(contract.get_dao_proposal_analyses_count(DAO.to_string()), 6);
+        assert_eq!(contract.get_dao_proposal_analyses_count("other.near".to_string()), 0);
+        let page: Vec<u64> = contract
+            .get_dao_proposal_analyses(DAO.to_string(), 1, 3)
+            .iter()
+            .map(|a| a.proposal_id)
+            .collect();
+        assert_eq!(page, vec![1, 2, 3]);
+
+        let latest = contract.get_proposal_analysis(DAO.to_string(), 2).unwrap();
+        assert_eq!((latest.risk_score, latest.recommended_vote.as_str()), (90, "VoteReject"));
+        assert_eq!(latest.worker_id, "worker.near");
+        assert!(contract.get_proposal_analysis(DAO.to_string(), 9).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Worker not registered or not trusted")]
+    fn only_workers_submit_analyses() {
+        let mut contract = setup_dao("0");
+        call_as("alice.near");
+        contract.submit_proposal_analysis(analysis(0, 10, "VoteApprove"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Risk score must be 0-100")]
+    fn analysis_scores_are_bounded() {
+        let mut contract = setup_dao("0");
+        call_as("worker.near");
+        contract.submit_proposal_analysis(analysis(0, 101, "VoteApprove"));
+    }
+}
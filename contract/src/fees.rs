@@ -0,0 +1,327 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{mul_div, AIPortfolioRebalancer, AIPortfolioRebalancerExt, MICRO_USD, NEAR_TOKEN_ID, SHARE_DECIMALS};
+
+const NANOS_PER_YEAR: u64 = 365 * 24 * 60 * 60 * 1_000_000_000;
+pub const POOL_FEE_ACCOUNT_ID: &str = "pool";
+
+// Fee state for one portfolio (or the strategy pool). USD values are decimal strings.
+// For the pool the high-water mark is the share price rather than the total value.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeAccount {
+    pub account_id: String,
+    pub high_water_mark_usd: String,
+    pub last_accrued_at: u64,
+    pub accrued_management_usd: String,
+    pub accrued_performance_usd: String,
+    pub paid_usd: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeSummary {
+    pub account_id: String,
+    pub accrued_usd: String,
+    pub paid_usd: String,
+    pub outstanding_usd: String,
+    pub high_water_mark_usd: String,
+    pub last_accrued_at: u64,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Anyone may trigger accrual; it only moves fees forward to the current block time
+    pub fn accrue_user_fees(&mut self, user_id: String) -> FeeSummary {
+        let user_id: AccountId = user_id.parse().unwrap();
+        require!(self.users.contains(&user_id), "User not registered");
+        self.internal_accrue_user_fees(&user_id);
+        self.get_fee_summary(user_id.to_string())
+    }
+
+    pub fn accrue_pool_fees(&mut self) -> FeeSummary {
+        self.internal_accrue_pool_fees();
+        fee_summary(&self.pool_fee_account)
+    }
+
+    // Outstanding user fees are paid from the user's vault NEAR balance to the fee recipient's
+    pub fn settle_user_fees(&mut self, user_id: String) -> FeeSummary {
+        let user_id: AccountId = user_id.parse().unwrap();
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == user_id || caller == self.owner_id,
+            "Only owner or the user can settle fees"
+        );
+        self.internal_accrue_user_fees(&user_id);
+
+        let mut account = self.fee_accounts.get(&user_id).cloned().expect("No fees accrued for user");
+        let outstanding = fee_outstanding(&account);
+        let near_price: f64 = self
+            .asset_prices
+            .get("NEAR")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0.0);
+        require!(near_price > 0.0, "NEAR has no price; cannot settle fees");
+
+        let balance = self.vault_balance(&user_id, NEAR_TOKEN_ID);
+        let owed = (outstanding / near_price * 10f64.powi(24)) as u128;
+        let amount = std::cmp::min(owed, balance);
+        if amount > 0 {
+            let recipient: AccountId = self.fee_params.fee_recipient.parse().unwrap();
+            self.internal_vault_withdraw(&user_id, NEAR_TOKEN_ID, amount);
+            self.internal_vault_deposit(&recipient, NEAR_TOKEN_ID, amount);
+
+            let paid = amount as f64 / 10f64.powi(24) * near_price;
+            account.paid_usd = format!("{:.6}", parse_usd(&account.paid_usd) + paid);
+            self.fee_accounts.insert(user_id.clone(), account);
+            log!("Settled ${:.2} of fees for {} ({} yoctoNEAR to {})", paid, user_id, amount, recipient);
+        }
+
+        self.get_fee_summary(user_id.to_string())
+    }
+
+    // Fee views
+    pub fn get_fee_account(&self, user_id: String) -> Option<FeeAccount> {
+        let user_id: AccountId = user_id.parse().unwrap();
+        self.fee_accounts.get(&user_id).cloned()
+    }
+
+    pub fn get_fee_summary(&self, user_id: String) -> FeeSummary {
+        let user_id: AccountId = user_id.parse().unwrap();
+        self.fee_accounts
+            .get(&user_id)
+            .map(fee_summary)
+            .unwrap_or_else(|| fee_summary(&new_fee_account(user_id.as_str(), 0.0)))
+    }
+
+    pub fn get_pool_fee_account(&self) -> FeeAccount {
+        self.pool_fee_account.clone()
+    }
+
+    pub fn get_fee_accounts(&self, from_index: u64, limit: u64) -> Vec<FeeSummary> {
+        self.fee_accounts
+            .values()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(fee_summary)
+            .collect()
+    }
+
+    // Fee helpers
+    pub(crate) fn internal_accrue_user_fees(&mut self, user_id: &AccountId) {
+        let value = self.vault_value_usd(user_id);
+        let now = block_timestamp();
+        let mut account = match self.fee_accounts.get(user_id) {
+            Some(account) => account.clone(),
+            None => {
+                // The first accrual only sets the baseline
+                self.fee_accounts.insert(user_id.clone(), new_fee_account(user_id.as_str(), value));
+                return;
+            }
+        };
+
+        let (management, performance) = self.accrue(&mut account, value, now);
+        self.fee_accounts.insert(user_id.clone(), account);
+        if management > 0.0 || performance > 0.0 {
+            log!(
+                "Fees accrued for {}: management ${:.6}, performance ${:.6}",
+                user_id, management, performance
+            );
+        }
+    }
+
+    // Pool fees are taken by minting shares to the fee recipient, diluting depositors.
+    // Nothing accrues while a holding is unpriced; the elapsed time is charged once it is.
+    pub(crate) fn internal_accrue_pool_fees(&mut self) {
+        let now = block_timestamp();
+        let nav_micro_usd = match self.priced_pool_nav() {
+            Some(nav) => nav,
+            None => return,
+        };
+        let nav = nav_micro_usd as f64 / MICRO_USD as f64;
+        if self.share_total_supply == 0 || nav_micro_usd == 0 {
+            let mut account = self.pool_fee_account.clone();
+            account.last_accrued_at = now;
+            self.pool_fee_account = account;
+            return;
+        }
+
+        let share_price = nav / (self.share_total_supply as f64 / 10f64.powi(SHARE_DECIMALS as i32));
+        let mut account = self.pool_fee_account.clone();
+        if parse_usd(&account.high_water_mark_usd) <= 0.0 {
+            account.last_accrued_at = now;
+            account.high_water_mark_usd = format!("{:.6}", share_price);
+            self.pool_fee_account = account;
+            return;
+        }
+
+        // Management fees are charged on NAV; performance on the per-share gain times supply
+        let elapsed = now.saturating_sub(account.last_accrued_at);
+        let management = nav * self.fee_params.management_fee_bps as f64 / 10_000.0 * elapsed as f64 / NANOS_PER_YEAR as f64;
+        let high_water_mark = parse_usd(&account.high_water_mark_usd);
+        let mut performance = 0.0;
+        if share_price > high_water_mark {
+            let gain = (share_price - high_water_mark) * self.share_total_supply as f64 / 10f64.powi(SHARE_DECIMALS as i32);
+            performance = gain * self.fee_params.performance_fee_bps as f64 / 10_000.0;
+            account.high_water_mark_usd = format!("{:.6}", share_price);
+        }
+        account.last_accrued_at = now;
+        account.accrued_management_usd = format!("{:.6}", parse_usd(&account.accrued_management_usd) + management);
+        account.accrued_performance_usd = format!("{:.6}", parse_usd(&account.accrued_performance_usd) + performance);
+
+        // Mint whatever is outstanding if the recipient can hold shares
+        let outstanding = fee_outstanding(&account);
+        let outstanding_micro_usd = (outstanding * MICRO_USD as f64) as u128;
+        let recipient: AccountId = self.fee_params.fee_recipient.parse().unwrap();
+        if outstanding_micro_usd > 0 && outstanding_micro_usd < nav_micro_usd && self.share_balances.contains_key(&recipient) {
+            let shares = mul_div(self.share_total_supply, outstanding_micro_usd, nav_micro_usd - outstanding_micro_usd);
+            if shares > 0 {
+                self.internal_mint_shares(&recipient, shares, "pool_fees");
+                account.paid_usd = format!("{:.6}", parse_usd(&account.paid_usd) + outstanding);
+            }
+        }
+        self.pool_fee_account = account;
+    }
+
+    // Deposits and withdrawals move the high-water mark so flows are not charged as performance
+    pub(crate) fn adjust_fee_high_water_mark(&mut self, user_id: &AccountId, delta_usd: f64) {
+        if let Some(account) = self.fee_accounts.get(user_id).cloned() {
+            let mut account = account;
+            let high_water_mark = (parse_usd(&account.high_water_mark_usd) + delta_usd).max(0.0);
+            account.high_water_mark_usd = format!("{:.6}", high_water_mark);
+            self.fee_accounts.insert(user_id.clone(), account);
+        }
+    }
+
+    fn accrue(&self, account: &mut FeeAccount, value: f64, now: u64) -> (f64, f64) {
+        let elapsed = now.saturating_sub(account.last_accrued_at);
+        let management = value * self.fee_params.management_fee_bps as f64 / 10_000.0 * elapsed as f64 / NANOS_PER_YEAR as f64;
+
+        let high_water_mark = parse_usd(&account.high_water_mark_usd);
+        let mut performance = 0.0;
+        if value > high_water_mark {
+            performance = (value - high_water_mark) * self.fee_params.performance_fee_bps as f64 / 10_000.0;
+            account.high_water_mark_usd = format!("{:.6}", value);
+        }
+
+        account.last_accrued_at = now;
+        account.accrued_management_usd = format!("{:.6}", parse_usd(&account.accrued_management_usd) + management);
+        account.accrued_performance_usd = format!("{:.6}", parse_usd(&account.accrued_performance_usd) + performance);
+        (management, performance)
+    }
+
+    // Fees are only charged on what the vault actually holds; self-reported holdings on
+    // other chains can't be verified, so they never count as value or gains
+    fn vault_value_usd(&self, user_id: &AccountId) -> f64 {
+        self.vault_tokens
+            .values()
+            .map(|token| self.token_value_usd(token, self.vault_balance(user_id, &token.token_id)))
+            .sum()
+    }
+}
+
+pub(crate) fn new_fee_account(account_id: &str, high_water_mark: f64) -> FeeAccount {
+    FeeAccount {
+        account_id: account_id.to_string(),
+        high_water_mark_usd: format!("{:.6}", high_water_mark),
+        last_accrued_at: block_timestamp(),
+        accrued_management_usd: "0.0".to_string(),
+        accrued_performance_usd: "0.0".to_string(),
+        paid_usd: "0.0".to_string(),
+    }
+}
+
+fn fee_summary(account: &FeeAccount) -> FeeSummary {
+    let accrued = parse_usd(&account.accrued_management_usd) + parse_usd(&account.accrued_performance_usd);
+    FeeSummary {
+        account_id: account.account_id.clone(),
+        accrued_usd: format!("{:.6}", accrued),
+        paid_usd: account.paid_usd.clone(),
+        outstanding_usd: format!("{:.6}", fee_outstanding(account)),
+        high_water_mark_usd: account.high_water_mark_usd.clone(),
+        last_accrued_at: account.last_accrued_at,
+    }
+}
+
+fn fee_outstanding(account: &FeeAccount) -> f64 {
+    let accrued = parse_usd(&account.accrued_management_usd) + parse_usd(&account.accrued_performance_usd);
+    (accrued - parse_usd(&account.paid_usd)).max(0.0)
+}
+
+fn parse_usd(value: &str) -> f64 {
+    value.parse().unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::NearToken;
+
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{FeeParams, PortfolioAsset};
+
+    fn performance_fees(contract: &AIPortfolioRebalancer) -> f64 {
+        parse_usd(&contract.get_fee_account("alice.near".to_string()).unwrap().accrued_performance_usd)
+    }
+
+    #[test]
+    fn fees_ignore_self_reported_holdings() {
+        let mut contract = setup();
+        contract.set_fee_params(FeeParams {
+            management_fee_bps: 0,
+            performance_fee_bps: 1_000,
+            fee_recipient: OWNER.to_string(),
+        });
+        contract.update_asset_price("NEAR".to_string(), "5".to_string());
+        register(&mut contract, "alice.near");
+        call_with_deposit("alice.near", NearToken::from_near(2));
+        contract.vault_deposit_near();
+        contract.accrue_user_fees("alice.near".to_string());
+
+        call_as("alice.near");
+        contract.set_user_portfolio(vec![PortfolioAsset {
+            token_symbol: "ETH".to_string(),
+            token_address: String::new(),
+            balance: "1000".to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: "3000000".to_string(),
+            percentage: "100".to_string(),
+        }]);
+        contract.accrue_user_fees("alice.near".to_string());
+        assert_eq!(performance_fees(&contract), 0.0);
+
+        // A real gain on vault holdings is charged: 10% of $2
+        call_as(OWNER);
+        contract.update_asset_price("NEAR".to_string(), "6".to_string());
+        contract.accrue_user_fees("alice.near".to_string());
+        assert!((performance_fees(&contract) - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn deposits_are_not_charged_as_performance() {
+        let mut contract = setup();
+        contract.set_fee_params(FeeParams {
+            management_fee_bps: 0,
+            performance_fee_bps: 1_000,
+            fee_recipient: OWNER.to_string(),
+        });
+        contract.update_asset_price("NEAR".to_string(), "5".to_string());
+        register(&mut contract, "alice.near");
+        call_with_deposit("alice.near", NearToken::from_near(2));
+        contract.vault_deposit_near();
+        contract.accrue_user_fees("alice.near".to_string());
+
+        call_with_deposit("alice.near", NearToken::from_near(3));
+        contract.vault_deposit_near();
+        contract.accrue_user_fees("alice.near".to_string());
+        assert_eq!(performance_fees(&contract), 0.0);
+        assert_eq!(contract.get_fee_summary("alice.near".to_string()).high_water_mark_usd, "25.000000");
+    }
+}
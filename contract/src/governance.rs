@@ -0,0 +1,672 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    json_types::Base64VecU8,
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId, Gas, NearToken, Promise,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{
+    validate_health_scoring_config, validate_risk_limits, AIPortfolioRebalancer, AIPortfolioRebalancerExt, FeeParams,
+    HealthScoringConfig, MpcConfig, RiskLimits,
+};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+// Floors for governance parameters, so a config change can't switch off review of later ones
+const MIN_VOTING_PERIOD_SEC: u64 = 60 * 60;
+const MIN_TIMELOCK_DELAY_SEC: u64 = 60 * 60;
+const MIN_QUORUM_BPS: u16 = 500;
+const MIN_THRESHOLD_BPS: u16 = 5000;
+const MIGRATE_GAS: Gas = Gas::from_tgas(50);
+
+// Governance data structures
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalKind {
+    AddSupportedAsset { asset: String },
+    AddSupportedChain { chain: String },
+    ApproveCodehash { codehash: String },
+    RegisterWorker { account_id: String, codehash: String, checksum: String },
+    RevokeWorker { account_id: String },
+    SetMpcConfig { config: MpcConfig },
+    SetFeeParams { params: FeeParams },
+    SetHealthScoringConfig { config: HealthScoringConfig },
+    SetGovernanceConfig { config: GovernanceConfig },
+    SetGuardian { guardian_id: Option<String> },
+    SetProtocolRiskLimits { limits: RiskLimits },
+    UpgradeContract { code_hash: String }, // sha256 hex of code staged via store_upgrade_code
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Vote {
+    Approve,
+    Reject,
+    Abstain,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    pub id: u64,
+    pub proposer: String,
+    pub description: String,
+    pub kind: ProposalKind,
+    pub status: String, // "active", "approved", "rejected", "queued", "cancelled", "executed"
+    pub votes_for: String, // voting power in yoctoNEAR
+    pub votes_against: String,
+    pub votes_abstain: String,
+    pub snapshot_block: u64, // voting power is measured as of the block before this one
+    pub total_voting_power: String,
+    pub created_at: u64,
+    pub voting_ends_at: u64,
+    pub executed_at: u64,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct QueuedAction {
+    pub proposal_id: u64,
+    pub kind: ProposalKind,
+    pub queued_at: u64,
+    pub eta: u64, // earliest execution timestamp
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GovernanceConfig {
+    pub voting_period_sec: u64,
+    pub quorum_bps: u16, // share of total voting power that must vote
+    pub threshold_bps: u16, // share of approve votes among approve + reject required to pass
+    pub timelock_delay_sec: u64,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Proposal lifecycle
+    #[payable]
+    pub fn create_proposal(&mut self, description: String, kind: ProposalKind) -> u64 {
+        let proposer = env::predecessor_account_id();
+        require!(
+            self.users.contains(&proposer) || proposer == self.owner_id,
+            "Only registered users can create proposals"
+        );
+        self.validate_proposal_kind(&kind);
+        let initial_storage = self.begin_storage_charge(&proposer);
+
+        let proposal_id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+
+        let now = block_timestamp();
+        let snapshot_block = env::block_height();
+        let proposal = Proposal {
+            id: proposal_id,
+            proposer: proposer.to_string(),
+            description,
+            kind,
+            status: "active".to_string(),
+            votes_for: "0".to_string(),
+            votes_against: "0".to_string(),
+            votes_abstain: "0".to_string(),
+            snapshot_block,
+            total_voting_power: self.total_voting_power_before(snapshot_block).to_string(),
+            created_at: now,
+            voting_ends_at: now + self.governance_config.voting_period_sec * NANOS_PER_SEC,
+            executed_at: 0,
+        };
+
+        self.proposals.insert(proposal_id, proposal);
+        self.settle_storage_charge(&proposer, initial_storage);
+
+        log!("Proposal {} created by {}", proposal_id, proposer);
+        proposal_id
+    }
+
+    #[payable]
+    pub fn vote_on_proposal(&mut self, proposal_id: u64, vote: Vote) -> String {
+        let voter = env::predecessor_account_id();
+        let initial_storage = self.begin_storage_charge(&voter);
+        let mut proposal = self.proposals.get(&proposal_id).expect("Proposal not found").clone();
+        require!(proposal.status == "active", "Proposal is not active");
+        require!(block_timestamp() < proposal.voting_ends_at, "Voting period has ended");
+        require!(
+            !self.proposal_votes.contains_key(&(proposal_id, voter.clone())),
+            "Already voted on this proposal"
+        );
+
+        // Weight is taken from the snapshot so stake moved after creation cannot vote twice
+        let weight = self.voting_power_before(&voter, proposal.snapshot_block);
+        require!(weight > 0, "No voting power at proposal snapshot");
+
+        let tally = match vote {
+            Vote::Approve => &mut proposal.votes_for,
+            Vote::Reject => &mut proposal.votes_against,
+            Vote::Abstain => &mut proposal.votes_abstain,
+        };
+        *tally = (tally.parse::<u128>().unwrap_or(0) + weight).to_string();
+
+        self.proposals.insert(proposal_id, proposal);
+        self.proposal_votes.insert((proposal_id, voter.clone()), vote);
+        self.settle_storage_charge(&voter, initial_storage);
+
+        log!("{} voted on proposal {} with weight {}", voter, proposal_id, weight);
+        format!("Vote recorded on proposal {}", proposal_id)
+    }
+
+    pub fn finalize_proposal(&mut self, proposal_id: u64) -> String {
+        let mut proposal = self.proposals.get(&proposal_id).expect("Proposal not found").clone();
+        require!(proposal.status == "active", "Proposal is not active");
+        require!(block_timestamp() >= proposal.voting_ends_at, "Voting period has not ended");
+
+        proposal.status = if self.proposal_passed(&proposal) {
+            "approved".to_string()
+        } else {
+            "rejected".to_string()
+        };
+        let status = proposal.status.clone();
+        self.proposals.insert(proposal_id, proposal);
+
+        log!("Proposal {} finalized: {}", proposal_id, status);
+        format!("Proposal {} {}", proposal_id, status)
+    }
+
+    // Timelock queue
+    pub fn queue_proposal(&mut self, proposal_id: u64) -> String {
+        let status = self.proposals.get(&proposal_id).expect("Proposal not found").status.clone();
+        if status == "active" {
+            self.finalize_proposal(proposal_id);
+        }
+
+        let mut proposal = self.proposals.get(&proposal_id).unwrap().clone();
+        if proposal.status != "approved" {
+            return format!("Proposal {} is {} and cannot be queued", proposal_id, proposal.status);
+        }
+
+        let now = block_timestamp();
+        let eta = now + self.governance_config.timelock_delay_sec * NANOS_PER_SEC;
+        proposal.status = "queued".to_string();
+        self.proposals.insert(proposal_id, proposal.clone());
+        self.timelock_queue.insert(proposal_id, QueuedAction {
+            proposal_id,
+            kind: proposal.kind,
+            queued_at: now,
+            eta,
+        });
+
+        log!("Proposal {} queued until {}", proposal_id, eta);
+        format!("Proposal {} queued, executable after {}", proposal_id, eta)
+    }
+
+    pub fn cancel_queued_proposal(&mut self, proposal_id: u64) -> String {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.guardian_id.as_ref() == Some(&caller),
+            "Only guardian can cancel queued proposals"
+        );
+
+        let action = self.timelock_queue.get(&proposal_id).expect("Proposal is not queued").clone();
+        require!(block_timestamp() < action.eta, "Timelock delay has elapsed");
+
+        self.timelock_queue.remove(&proposal_id);
+        let mut proposal = self.proposals.get(&proposal_id).unwrap().clone();
+        proposal.status = "cancelled".to_string();
+        self.proposals.insert(proposal_id, proposal);
+
+        log!("Proposal {} cancelled by guardian {}", proposal_id, caller);
+        format!("Proposal {} cancelled", proposal_id)
+    }
+
+    pub fn execute_proposal(&mut self, proposal_id: u64) -> String {
+        let action = match self.timelock_queue.get(&proposal_id) {
+            Some(action) => action.clone(),
+            None => {
+                let status = &self.proposals.get(&proposal_id).expect("Proposal not found").status;
+                return format!("Proposal {} is {} and cannot be executed", proposal_id, status);
+            }
+        };
+        require!(block_timestamp() >= action.eta, "Timelock delay has not elapsed");
+
+        self.timelock_queue.remove(&proposal_id);
+        let mut proposal = self.proposals.get(&proposal_id).unwrap().clone();
+        proposal.status = "executed".to_string();
+        proposal.executed_at = block_timestamp();
+        self.proposals.insert(proposal_id, proposal);
+
+        let result = self.internal_execute_proposal_kind(&action.kind);
+
+        log!("Proposal {} executed: {}", proposal_id, result);
+        result
+    }
+
+    // Staged by the owner or anyone holding voting power; only a passed proposal deploys it
+    #[payable]
+    pub fn store_upgrade_code(&mut self, code: Base64VecU8) -> String {
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner_id || self.voting_power_before(&caller, env::block_height().saturating_add(1)) > 0,
+            "Only the owner or accounts with voting power can stage upgrade code"
+        );
+        let initial_storage = env::storage_usage();
+        let code: Vec<u8> = code.into();
+        let code_hash = hex::encode(env::sha256(&code));
+
+        self.upgrade_code.insert(code_hash.clone(), code);
+        self.upgrade_code.flush();
+
+        let storage_cost = env::storage_byte_cost()
+            .saturating_mul((env::storage_usage().saturating_sub(initial_storage)) as u128);
+        require!(
+            env::attached_deposit() >= storage_cost,
+            format!("Attach at least {} to cover code storage", storage_cost)
+        );
+
+        log!("Upgrade code staged: {}", code_hash);
+        code_hash
+    }
+
+    // Governance views
+    pub fn get_proposal(&self, proposal_id: u64) -> Option<Proposal> {
+        self.proposals.get(&proposal_id).cloned()
+    }
+
+    pub fn get_proposals(&self, from_index: u64, limit: u64) -> Vec<Proposal> {
+        self.proposals
+            .values()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_proposal_vote(&self, proposal_id: u64, account_id: String) -> Option<Vote> {
+        let account_id: AccountId = account_id.parse().unwrap();
+        self.proposal_votes.get(&(proposal_id, account_id)).cloned()
+    }
+
+    pub fn get_queued_actions(&self, from_index: u64, limit: u64) -> Vec<QueuedAction> {
+        self.timelock_queue
+            .values()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_guardian(&self) -> Option<String> {
+        self.guardian_id.as_ref().map(|id| id.to_string())
+    }
+
+    pub fn set_guardian(&mut self, guardian_id: Option<String>) {
+        self.require_bootstrap_owner();
+        self.guardian_id = guardian_id.map(|id| id.parse().unwrap());
+    }
+
+    pub fn get_governance_config(&self) -> GovernanceConfig {
+        self.governance_config.clone()
+    }
+
+    pub fn set_governance_config(&mut self, config: GovernanceConfig) {
+        self.require_bootstrap_owner();
+        validate_governance_config(&config);
+        self.governance_config = config;
+    }
+
+    // Bootstrap: the owner sets protocol parameters directly until this is called, after which
+    // they only change through proposals
+    pub fn end_bootstrap(&mut self) {
+        self.require_bootstrap_owner();
+        self.bootstrap_open = false;
+        log!("Bootstrap ended; protocol parameters are now governed by proposals");
+    }
+
+    pub fn is_bootstrap_open(&self) -> bool {
+        self.bootstrap_open
+    }
+
+    // Runs after an UpgradeContract deploy. It only reloads the stored state, so an upgrade must
+    // keep the state layout unchanged. Contracts on the pre-governance layout cannot be upgraded
+    // in place and need a fresh deploy with `new`.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let raw = env::storage_read(b"STATE").unwrap_or_else(|| env::panic_str("No contract state to migrate"));
+        Self::try_from_slice(&raw)
+            .unwrap_or_else(|_| env::panic_str("State layout changed; this upgrade needs a fresh deploy"))
+    }
+
+    // Governance helpers
+    fn validate_proposal_kind(&self, kind: &ProposalKind) {
+        match kind {
+            ProposalKind::RegisterWorker { account_id, .. } | ProposalKind::RevokeWorker { account_id } => {
+                account_id.parse::<AccountId>().expect("Invalid worker account id");
+            }
+            ProposalKind::SetMpcConfig { config } => {
+                config.contract_id.parse::<AccountId>().expect("Invalid MPC contract id");
+            }
+            ProposalKind::SetFeeParams { params } => {
+                require!(
+                    params.management_fee_bps <= 10_000 && params.performance_fee_bps <= 10_000,
+                    "Fee bps must be at most 10000"
+                );
+                params.fee_recipient.parse::<AccountId>().expect("Invalid fee recipient");
+            }
+            ProposalKind::SetHealthScoringConfig { config } => validate_health_scoring_config(config),
+            ProposalKind::SetGovernanceConfig { config } => validate_governance_config(config),
+            ProposalKind::SetGuardian { guardian_id: Some(guardian_id) } => {
+                guardian_id.parse::<AccountId>().expect("Invalid guardian account id");
+            }
+            ProposalKind::SetProtocolRiskLimits { limits } => validate_risk_limits(limits),
+            ProposalKind::UpgradeContract { code_hash } => {
+                require!(self.upgrade_code.contains_key(code_hash), "Upgrade code not staged");
+            }
+            _ => {}
+        }
+    }
+
+    fn proposal_passed(&self, proposal: &Proposal) -> bool {
+        let config = &self.governance_config;
+        let votes_for: u128 = proposal.votes_for.parse().unwrap_or(0);
+        let votes_against: u128 = proposal.votes_against.parse().unwrap_or(0);
+        let votes_abstain: u128 = proposal.votes_abstain.parse().unwrap_or(0);
+        let total_power: u128 = proposal.total_voting_power.parse().unwrap_or(0);
+
+        // Compare as fractions of 10_000 without multiplying yocto amounts up to overflow
+        let total_votes = votes_for + votes_against + votes_abstain;
+        let quorum_reached = total_power > 0
+            && total_votes >= total_power / 10_000 * config.quorum_bps as u128
+                + total_power % 10_000 * config.quorum_bps as u128 / 10_000;
+        let decisive_votes = votes_for + votes_against;
+        let threshold_reached = decisive_votes > 0
+            && votes_for > decisive_votes / 10_000 * config.threshold_bps as u128
+                + decisive_votes % 10_000 * config.threshold_bps as u128 / 10_000;
+
+        quorum_reached && threshold_reached
+    }
+
+    fn internal_execute_proposal_kind(&mut self, kind: &ProposalKind) -> String {
+        match kind.clone() {
+            ProposalKind::AddSupportedAsset { asset } => self.internal_add_supported_asset(asset),
+            ProposalKind::AddSupportedChain { chain } => self.internal_add_supported_chain(chain),
+            ProposalKind::ApproveCodehash { codehash } => {
+                self.approved_codehashes.insert(codehash.clone());
+                format!("Codehash {} approved", codehash)
+            }
+            ProposalKind::RegisterWorker { account_id, codehash, checksum } => {
+                self.internal_register_worker(account_id.parse().unwrap(), codehash, checksum);
+                format!("Worker {} registered", account_id)
+            }
+            ProposalKind::RevokeWorker { account_id } => {
+                self.internal_revoke_worker(account_id.parse().unwrap())
+            }
+            ProposalKind::SetMpcConfig { config } => {
+                let contract_id = config.contract_id.clone();
+                self.mpc_config = config;
+                format!("MPC config updated to {}", contract_id)
+            }
+            ProposalKind::SetFeeParams { params } => {
+                self.internal_set_fee_params(params);
+                "Fee parameters updated".to_string()
+            }
+            ProposalKind::SetHealthScoringConfig { config } => {
+                self.health_scoring_config = config;
+                "Health scoring config updated".to_string()
+            }
+            ProposalKind::SetGovernanceConfig { config } => {
+                self.governance_config = config;
+                "Governance config updated".to_string()
+            }
+            ProposalKind::SetGuardian { guardian_id } => {
+                self.guardian_id = guardian_id.map(|id| id.parse().unwrap());
+                "Guardian updated".to_string()
+            }
+            ProposalKind::SetProtocolRiskLimits { limits } => {
+                self.protocol_risk_limits = limits;
+                "Protocol risk limits updated".to_string()
+            }
+            ProposalKind::UpgradeContract { code_hash } => {
+                let code = self.upgrade_code.remove(&code_hash).expect("Upgrade code not staged");
+                Promise::new(env::current_account_id())
+                    .deploy_contract(code)
+                    .function_call("migrate".to_string(), Vec::new(), NearToken::from_yoctonear(0), MIGRATE_GAS);
+                format!("Contract upgrade to {} scheduled", code_hash)
+            }
+        }
+    }
+}
+
+pub(crate) fn validate_governance_config(config: &GovernanceConfig) {
+    require!(
+        config.quorum_bps <= 10_000 && config.threshold_bps <= 10_000,
+        "Quorum and threshold must be at most 10000 bps"
+    );
+    require!(config.quorum_bps >= MIN_QUORUM_BPS, format!("Quorum must be at least {} bps", MIN_QUORUM_BPS));
+    require!(
+        config.threshold_bps >= MIN_THRESHOLD_BPS,
+        format!("Threshold must be at least {} bps", MIN_THRESHOLD_BPS)
+    );
+    require!(
+        config.voting_period_sec >= MIN_VOTING_PERIOD_SEC,
+        format!("Voting period must be at least {} seconds", MIN_VOTING_PERIOD_SEC)
+    );
+    require!(
+        config.timelock_delay_sec >= MIN_TIMELOCK_DELAY_SEC,
+        format!("Timelock delay must be at least {} seconds", MIN_TIMELOCK_DELAY_SEC)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts, testing_env};
+
+    use super::*;
+    use crate::test_utils::*;
+
+    fn call_at_block(predecessor: &str, block_height: u64, timestamp: u64, deposit: NearToken) {
+        testing_env!(context(predecessor)
+            .block_height(block_height)
+            .block_timestamp(timestamp)
+            .attached_deposit(deposit)
+            .build());
+    }
+
+    // Alice stakes, proposes and approves alone; returns the proposal id and its timelock eta
+    fn queue_passed_proposal(contract: &mut AIPortfolioRebalancer, kind: ProposalKind) -> (u64, u64) {
+        register(contract, "alice.near");
+        call_at_block("alice.near", 1, 0, NearToken::from_near(10));
+        contract.deposit_voting_stake();
+        call_at_block("alice.near", 2, 0, NearToken::from_near(0));
+        let proposal_id = contract.create_proposal("test".to_string(), kind);
+        contract.vote_on_proposal(proposal_id, Vote::Approve);
+
+        let config = contract.get_governance_config();
+        let voting_ends_at = config.voting_period_sec * NANOS_PER_SEC;
+        call_at_block("bob.near", 3, voting_ends_at, NearToken::from_near(0));
+        contract.queue_proposal(proposal_id);
+        (proposal_id, voting_ends_at + config.timelock_delay_sec * NANOS_PER_SEC)
+    }
+
+    // As above, then moves past the timelock so anyone can execute
+    fn pass_proposal(contract: &mut AIPortfolioRebalancer, kind: ProposalKind) -> u64 {
+        let (proposal_id, eta) = queue_passed_proposal(contract, kind);
+        call_at_block("bob.near", 4, eta, NearToken::from_near(0));
+        proposal_id
+    }
+
+    fn add_chain() -> ProposalKind {
+        ProposalKind::AddSupportedChain { chain: "base".to_string() }
+    }
+
+    fn config(quorum_bps: u16, timelock_delay_sec: u64) -> GovernanceConfig {
+        GovernanceConfig {
+            voting_period_sec: 24 * 60 * 60,
+            quorum_bps,
+            threshold_bps: 5000,
+            timelock_delay_sec,
+        }
+    }
+
+    #[test]
+    fn owner_sets_parameters_during_bootstrap() {
+        let mut contract = setup();
+        contract.set_governance_config(config(1000, 60 * 60));
+        contract.set_guardian(Some("guardian.near".to_string()));
+        assert_eq!(contract.get_governance_config().quorum_bps, 1000);
+        assert_eq!(contract.get_guardian(), Some("guardian.near".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bootstrap has ended")]
+    fn owner_setters_close_after_bootstrap() {
+        let mut contract = setup();
+        contract.end_bootstrap();
+        assert!(!contract.is_bootstrap_open());
+        contract.set_fee_params(FeeParams {
+            management_fee_bps: 100,
+            performance_fee_bps: 0,
+            fee_recipient: OWNER.to_string(),
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Timelock delay must be at least")]
+    fn rejects_zero_timelock() {
+        let mut contract = setup();
+        contract.set_governance_config(config(1000, 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Quorum must be at least")]
+    fn rejects_zero_quorum() {
+        let mut contract = setup();
+        contract.set_governance_config(config(0, 60 * 60));
+    }
+
+    #[test]
+    #[should_panic(expected = "Quorum must be at least")]
+    fn rejects_governance_proposal_below_minimums() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        call_as("alice.near");
+        contract.create_proposal("no quorum".to_string(), ProposalKind::SetGovernanceConfig { config: config(0, 60 * 60) });
+    }
+
+    #[test]
+    fn proposals_set_parameters_after_bootstrap() {
+        let mut contract = setup();
+        contract.end_bootstrap();
+
+        let proposal_id = pass_proposal(&mut contract, ProposalKind::SetGovernanceConfig { config: config(1000, 2 * 60 * 60) });
+        contract.execute_proposal(proposal_id);
+        assert_eq!(contract.get_governance_config().quorum_bps, 1000);
+        assert_eq!(contract.get_governance_config().timelock_delay_sec, 2 * 60 * 60);
+        assert_eq!(contract.get_proposal(proposal_id).unwrap().status, "executed");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner or accounts with voting power can stage upgrade code")]
+    fn strangers_cannot_stage_upgrade_code() {
+        let mut contract = setup();
+        call_with_deposit("mallory.near", NearToken::from_near(1));
+        contract.store_upgrade_code(vec![0u8; 16].into());
+    }
+
+    #[test]
+    fn upgrade_deploys_and_migrates() {
+        let mut contract = setup();
+        call_with_deposit(OWNER, NearToken::from_near(1));
+        let code = vec![7u8; 16];
+        let code_hash = contract.store_upgrade_code(code.clone().into());
+
+        let proposal_id = pass_proposal(&mut contract, ProposalKind::UpgradeContract { code_hash });
+        contract.execute_proposal(proposal_id);
+
+        let receipt = get_created_receipts()
+            .into_iter()
+            .find(|receipt| receipt.receiver_id.as_str() == CONTRACT)
+            .expect("No upgrade receipt");
+        assert!(matches!(&receipt.actions[0], MockAction::DeployContract { code: deployed, .. } if *deployed == code));
+        assert!(matches!(
+            &receipt.actions[1],
+            MockAction::FunctionCallWeight { method_name, .. } if method_name.as_slice() == b"migrate"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "State layout changed; this upgrade needs a fresh deploy")]
+    fn migrate_rejects_an_incompatible_state_layout() {
+        setup();
+        call_as(CONTRACT);
+        env::storage_write(b"STATE", &[1, 2, 3]);
+        AIPortfolioRebalancer::migrate();
+    }
+
+    #[test]
+    fn passed_proposals_wait_in_the_timelock_queue() {
+        let mut contract = setup();
+        let (proposal_id, eta) = queue_passed_proposal(&mut contract, add_chain());
+
+        let queued = contract.get_queued_actions(0, 10);
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].proposal_id, proposal_id);
+        assert_eq!(queued[0].eta, eta);
+        assert_eq!(contract.get_proposal(proposal_id).unwrap().status, "queued");
+    }
+
+    #[test]
+    #[should_panic(expected = "Timelock delay has not elapsed")]
+    fn queued_proposals_cannot_execute_early() {
+        let mut contract = setup();
+        let (proposal_id, eta) = queue_passed_proposal(&mut contract, add_chain());
+        call_at_block("bob.near", 4, eta - 1, NearToken::from_near(0));
+        contract.execute_proposal(proposal_id);
+    }
+
+    #[test]
+    fn anyone_executes_after_the_delay() {
+        let mut contract = setup();
+        let proposal_id = pass_proposal(&mut contract, add_chain());
+        contract.execute_proposal(proposal_id);
+        assert!(contract.get_supported_chains().contains(&"base".to_string()));
+        assert!(contract.get_queued_actions(0, 10).is_empty());
+    }
+
+    #[test]
+    fn guardian_cancels_during_the_delay() {
+        let mut contract = setup();
+        contract.set_guardian(Some("guardian.near".to_string()));
+        let (proposal_id, eta) = queue_passed_proposal(&mut contract, add_chain());
+
+        call_at_block("guardian.near", 4, eta - 1, NearToken::from_near(0));
+        contract.cancel_queued_proposal(proposal_id);
+        assert_eq!(contract.get_proposal(proposal_id).unwrap().status, "cancelled");
+
+        call_at_block("bob.near", 5, eta, NearToken::from_near(0));
+        assert_eq!(
+            contract.execute_proposal(proposal_id),
+            format!("Proposal {} is cancelled and cannot be executed", proposal_id)
+        );
+        assert!(!contract.get_supported_chains().contains(&"base".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only guardian can cancel queued proposals")]
+    fn only_the_guardian_cancels() {
+        let mut contract = setup();
+        contract.set_guardian(Some("guardian.near".to_string()));
+        let (proposal_id, _) = queue_passed_proposal(&mut contract, add_chain());
+        call_as(OWNER);
+        contract.cancel_queued_proposal(proposal_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Timelock delay has elapsed")]
+    fn guardian_cannot_cancel_after_the_delay() {
+        let mut contract = setup();
+        contract.set_guardian(Some("guardian.near".to_string()));
+        let (proposal_id, eta) = queue_passed_proposal(&mut contract, add_chain());
+        call_at_block("guardian.near", 4, eta, NearToken::from_near(0));
+        contract.cancel_queued_proposal(proposal_id);
+    }
+}
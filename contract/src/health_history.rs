@@ -0,0 +1,178 @@
+use near_sdk::{
+    near,
+    serde::{Deserialize, Serialize},
+    borsh::{BorshDeserialize, BorshSerialize},
+    AccountId,
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset, PortfolioHealth};
+
+// Oldest snapshots are dropped once a user has this many
+pub const MAX_HEALTH_SNAPSHOTS: usize = 90;
+const NANOS_PER_DAY: u64 = 86_400_000_000_000;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HealthSnapshot {
+    pub score: u8,
+    pub grade: String,
+    pub diversification_score: u8,
+    pub risk_score: u8,
+    pub concentration_risk: u8,
+    pub total_value_usd: String,
+    pub timestamp: u64,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Newest first
+    pub fn get_health_history(&self, user_id: String, from_index: u64, limit: u64) -> Vec<HealthSnapshot> {
+        let account_id: AccountId = user_id.parse().unwrap();
+        self.health_history
+            .get(&account_id)
+            .map(|history| {
+                history
+                    .iter()
+                    .rev()
+                    .skip(from_index as usize)
+                    .take(limit as usize)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Health history helpers
+    // Score change against the newest snapshot that is at least 7 / 30 days older than `health`
+    pub(crate) fn apply_health_trend(&self, user_id: &AccountId, health: &mut PortfolioHealth) {
+        let history = match self.health_history.get(user_id) {
+            Some(history) => history,
+            None => return,
+        };
+        let change_since = |days: u64| {
+            let cutoff = health.last_updated.checked_sub(days * NANOS_PER_DAY)?;
+            history
+                .iter()
+                .rev()
+                .find(|snapshot| snapshot.timestamp <= cutoff)
+                .map(|snapshot| health.score as i16 - snapshot.score as i16)
+        };
+        health.score_change_7d = change_since(7);
+        health.score_change_30d = change_since(30);
+    }
+
+    pub(crate) fn record_health_snapshot(&mut self, user_id: &AccountId, health: &PortfolioHealth, portfolio: &[PortfolioAsset]) {
+        let total_value: f64 = portfolio.iter().map(|asset| asset.value_usd.parse::<f64>().unwrap_or(0.0)).sum();
+        let mut history = self.health_history.get(user_id).cloned().unwrap_or_default();
+        history.push(HealthSnapshot {
+            score: health.score,
+            grade: health.grade.clone(),
+            diversification_score: health.diversification_score,
+            risk_score: health.risk_score,
+            concentration_risk: health.concentration_risk,
+            total_value_usd: format!("{:.2}", total_value),
+            timestamp: health.last_updated,
+        });
+        if history.len() > MAX_HEALTH_SNAPSHOTS {
+            history.drain(..history.len() - MAX_HEALTH_SNAPSHOTS);
+        }
+        self.health_history.insert(user_id.clone(), history);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const USER: &str = "alice.near";
+
+    fn health(score: u8, day: u64) -> PortfolioHealth {
+        PortfolioHealth {
+            user_id: USER.to_string(),
+            grade: "B".to_string(),
+            score,
+            diversification_score: 0,
+            risk_score: 0,
+            concentration_risk: 0,
+            volatility_pct: None,
+            value_at_risk_usd: None,
+            score_change_7d: None,
+            score_change_30d: None,
+            recommendations: Vec::new(),
+            last_updated: day * NANOS_PER_DAY,
+        }
+    }
+
+    // Records one snapshot per (score, day), oldest first
+    fn history(snapshots: &[(u8, u64)]) -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        for (score, day) in snapshots {
+            contract.record_health_snapshot(&account(USER), &health(*score, *day), &[]);
+        }
+        contract
+    }
+
+    fn trend(contract: &AIPortfolioRebalancer, score: u8, day: u64) -> (Option<i16>, Option<i16>) {
+        let mut current = health(score, day);
+        contract.apply_health_trend(&account(USER), &mut current);
+        (current.score_change_7d, current.score_change_30d)
+    }
+
+    #[test]
+    fn only_the_newest_snapshots_are_kept() {
+        let snapshots: Vec<(u8, u64)> = (0..MAX_HEALTH_SNAPSHOTS as u64 + 5).map(|day| (day as u8, day)).collect();
+        let contract = history(&snapshots);
+
+        let all = contract.get_health_history(USER.to_string(), 0, 1_000);
+        assert_eq!(all.len(), MAX_HEALTH_SNAPSHOTS);
+        assert_eq!(all.first().unwrap().timestamp, (MAX_HEALTH_SNAPSHOTS as u64 + 4) * NANOS_PER_DAY);
+        assert_eq!(all.last().unwrap().timestamp, 5 * NANOS_PER_DAY);
+
+        let page: Vec<u8> = contract.get_health_history(USER.to_string(), 2, 3).iter().map(|s| s.score).collect();
+        assert_eq!(page, [92, 91, 90]);
+    }
+
+    #[test]
+    fn snapshots_record_the_portfolio_value() {
+        let mut contract = setup();
+        let portfolio = [PortfolioAsset {
+            token_symbol: "ETH".to_string(),
+            token_address: "eth".to_string(),
+            balance: "1".to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: "2800.5".to_string(),
+            percentage: "100".to_string(),
+        }];
+        contract.record_health_snapshot(&account(USER), &health(70, 1), &portfolio);
+        let snapshot = &contract.get_health_history(USER.to_string(), 0, 1)[0];
+        assert_eq!((snapshot.score, snapshot.total_value_usd.as_str()), (70, "2800.50"));
+    }
+
+    #[test]
+    fn trends_compare_against_the_newest_snapshot_old_enough() {
+        let contract = history(&[(50, 0), (60, 20), (65, 31), (70, 38)]);
+        // Day 40: 7d looks back to day 33 (score 65), 30d to day 10 (score 50)
+        assert_eq!(trend(&contract, 75, 40), (Some(10), Some(25)));
+        // Exactly 7 and 30 days count as old enough
+        assert_eq!(trend(&contract, 75, 45).0, Some(5));
+        assert_eq!(trend(&contract, 75, 50).1, Some(15));
+    }
+
+    #[test]
+    fn too_little_history_leaves_trends_unset() {
+        assert_eq!(trend(&setup(), 80, 40), (None, None));
+
+        // Only recent snapshots: nothing is 7 or 30 days old yet
+        let recent = history(&[(60, 36), (70, 38)]);
+        assert_eq!(trend(&recent, 80, 40), (None, None));
+
+        // A week of history, but not a month
+        let weekly = history(&[(60, 30), (70, 38)]);
+        assert_eq!(trend(&weekly, 80, 40), (Some(20), None));
+
+        // Near the epoch the look-back would underflow
+        assert_eq!(trend(&weekly, 80, 5), (None, None));
+    }
+}
@@ -0,0 +1,118 @@
+use near_sdk::AccountId;
+
+use crate::{AIPortfolioRebalancer, PortfolioAsset, PortfolioHealth};
+
+impl AIPortfolioRebalancer {
+    // Health of the user's current holdings and of the proposed target allocations
+    pub(crate) fn project_health(&self, user_id: &AccountId, target_allocations: &[PortfolioAsset]) -> (PortfolioHealth, PortfolioHealth) {
+        let portfolio = self.user_portfolios.get(user_id).cloned().unwrap_or_default();
+        (
+            self.calculate_portfolio_health(&portfolio, user_id),
+            self.calculate_portfolio_health(target_allocations, user_id),
+        )
+    }
+
+    // Plans may not push the score down below the user's floor; plans that raise a score that is
+    // already below the floor are allowed
+    pub(crate) fn check_health_floor(&self, user_id: &AccountId, before: &PortfolioHealth, after: &PortfolioHealth) -> Result<(), String> {
+        let floor = match self.user_preferences.get(user_id).and_then(|prefs| prefs.min_health_score) {
+            Some(floor) => floor,
+            None => return Ok(()),
+        };
+        if after.score < floor && after.score < before.score {
+            return Err(format!(
+                "Plan lowers portfolio health from {} ({}) to {} ({}), below the floor of {}",
+                before.score, before.grade, after.score, after.grade, floor
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::UserPreferences;
+
+    const USER: &str = "alice.near";
+
+    fn asset(symbol: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: value_usd.to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    fn health(score: u8) -> PortfolioHealth {
+        PortfolioHealth {
+            user_id: USER.to_string(),
+            grade: "C".to_string(),
+            score,
+            diversification_score: 0,
+            risk_score: 0,
+            concentration_risk: 0,
+            volatility_pct: None,
+            value_at_risk_usd: None,
+            score_change_7d: None,
+            score_change_30d: None,
+            recommendations: Vec::new(),
+            last_updated: 0,
+        }
+    }
+
+    fn with_floor(min_health_score: Option<u8>) -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(USER);
+        contract.set_user_preferences(UserPreferences {
+            risk_tolerance: "medium".to_string(),
+            investment_horizon: "medium".to_string(),
+            preferred_chains: Vec::new(),
+            excluded_assets: Vec::new(),
+            rebalance_threshold: "5.0".to_string(),
+            auto_rebalance: false,
+            max_slippage_bps: 100,
+            min_health_score,
+        });
+        contract
+    }
+
+    #[test]
+    fn projection_scores_current_holdings_and_the_targets() {
+        let mut contract = with_floor(None);
+        contract.set_user_portfolio(vec![asset("ETH", "1000")]);
+        let targets = [asset("ETH", "500"), asset("USDC", "500")];
+
+        let (before, after) = contract.project_health(&account(USER), &targets);
+        let portfolio = contract.user_portfolios.get(&account(USER)).unwrap().clone();
+        assert_eq!(before.score, contract.calculate_portfolio_health(&portfolio, &account(USER)).score);
+        assert_eq!(after.score, contract.calculate_portfolio_health(&targets, &account(USER)).score);
+        assert!(after.diversification_score > before.diversification_score);
+    }
+
+    #[test]
+    fn plans_may_not_lower_health_below_the_floor() {
+        let contract = with_floor(Some(60));
+        let user = account(USER);
+        assert!(contract.check_health_floor(&user, &health(80), &health(60)).is_ok());
+        assert_eq!(
+            contract.check_health_floor(&user, &health(80), &health(59)),
+            Err("Plan lowers portfolio health from 80 (C) to 59 (C), below the floor of 60".to_string())
+        );
+        // Already below the floor: improving or holding steady is allowed
+        assert!(contract.check_health_floor(&user, &health(40), &health(50)).is_ok());
+        assert!(contract.check_health_floor(&user, &health(40), &health(40)).is_ok());
+        assert!(contract.check_health_floor(&user, &health(40), &health(30)).is_err());
+    }
+
+    #[test]
+    fn users_without_a_floor_accept_any_plan() {
+        let contract = with_floor(None);
+        assert!(contract.check_health_floor(&account(USER), &health(100), &health(0)).is_ok());
+    }
+}
@@ -0,0 +1,228 @@
+use near_sdk::{
+    near, require,
+    serde::{Deserialize, Serialize},
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+use std::collections::HashMap;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset};
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GradeThresholds {
+    pub a: u8, // minimum overall score for each grade; below d is "F"
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HealthScoringConfig {
+    // Overall score weights, summing to 100; concentration counts as 100 - concentration_risk
+    pub diversification_weight: u8,
+    pub risk_weight: u8,
+    pub concentration_weight: u8,
+    // Diversification split between assets and chains, summing to 100
+    pub asset_diversity_weight: u8,
+    pub chain_diversity_weight: u8,
+    // Evenly spread holdings across this many assets / chains score 100
+    pub target_asset_count: u8,
+    pub target_chain_count: u8,
+    pub grade_thresholds: GradeThresholds,
+}
+
+impl Default for HealthScoringConfig {
+    fn default() -> Self {
+        Self {
+            diversification_weight: 34,
+            risk_weight: 33,
+            concentration_weight: 33,
+            asset_diversity_weight: 50,
+            chain_diversity_weight: 50,
+            target_asset_count: 5,
+            target_chain_count: 3,
+            grade_thresholds: GradeThresholds { a: 90, b: 80, c: 70, d: 60 },
+        }
+    }
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    pub fn set_health_scoring_config(&mut self, config: HealthScoringConfig) {
+        self.require_bootstrap_owner();
+        validate_health_scoring_config(&config);
+        self.health_scoring_config = config;
+    }
+
+    pub fn get_health_scoring_config(&self) -> HealthScoringConfig {
+        self.health_scoring_config.clone()
+    }
+
+    // Health scoring helpers
+    // Value-weighted: 1 - HHI of the value shares, scaled so an even spread over the target
+    // count scores 100. Dust positions barely move the index.
+    pub(crate) fn calculate_diversification_score(&self, portfolio: &[PortfolioAsset]) -> u8 {
+        let config = &self.health_scoring_config;
+        let asset_score = diversity_score(portfolio, |asset| asset.token_symbol.as_str(), config.target_asset_count);
+        let chain_score = diversity_score(portfolio, |asset| asset.chain.as_str(), config.target_chain_count);
+        ((asset_score * config.asset_diversity_weight as f64 + chain_score * config.chain_diversity_weight as f64) / 100.0) as u8
+    }
+
+    pub(crate) fn combine_health_scores(&self, diversification_score: u8, risk_score: u8, concentration_risk: u8) -> u8 {
+        let config = &self.health_scoring_config;
+        let weighted = diversification_score as u32 * config.diversification_weight as u32
+            + risk_score as u32 * config.risk_weight as u32
+            + (100 - concentration_risk.min(100)) as u32 * config.concentration_weight as u32;
+        (weighted / 100) as u8
+    }
+
+    pub(crate) fn health_grade(&self, score: u8) -> &'static str {
+        let thresholds = &self.health_scoring_config.grade_thresholds;
+        if score >= thresholds.a {
+            "A"
+        } else if score >= thresholds.b {
+            "B"
+        } else if score >= thresholds.c {
+            "C"
+        } else if score >= thresholds.d {
+            "D"
+        } else {
+            "F"
+        }
+    }
+}
+
+pub(crate) fn validate_health_scoring_config(config: &HealthScoringConfig) {
+    require!(
+        config.diversification_weight as u32 + config.risk_weight as u32 + config.concentration_weight as u32 == 100,
+        "Score weights must sum to 100"
+    );
+    require!(
+        config.asset_diversity_weight as u32 + config.chain_diversity_weight as u32 == 100,
+        "Diversity weights must sum to 100"
+    );
+    require!(
+        config.target_asset_count >= 2 && config.target_chain_count >= 2,
+        "Target counts must be at least 2"
+    );
+    let t = &config.grade_thresholds;
+    require!(
+        t.a <= 100 && t.a > t.b && t.b > t.c && t.c > t.d,
+        "Grade thresholds must be strictly decreasing and at most 100"
+    );
+}
+
+fn diversity_score(portfolio: &[PortfolioAsset], key: impl Fn(&PortfolioAsset) -> &str, target_count: u8) -> f64 {
+    let mut values: HashMap<&str, f64> = HashMap::new();
+    for asset in portfolio {
+        *values.entry(key(asset)).or_default() += asset.value_usd.parse::<f64>().unwrap_or(0.0).max(0.0);
+    }
+    let total: f64 = values.values().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let hhi: f64 = values.values().map(|value| (value / total).powi(2)).sum();
+    let best = 1.0 - 1.0 / target_count as f64;
+    ((1.0 - hhi) / best * 100.0).min(100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::*, AssetMetadata};
+
+    fn holding(symbol: &str, chain: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: "1".to_string(),
+            chain: chain.to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    fn by_symbol(portfolio: &[PortfolioAsset], target_count: u8) -> f64 {
+        let score = diversity_score(portfolio, |asset| asset.token_symbol.as_str(), target_count);
+        (score * 1e6).round() / 1e6
+    }
+
+    fn set_volatility(contract: &mut AIPortfolioRebalancer, symbol: &str, risk_tier: &str, volatility_pct: &str) {
+        contract.set_asset_metadata(AssetMetadata {
+            symbol: symbol.to_string(),
+            risk_tier: risk_tier.to_string(),
+            volatility_pct: Some(volatility_pct.to_string()),
+            depegged: false,
+        });
+    }
+
+    #[test]
+    fn hhi_scores_an_even_spread_over_the_target_count_as_full_diversity() {
+        let even: Vec<PortfolioAsset> = ["ETH", "BTC", "NEAR", "USDC", "LINK"].iter().map(|s| holding(s, "ethereum", "100")).collect();
+        assert_eq!(by_symbol(&even, 5), 100.0);
+        // 1 - HHI is 0.5 against a best case of 0.8
+        assert_eq!(by_symbol(&even[..2], 5), 62.5);
+        assert_eq!(by_symbol(&even[..1], 5), 0.0);
+        assert_eq!(by_symbol(&[], 5), 0.0);
+        // Holding more assets than the target does not score above 100
+        assert_eq!(by_symbol(&even, 3), 100.0);
+    }
+
+    #[test]
+    fn hhi_weights_positions_by_value() {
+        let mut dusty = vec![holding("ETH", "ethereum", "1000")];
+        dusty.extend(["BTC", "NEAR", "USDC", "LINK", "UNI", "AAVE", "DAI", "USDT", "COMP"].iter().map(|s| holding(s, "ethereum", "1")));
+        let score = by_symbol(&dusty, 5);
+        assert!(score > 2.0 && score < 2.5, "{}", score);
+
+        // The same symbol on two chains is one asset but two chains
+        let contract = setup();
+        let split = [holding("ETH", "ethereum", "50"), holding("ETH", "arbitrum", "50")];
+        // Assets: 0; chains: 0.5 / (2/3) = 75; weighted 50/50
+        assert_eq!(contract.calculate_diversification_score(&split), 37);
+    }
+
+    #[test]
+    fn volatility_caps_asset_safety_and_feeds_portfolio_volatility() {
+        let mut contract = setup();
+        let portfolio = [holding("ETH", "ethereum", "500"), holding("USDC", "ethereum", "500")];
+        assert_eq!(contract.calculate_risk_score(&portfolio), 80);
+        assert_eq!(contract.portfolio_volatility(&portfolio), None);
+
+        set_volatility(&mut contract, "ETH", "blue_chip", "65");
+        assert_eq!(contract.asset_safety("ETH"), 35.0);
+        assert_eq!(contract.calculate_risk_score(&portfolio), 67);
+        // 65% a year is 3.40% a day; half the portfolio carries it and USDC has no estimate
+        assert_eq!(contract.portfolio_risk_estimates(&portfolio).0.as_deref(), Some("1.70"));
+
+        // Two equally weighted, uncorrelated positions diversify to vol / sqrt(2)
+        set_volatility(&mut contract, "BTC", "blue_chip", "65");
+        let pair = [holding("ETH", "ethereum", "500"), holding("BTC", "ethereum", "500")];
+        assert_eq!(contract.portfolio_risk_estimates(&pair).0.as_deref(), Some("2.41"));
+    }
+
+    #[test]
+    fn grades_follow_the_configured_thresholds() {
+        let mut contract = setup();
+        let grades: Vec<&str> = [100, 90, 89, 80, 79, 70, 69, 60, 59, 0].iter().map(|score| contract.health_grade(*score)).collect();
+        assert_eq!(grades, ["A", "A", "B", "B", "C", "C", "D", "D", "F", "F"]);
+
+        let mut config = contract.get_health_scoring_config();
+        config.grade_thresholds = GradeThresholds { a: 80, b: 70, c: 60, d: 50 };
+        contract.set_health_scoring_config(config);
+        assert_eq!(contract.health_grade(68), "C");
+        assert_eq!(contract.health_grade(80), "A");
+        assert_eq!(contract.health_grade(49), "F");
+    }
+
+    #[test]
+    #[should_panic(expected = "Grade thresholds must be strictly decreasing and at most 100")]
+    fn grade_thresholds_must_decrease() {
+        let mut contract = setup();
+        let mut config = contract.get_health_scoring_config();
+        config.grade_thresholds = GradeThresholds { a: 80, b: 80, c: 60, d: 50 };
+        contract.set_health_scoring_config(config);
+    }
+}
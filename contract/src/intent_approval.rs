@@ -0,0 +1,191 @@
+use near_sdk::{env, log, near, require, AccountId};
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset, RebalanceIntent};
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Binds the user's approval to the exact plan they reviewed
+    pub fn approve_intent(&mut self, intent_id: u64, plan_hash: String) -> String {
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        self.require_intent_owner(&intent);
+        require!(intent.status == "awaiting_approval", "Intent is not awaiting approval");
+        self.require_not_expired(&intent);
+        require!(plan_hash == self.intent_plan_hash(&intent), "Plan hash does not match the current plan");
+
+        // The approved slippage bound no longer follows the user's preferences
+        intent.max_slippage_bps = Some(self.intent_max_slippage_bps(&intent));
+        intent.status = "ready".to_string();
+        intent.approved_plan_hash = Some(plan_hash);
+        self.intents.insert(intent_id, intent);
+
+        log!("Intent {} plan approved", intent_id);
+        format!("Intent {} approved for execution", intent_id)
+    }
+
+    pub fn reject_intent(&mut self, intent_id: u64, reason: Option<String>) -> String {
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        self.require_intent_owner(&intent);
+        require!(
+            intent.status == "awaiting_approval" || intent.status == "ready",
+            "Only analyzed intents that have not started executing can be rejected"
+        );
+
+        intent.status = "rejected".to_string();
+        intent.approved_plan_hash = None;
+        intent.rejection_reason = Some(format!("Rejected by user: {}", reason.unwrap_or_default()));
+        self.intents.insert(intent_id, intent);
+
+        log!("Intent {} rejected by its owner", intent_id);
+        format!("Intent {} rejected", intent_id)
+    }
+
+    // Replaces the proposed allocations; any earlier approval is dropped and the new plan needs approving
+    #[payable]
+    pub fn amend_intent_plan(&mut self, intent_id: u64, target_allocations: Vec<PortfolioAsset>) -> String {
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        let user_id = self.require_intent_owner(&intent);
+        require!(
+            ["awaiting_approval", "ready", "rejected"].contains(&intent.status.as_str()),
+            "Only analyzed intents that have not started executing can be amended"
+        );
+        let initial_storage = self.begin_storage_charge(&user_id);
+
+        intent.execution_steps = self.generate_execution_steps(&target_allocations);
+        intent.target_allocations = target_allocations;
+        intent.approved_plan_hash = None;
+        self.complete_intent_analysis(&mut intent);
+
+        let status = intent.status.clone();
+        self.intents.insert(intent_id, intent);
+        self.settle_storage_charge(&user_id, initial_storage);
+
+        log!("Intent {} plan amended: {}", intent_id, status);
+        status
+    }
+
+    pub fn get_intent_plan_hash(&self, intent_id: u64) -> String {
+        let intent = self.intents.get(&intent_id).expect("Intent not found");
+        self.intent_plan_hash(intent)
+    }
+
+    // Approval helpers
+    pub(crate) fn plan_matches_approval(&self, intent: &RebalanceIntent) -> bool {
+        intent.approved_plan_hash.as_deref() == Some(self.intent_plan_hash(intent).as_str())
+    }
+
+    // sha256 over the target allocations, execution steps, slippage bound and the trades planned
+    // from current holdings. Execution re-plans, so holdings that moved since approval change the
+    // hash and the rebalance is refused.
+    pub(crate) fn intent_plan_hash(&self, intent: &RebalanceIntent) -> String {
+        let max_slippage_bps = self.intent_max_slippage_bps(intent);
+        let trades = self.plan_intent_trades(intent);
+        let plan = near_sdk::borsh::to_vec(&(&intent.target_allocations, &intent.execution_steps, max_slippage_bps, &trades)).unwrap();
+        hex::encode(env::sha256(&plan))
+    }
+
+    fn require_intent_owner(&self, intent: &RebalanceIntent) -> AccountId {
+        let caller = env::predecessor_account_id();
+        require!(caller.as_str() == intent.user_id, "Only the intent creator can approve, reject or amend it");
+        caller
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::UserPreferences;
+
+    const USER: &str = "alice.near";
+
+    fn preferences(max_slippage_bps: u16) -> UserPreferences {
+        UserPreferences {
+            risk_tolerance: "medium".to_string(),
+            investment_horizon: "medium".to_string(),
+            preferred_chains: vec!["ethereum".to_string(), "near".to_string()],
+            excluded_assets: Vec::new(),
+            rebalance_threshold: "5.0".to_string(),
+            auto_rebalance: false,
+            max_slippage_bps,
+            min_health_score: None,
+        }
+    }
+
+    fn analyzed_intent() -> (AIPortfolioRebalancer, u64) {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(USER);
+        let intent_id = contract.submit_intent("rebalance my portfolio".to_string(), None);
+        contract.analyze_intent(intent_id);
+        (contract, intent_id)
+    }
+
+    #[test]
+    fn plan_hash_covers_the_slippage_bound() {
+        let (mut contract, intent_id) = analyzed_intent();
+        let default_hash = contract.get_intent_plan_hash(intent_id);
+        contract.set_intent_max_slippage(intent_id, 300);
+        assert_ne!(contract.get_intent_plan_hash(intent_id), default_hash);
+        contract.set_intent_max_slippage(intent_id, 100);
+        assert_eq!(contract.get_intent_plan_hash(intent_id), default_hash);
+    }
+
+    fn holding(symbol: &str, balance: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: balance.to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Intent plan differs from the approved plan")]
+    fn holdings_that_change_after_approval_block_execution() {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(USER);
+        contract.set_user_portfolio(vec![holding("ETH", "1", "3000"), holding("USDC", "1000", "1000")]);
+        let intent_id = contract.submit_intent("rebalance my portfolio".to_string(), None);
+        contract.analyze_intent(intent_id);
+        contract.amend_intent_plan(intent_id, vec![holding("ETH", "0", "2000"), holding("USDC", "0", "2000")]);
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash.clone());
+
+        contract.set_user_portfolio(vec![holding("ETH", "0.5", "1500"), holding("USDC", "1000", "1000")]);
+        assert_ne!(contract.get_intent_plan_hash(intent_id), plan_hash);
+        let _ = contract.execute_rebalance(intent_id);
+    }
+
+    #[test]
+    fn loosening_slippage_after_approval_needs_a_new_approval() {
+        let (mut contract, intent_id) = analyzed_intent();
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash);
+
+        contract.set_intent_max_slippage(intent_id, 5_000);
+        let intent = contract.get_intent(intent_id).unwrap();
+        assert_eq!(intent.status, "awaiting_approval");
+        assert_eq!(intent.approved_plan_hash, None);
+
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash);
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "ready");
+    }
+
+    #[test]
+    fn approval_freezes_the_preferred_slippage() {
+        let (mut contract, intent_id) = analyzed_intent();
+        contract.set_user_preferences(preferences(50));
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash);
+
+        // A later preference change neither loosens the approved bound nor invalidates the approval
+        contract.set_user_preferences(preferences(5_000));
+        let intent = contract.get_intent(intent_id).unwrap();
+        assert_eq!(intent.max_slippage_bps, Some(50));
+        assert!(contract.plan_matches_approval(&intent));
+    }
+}
@@ -0,0 +1,337 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    log, near, require,
+};
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, RebalanceIntent, TERMINAL_TRADE_STATUSES};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+pub const DEFAULT_INTENT_TTL_SEC: u64 = 7 * 24 * 60 * 60;
+
+// Statuses in which an intent has not started executing and can still be withdrawn
+const PRE_EXECUTION_STATUSES: [&str; 5] = ["pending_dao_approval", "analyzing", "awaiting_approval", "ready", "rejected"];
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Executing intents can be cancelled until a worker starts one of their trades
+    pub fn cancel_intent(&mut self, intent_id: u64) -> String {
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        require!(
+            env::predecessor_account_id().as_str() == intent.user_id,
+            "Only the intent creator can cancel it"
+        );
+        require!(
+            PRE_EXECUTION_STATUSES.contains(&intent.status.as_str()) || self.no_trade_started(&intent),
+            "Intent can no longer be cancelled"
+        );
+
+        self.end_intent(&mut intent, "cancelled");
+        self.intents.insert(intent_id, intent);
+
+        log!("Intent {} cancelled", intent_id);
+        format!("Intent {} cancelled", intent_id)
+    }
+
+    // Creates a revision with new text; the original is superseded and the revision starts a fresh analysis
+    #[payable]
+    pub fn amend_intent(&mut self, intent_id: u64, intent_text: String, valid_for_sec: Option<u64>) -> u64 {
+        let user_id = env::predecessor_account_id();
+        let mut original = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        require!(user_id.as_str() == original.user_id, "Only the intent creator can amend it");
+        require!(original.dao_proposal_id.is_none(), "DAO intents are bound to their proposal and cannot be amended");
+        require!(
+            PRE_EXECUTION_STATUSES.contains(&original.status.as_str()) && !is_expired(&original),
+            "Intent can no longer be amended"
+        );
+        let initial_storage = self.begin_storage_charge(&user_id);
+
+        let revision_id = self.internal_submit_intent(user_id.clone(), intent_text, valid_for_sec);
+        let mut revision = self.intents.get(&revision_id).cloned().unwrap();
+        revision.revision_of = Some(intent_id);
+        revision.max_slippage_bps = original.max_slippage_bps;
+        self.intents.insert(revision_id, revision);
+
+        original.status = "superseded".to_string();
+        original.superseded_by = Some(revision_id);
+        original.approved_plan_hash = None;
+        self.intents.insert(intent_id, original);
+        self.settle_storage_charge(&user_id, initial_storage);
+
+        log!("Intent {} amended as {}", intent_id, revision_id);
+        revision_id
+    }
+
+    // Marks expired intents in [from_index, from_index + limit). Executing intents expire only
+    // while none of their trades has started; ones with trades in flight run to completion.
+    pub fn cleanup_expired_intents(&mut self, from_index: u64, limit: u64) -> u64 {
+        let expired: Vec<u64> = self
+            .intents
+            .values()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter(|intent| {
+                is_expired(intent) && (PRE_EXECUTION_STATUSES.contains(&intent.status.as_str()) || self.no_trade_started(intent))
+            })
+            .map(|intent| intent.id)
+            .collect();
+
+        for intent_id in &expired {
+            let mut intent = self.intents.get(intent_id).cloned().unwrap();
+            self.end_intent(&mut intent, "expired");
+            self.intents.insert(*intent_id, intent);
+        }
+
+        log!("Cleaned up {} expired intents", expired.len());
+        expired.len() as u64
+    }
+
+    pub fn set_intent_ttl(&mut self, ttl_sec: u64) {
+        self.require_owner();
+        require!(ttl_sec > 0, "TTL must be positive");
+        self.intent_ttl_sec = ttl_sec;
+    }
+
+    pub fn get_intent_ttl(&self) -> u64 {
+        self.intent_ttl_sec
+    }
+
+    pub fn get_active_rebalances(&self) -> Vec<u64> {
+        self.active_rebalances.iter().copied().collect()
+    }
+
+    pub fn get_intent_trades(&self, intent_id: u64) -> Vec<u64> {
+        self.intent_trades.get(&intent_id).cloned().unwrap_or_default()
+    }
+
+    pub fn get_intent_revisions(&self, intent_id: u64) -> Vec<u64> {
+        // Walk back to the first revision, then forward through superseded_by
+        let mut first = intent_id;
+        while let Some(previous) = self.intents.get(&first).and_then(|intent| intent.revision_of) {
+            first = previous;
+        }
+        let mut revisions = vec![first];
+        while let Some(next) = self.intents.get(revisions.last().unwrap()).and_then(|intent| intent.superseded_by) {
+            revisions.push(next);
+        }
+        revisions
+    }
+
+    // Lifecycle helpers
+    pub(crate) fn intent_valid_until(&self, valid_for_sec: Option<u64>) -> u64 {
+        let valid_for_sec = valid_for_sec.unwrap_or(self.intent_ttl_sec);
+        require!(valid_for_sec > 0, "valid_for_sec must be positive");
+        valid_for_sec
+            .checked_mul(NANOS_PER_SEC)
+            .and_then(|valid_for| block_timestamp().checked_add(valid_for))
+            .unwrap_or_else(|| env::panic_str("valid_for_sec is too long"))
+    }
+
+    pub(crate) fn require_not_expired(&self, intent: &RebalanceIntent) {
+        require!(!is_expired(intent), "Intent has expired");
+    }
+
+    // Completes an executing intent once all of its trades have settled: "completed" unless a
+    // trade failed
+    pub(crate) fn settle_rebalance(&mut self, intent_id: u64) {
+        if !self.active_rebalances.contains(&intent_id) {
+            return;
+        }
+        let statuses: Vec<String> = self
+            .get_intent_trades(intent_id)
+            .iter()
+            .filter_map(|trade_id| self.trades.get(trade_id).map(|trade| trade.status.clone()))
+            .collect();
+        if !statuses.iter().all(|status| TERMINAL_TRADE_STATUSES.contains(&status.as_str())) {
+            return;
+        }
+
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        intent.status = if statuses.iter().any(|status| status == "failed") { "failed" } else { "completed" }.to_string();
+        self.intents.insert(intent_id, intent);
+        self.active_rebalances.remove(&intent_id);
+        log!("Rebalance for intent {} settled", intent_id);
+    }
+
+    fn no_trade_started(&self, intent: &RebalanceIntent) -> bool {
+        intent.status == "executing"
+            && self
+                .get_intent_trades(intent.id)
+                .iter()
+                .all(|trade_id| self.trades.get(trade_id).is_some_and(|trade| trade.status == "pending"))
+    }
+
+    // Cancels or expires an intent; an executing one's unstarted trades are failed so they never run
+    fn end_intent(&mut self, intent: &mut RebalanceIntent, status: &str) {
+        if self.active_rebalances.remove(&intent.id) {
+            for trade_id in self.get_intent_trades(intent.id) {
+                let mut trade = self.trades.get(&trade_id).cloned().unwrap();
+                trade.status = "failed".to_string();
+                self.trades.insert(trade_id, trade);
+            }
+        }
+        intent.status = status.to_string();
+        intent.approved_plan_hash = None;
+    }
+}
+
+pub(crate) fn is_expired(intent: &RebalanceIntent) -> bool {
+    block_timestamp() > intent.valid_until
+}
+
+// Views report expiry even before cleanup_expired_intents has stored it
+pub(crate) fn with_expiry(mut intent: RebalanceIntent) -> RebalanceIntent {
+    if PRE_EXECUTION_STATUSES.contains(&intent.status.as_str()) && is_expired(&intent) {
+        intent.status = "expired".to_string();
+    }
+    intent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::PortfolioAsset;
+    use near_sdk::testing_env;
+
+    const USER: &str = "alice.near";
+
+    fn user_intent(valid_for_sec: u64) -> (AIPortfolioRebalancer, u64) {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(USER);
+        let intent_id = contract.submit_intent("rebalance my portfolio".to_string(), Some(valid_for_sec));
+        (contract, intent_id)
+    }
+
+    fn after(seconds: u64, predecessor: &str) {
+        testing_env!(context(predecessor).block_timestamp(seconds * NANOS_PER_SEC).build());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the intent creator, the owner or a trusted worker can analyze an intent")]
+    fn strangers_cannot_analyze_an_intent() {
+        let (mut contract, intent_id) = user_intent(60);
+        call_as("mallory.near");
+        contract.analyze_intent(intent_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Intent is not awaiting analysis")]
+    fn analyzed_intents_cannot_be_reanalyzed() {
+        let (mut contract, intent_id) = user_intent(60);
+        contract.analyze_intent(intent_id);
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash);
+        // Re-analysis would swap the approved plan's allocations underneath the approval
+        call_as(OWNER);
+        contract.analyze_intent(intent_id);
+    }
+
+    #[test]
+    fn owner_and_workers_may_analyze_for_the_user() {
+        let (mut contract, intent_id) = user_intent(60);
+        call_as(OWNER);
+        contract.analyze_intent(intent_id);
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "awaiting_approval");
+    }
+
+    fn holding(symbol: &str, balance: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: balance.to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    // Executes a plan that sells $1000 of ETH into USDC and buys $500 of WBTC, as two trades
+    fn executing_intent(valid_for_sec: u64) -> (AIPortfolioRebalancer, u64, Vec<u64>) {
+        let (mut contract, intent_id) = user_intent(valid_for_sec);
+        contract.set_user_portfolio(vec![holding("ETH", "1", "3000"), holding("USDC", "1000", "1000")]);
+        contract.analyze_intent(intent_id);
+        contract.amend_intent_plan(
+            intent_id,
+            vec![holding("ETH", "0", "1500"), holding("USDC", "0", "2000"), holding("WBTC", "0", "500")],
+        );
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash);
+        let _ = contract.execute_rebalance(intent_id);
+        let trade_ids = contract.get_intent_trades(intent_id);
+        assert_eq!(trade_ids.len(), 2);
+        assert_eq!(contract.get_active_rebalances(), vec![intent_id]);
+        (contract, intent_id, trade_ids)
+    }
+
+    fn report(contract: &mut AIPortfolioRebalancer, trade_id: u64, status: &str) {
+        call_as(OWNER);
+        contract.update_trade_status(trade_id, status.to_string(), "0xabc".to_string(), "1".to_string());
+    }
+
+    #[test]
+    fn executing_intents_with_trades_in_flight_never_expire() {
+        let (mut contract, intent_id, trade_ids) = executing_intent(60);
+        report(&mut contract, trade_ids[0], "executing");
+        call_as(USER);
+        let stale_id = contract.submit_intent("buy $100 of NEAR".to_string(), Some(60));
+
+        after(120, USER);
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "executing");
+        assert_eq!(contract.get_intent(stale_id).unwrap().status, "expired");
+
+        assert_eq!(contract.cleanup_expired_intents(0, 10), 1);
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "executing");
+        assert_eq!(contract.get_active_rebalances(), vec![intent_id]);
+    }
+
+    #[test]
+    fn rebalance_completes_when_every_trade_settles() {
+        let (mut contract, intent_id, trade_ids) = executing_intent(60);
+        report(&mut contract, trade_ids[0], "confirmed");
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "executing");
+        assert_eq!(contract.get_active_rebalances(), vec![intent_id]);
+
+        report(&mut contract, trade_ids[1], "confirmed");
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "completed");
+        assert!(contract.get_active_rebalances().is_empty());
+    }
+
+    #[test]
+    fn rebalance_fails_when_a_trade_fails() {
+        let (mut contract, intent_id, trade_ids) = executing_intent(60);
+        report(&mut contract, trade_ids[0], "failed");
+        report(&mut contract, trade_ids[1], "confirmed");
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "failed");
+        assert!(contract.get_active_rebalances().is_empty());
+    }
+
+    #[test]
+    fn unstarted_rebalances_expire_and_their_trades_never_run() {
+        let (mut contract, intent_id, trade_ids) = executing_intent(60);
+        after(120, USER);
+        assert_eq!(contract.cleanup_expired_intents(0, 10), 1);
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "expired");
+        assert!(contract.get_active_rebalances().is_empty());
+        assert!(trade_ids.iter().all(|id| contract.get_trade(*id).unwrap().status == "failed"));
+    }
+
+    #[test]
+    fn unstarted_rebalances_can_be_cancelled() {
+        let (mut contract, intent_id, trade_ids) = executing_intent(60);
+        call_as(USER);
+        contract.cancel_intent(intent_id);
+        assert_eq!(contract.get_intent(intent_id).unwrap().status, "cancelled");
+        assert!(contract.get_active_rebalances().is_empty());
+        assert!(trade_ids.iter().all(|id| contract.get_trade(*id).unwrap().status == "failed"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Intent can no longer be cancelled")]
+    fn started_rebalances_cannot_be_cancelled() {
+        let (mut contract, intent_id, trade_ids) = executing_intent(60);
+        report(&mut contract, trade_ids[0], "executing");
+        call_as(USER);
+        contract.cancel_intent(intent_id);
+    }
+}
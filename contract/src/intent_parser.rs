@@ -0,0 +1,870 @@
+use near_sdk::{
+    near,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset};
+
+// Intent language, one clause per action; clauses are split on punctuation and "and"/"then":
+//   move|swap|sell [QTY] [of] [my] ASSET [to|into|for ASSET] [on CHAIN]
+//   reduce|increase|buy ASSET [by QTY | to PCT] [with|from ASSET] [on CHAIN]
+//   set|allocate|put PCT [to|in] ASSET  /  set ASSET to PCT
+//   keep|hold ASSET  /  don't touch ASSET  /  avoid ASSET
+// QTY is "20%", "$500", "2.5 ETH", "half" or "all". A negation word before the verb drops the
+// clause and protects the assets it names. Strategy words ("conservative", "aggressive", ...)
+// only drive the analysis when no clause yields an action.
+
+const MOVE_VERBS: [&str; 8] = ["move", "shift", "transfer", "swap", "convert", "exchange", "trade", "sell"];
+const REDUCE_VERBS: [&str; 6] = ["reduce", "decrease", "cut", "trim", "lower", "lessen"];
+const INCREASE_VERBS: [&str; 7] = ["increase", "buy", "add", "raise", "boost", "grow", "acquire"];
+const SET_VERBS: [&str; 5] = ["set", "allocate", "put", "make", "target"];
+const PROTECT_VERBS: [&str; 4] = ["keep", "hold", "preserve", "protect"];
+// Only meaningful after a negation: "don't touch BTC"
+const NEUTRAL_VERBS: [&str; 4] = ["touch", "change", "use", "sacrifice"];
+const NEGATIONS: [&str; 11] = ["not", "no", "never", "don't", "dont", "avoid", "without", "except", "exclude", "excluding", "nothing"];
+const CLAUSE_WORDS: [&str; 5] = ["and", "then", "also", "but", "while"];
+const FILLERS: [&str; 52] = [
+    "i", "i'm", "im", "i'd", "me", "my", "our", "we", "want", "would", "like", "please", "the", "a", "an", "of",
+    "some", "at", "all", "be", "more", "less", "go", "get", "portfolio", "holdings", "position", "positions",
+    "funds", "money", "let's", "lets", "rebalance", "rebalancing", "strategy", "it", "is", "am", "really", "very",
+    "much", "bit", "little", "do", "so", "towards", "asset", "assets", "token", "tokens", "coins", "crypto",
+];
+const PREPOSITIONS: [&str; 12] = ["to", "into", "for", "in", "by", "from", "with", "using", "on", "over", "across", "toward"];
+const USD_WORDS: [&str; 4] = ["usd", "dollars", "dollar", "bucks"];
+const ASSET_ALIASES: [(&str, &str); 7] = [
+    ("bitcoin", "BTC"),
+    ("ethereum", "ETH"),
+    ("ether", "ETH"),
+    ("tether", "USDT"),
+    ("stables", "USDC"),
+    ("stablecoins", "USDC"),
+    ("stablecoin", "USDC"),
+];
+
+// In fallback priority order, matching the original keyword classifier
+const STRATEGY_WORDS: [(&str, &[&str]); 4] = [
+    ("conservative", &["conservative", "safe", "safer", "safety", "low-risk"]),
+    ("aggressive", &["aggressive", "risky", "riskier", "high-risk"]),
+    ("defi_focused", &["defi", "yield", "farming"]),
+    ("cross_chain", &["cross-chain", "crosschain", "cross", "multichain", "multi-chain", "chain", "chains"]),
+];
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntentQuantity {
+    pub unit: String, // "percent_of_holding", "target_percent", "usd", "units"
+    pub amount: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntentAction {
+    pub verb: String, // "move", "reduce", "increase", "set"
+    pub asset: String,
+    pub counter_asset: Option<String>, // destination for move/reduce, funding source for increase/set
+    pub chain: Option<String>, // chain the bought side lands on
+    pub quantity: Option<IntentQuantity>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ParsedIntent {
+    pub actions: Vec<IntentAction>,
+    pub strategy: Option<String>,
+    pub negated_strategies: Vec<String>,
+    pub excluded_assets: Vec<String>,
+    pub unrecognized: Vec<String>,
+    pub recognized_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TargetDelta {
+    pub asset: String,
+    pub chain: Option<String>,
+    pub current_usd: String,
+    pub target_usd: String,
+    pub delta_usd: String, // signed
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntentParse {
+    pub parsed: ParsedIntent,
+    pub deltas: Vec<TargetDelta>,
+    pub warnings: Vec<String>,
+}
+
+// Supported assets and chains; any other word is reported as unrecognized
+pub(crate) struct ParserVocabulary {
+    pub assets: Vec<String>,
+    pub chains: Vec<String>,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Deltas are resolved against the user's portfolio when user_id is given
+    pub fn parse_intent(&self, intent_text: String, user_id: Option<String>) -> IntentParse {
+        let parsed = parse_intent_text(&intent_text, &self.parser_vocabulary());
+        let portfolio = user_id
+            .map(|user_id| {
+                let user_id: AccountId = user_id.parse().unwrap();
+                self.user_portfolios.get(&user_id).cloned().unwrap_or_default()
+            })
+            .unwrap_or_default();
+        let (deltas, warnings) = resolve_target_deltas(&parsed, &portfolio, &self.preferred_stable(), |symbol| self.price_of(symbol));
+        IntentParse { parsed, deltas, warnings }
+    }
+
+    // Parser helpers
+    pub(crate) fn price_of(&self, symbol: &str) -> Option<f64> {
+        self.asset_prices.get(symbol).and_then(|p| p.parse::<f64>().ok()).filter(|p| *p > 0.0)
+    }
+
+    pub(crate) fn parser_vocabulary(&self) -> ParserVocabulary {
+        ParserVocabulary {
+            assets: self.supported_assets.iter().cloned().collect(),
+            chains: self.supported_chains.iter().cloned().collect(),
+        }
+    }
+}
+
+pub(crate) fn parse_intent_text(intent_text: &str, vocab: &ParserVocabulary) -> ParsedIntent {
+    let mut parsed = ParsedIntent {
+        actions: Vec::new(),
+        strategy: None,
+        negated_strategies: Vec::new(),
+        excluded_assets: Vec::new(),
+        unrecognized: Vec::new(),
+        recognized_tokens: 0,
+        total_tokens: 0,
+    };
+    let mut strategies: Vec<&str> = Vec::new();
+
+    for clause in split_clauses(intent_text, &mut parsed) {
+        parse_clause(&clause, vocab, &mut parsed, &mut strategies);
+    }
+
+    parsed.strategy = STRATEGY_WORDS
+        .iter()
+        .map(|(strategy, _)| *strategy)
+        .find(|strategy| strategies.contains(strategy))
+        .map(str::to_string);
+    parsed
+}
+
+fn split_clauses(intent_text: &str, parsed: &mut ParsedIntent) -> Vec<Vec<String>> {
+    let chars: Vec<char> = intent_text.to_lowercase().replace('\u{2019}', "'").chars().collect();
+    let mut clauses = vec![Vec::new()];
+    let mut token = String::new();
+
+    let is_digit_at = |i: usize| chars.get(i).is_some_and(|c| c.is_ascii_digit());
+    for (i, c) in chars.iter().enumerate() {
+        let between_digits = i > 0 && is_digit_at(i - 1) && is_digit_at(i + 1);
+        match c {
+            ',' if between_digits => {} // thousands separator
+            '.' if between_digits => token.push('.'),
+            c if c.is_alphanumeric() || ['%', '$', '\'', '-', '_'].contains(c) => token.push(*c),
+            _ => {
+                push_token(&mut clauses, &mut token, parsed);
+                if ['.', ',', ';', '!', '?', '\n'].contains(c) {
+                    clauses.push(Vec::new());
+                }
+            }
+        }
+    }
+    push_token(&mut clauses, &mut token, parsed);
+    clauses.into_iter().filter(|clause| !clause.is_empty()).collect()
+}
+
+fn push_token(clauses: &mut Vec<Vec<String>>, token: &mut String, parsed: &mut ParsedIntent) {
+    let word = std::mem::take(token);
+    let word = word.trim_matches(|c| c == '\'' || c == '-' || c == '_');
+    if word.is_empty() {
+        return;
+    }
+    parsed.total_tokens += 1;
+    if CLAUSE_WORDS.contains(&word) {
+        parsed.recognized_tokens += 1;
+        clauses.push(Vec::new());
+    } else {
+        clauses.last_mut().unwrap().push(word.to_string());
+    }
+}
+
+fn parse_clause(clause: &[String], vocab: &ParserVocabulary, parsed: &mut ParsedIntent, strategies: &mut Vec<&'static str>) {
+    let negation_at = clause.iter().position(|word| NEGATIONS.contains(&word.as_str()));
+    let verb_at = clause.iter().position(|word| verb_class(word).is_some());
+    let negated = match (negation_at, verb_at) {
+        (Some(n), Some(v)) => n < v,
+        (Some(_), None) => true,
+        _ => false,
+    };
+
+    let mut recognized = vec![false; clause.len()];
+    for (i, word) in clause.iter().enumerate() {
+        if let Some(strategy) = strategy_of(word) {
+            recognized[i] = true;
+            if negation_at.is_some_and(|n| n < i) {
+                if !parsed.negated_strategies.iter().any(|s| s == strategy) {
+                    parsed.negated_strategies.push(strategy.to_string());
+                }
+            } else if !strategies.contains(&strategy) {
+                strategies.push(strategy);
+            }
+        } else if is_keyword(word) {
+            recognized[i] = true;
+        }
+    }
+
+    if negated {
+        // Everything the negated clause names is left alone
+        for (i, word) in clause.iter().enumerate().skip(negation_at.unwrap() + 1) {
+            if !recognized[i] && is_asset_like(word, vocab) {
+                recognized[i] = true;
+                push_unique(&mut parsed.excluded_assets, asset_symbol(word));
+            }
+        }
+    } else if let Some(v) = verb_at {
+        let class = verb_class(&clause[v]).unwrap();
+        if class == "protect" {
+            for (i, word) in clause.iter().enumerate().skip(v + 1) {
+                if !recognized[i] && is_asset_like(word, vocab) {
+                    recognized[i] = true;
+                    push_unique(&mut parsed.excluded_assets, asset_symbol(word));
+                }
+            }
+        } else {
+            // "buy ETH not BTC": the action ends where a later negation starts
+            let end = negation_at.filter(|n| *n > v).unwrap_or(clause.len());
+            if let Some(action) = parse_action(class, &clause[..end], v, vocab, &mut recognized) {
+                parsed.actions.push(action);
+            }
+            for (i, word) in clause.iter().enumerate().skip(end) {
+                if !recognized[i] && is_asset_like(word, vocab) {
+                    recognized[i] = true;
+                    push_unique(&mut parsed.excluded_assets, asset_symbol(word));
+                }
+            }
+        }
+    }
+
+    for (i, word) in clause.iter().enumerate() {
+        if recognized[i] {
+            parsed.recognized_tokens += 1;
+        } else {
+            parsed.unrecognized.push(word.clone());
+        }
+    }
+}
+
+fn parse_action(verb: &str, clause: &[String], verb_at: usize, vocab: &ParserVocabulary, recognized: &mut [bool]) -> Option<IntentAction> {
+    let mut asset: Option<String> = None;
+    let mut counter_asset: Option<String> = None;
+    let mut chain: Option<String> = None;
+    let mut quantity: Option<IntentQuantity> = None;
+
+    let mut i = verb_at + 1;
+    while i < clause.len() {
+        let word = clause[i].as_str();
+        match word {
+            "to" | "into" | "for" | "in" | "toward" => {
+                if let Some((q, used)) = parse_quantity(clause, i + 1, vocab) {
+                    if q.unit == "percent_of_holding" && verb != "move" {
+                        quantity = Some(IntentQuantity { unit: "target_percent".to_string(), amount: q.amount });
+                        mark(recognized, i, used + 1);
+                        i += used + 1;
+                        continue;
+                    }
+                }
+                if let Some(target) = next_asset(clause, i + 1, vocab, recognized) {
+                    mark(recognized, i, target - i + 1);
+                    if verb == "set" && asset.is_none() {
+                        asset = Some(asset_symbol(&clause[target]));
+                    } else {
+                        counter_asset = Some(asset_symbol(&clause[target]));
+                    }
+                    i = target + 1;
+                    continue;
+                }
+            }
+            "by" => {
+                if let Some((q, used)) = parse_quantity(clause, i + 1, vocab) {
+                    quantity = Some(q);
+                    mark(recognized, i, used + 1);
+                    i += used + 1;
+                    continue;
+                }
+            }
+            "with" | "from" | "using" => {
+                if let Some(source) = next_asset(clause, i + 1, vocab, recognized) {
+                    mark(recognized, i, source - i + 1);
+                    counter_asset = Some(asset_symbol(&clause[source]));
+                    i = source + 1;
+                    continue;
+                }
+            }
+            "on" | "over" | "across" => {
+                if let Some(name) = clause.get(i + 1).filter(|w| vocab.chains.contains(w)) {
+                    chain = Some(name.clone());
+                    mark(recognized, i, 2);
+                    i += 2;
+                    continue;
+                }
+            }
+            _ => {
+                if quantity.is_none() {
+                    if let Some((mut q, used)) = parse_quantity(clause, i, vocab) {
+                        if verb == "set" && q.unit == "percent_of_holding" {
+                            q.unit = "target_percent".to_string();
+                        }
+                        // "2.5 eth": the units name the asset
+                        if q.unit == "units" && asset.is_none() {
+                            asset = Some(asset_symbol(&clause[i + used - 1]));
+                        }
+                        quantity = Some(q);
+                        mark(recognized, i, used);
+                        i += used;
+                        continue;
+                    }
+                }
+                if asset.is_none() && !recognized[i] && is_asset_like(word, vocab) {
+                    asset = Some(asset_symbol(word));
+                    recognized[i] = true;
+                }
+            }
+        }
+        i += 1;
+    }
+
+    let asset = asset?;
+    recognized[verb_at] = true;
+    Some(IntentAction {
+        verb: verb.to_string(),
+        asset,
+        counter_asset,
+        chain,
+        quantity,
+    })
+}
+
+// Returns the quantity starting at `i` and how many tokens it used
+fn parse_quantity(clause: &[String], i: usize, vocab: &ParserVocabulary) -> Option<(IntentQuantity, usize)> {
+    let word = clause.get(i)?.as_str();
+    let next = clause.get(i + 1).map(String::as_str);
+    let quantity = |unit: &str, amount: f64| IntentQuantity { unit: unit.to_string(), amount: format!("{}", amount) };
+
+    match word {
+        "all" | "everything" | "entire" => return Some((quantity("percent_of_holding", 100.0), 1)),
+        "half" => return Some((quantity("percent_of_holding", 50.0), 1)),
+        "quarter" => return Some((quantity("percent_of_holding", 25.0), 1)),
+        _ => {}
+    }
+    if let Some(amount) = word.strip_suffix('%').and_then(parse_number) {
+        return Some((quantity("percent_of_holding", amount), 1));
+    }
+    if let Some(amount) = word.strip_prefix('$').or_else(|| word.strip_suffix('$')).and_then(parse_number) {
+        return Some((quantity("usd", amount), 1));
+    }
+    let amount = parse_number(word)?;
+    match next {
+        Some("%") | Some("percent") | Some("pct") => Some((quantity("percent_of_holding", amount), 2)),
+        Some(unit) if USD_WORDS.contains(&unit) => Some((quantity("usd", amount), 2)),
+        Some(symbol) if is_asset_like(symbol, vocab) => Some((quantity("units", amount), 2)),
+        _ => None,
+    }
+}
+
+fn parse_number(word: &str) -> Option<f64> {
+    word.parse::<f64>().ok().filter(|n| n.is_finite() && *n > 0.0)
+}
+
+// Skips fillers such as "my" and "the" to reach the asset after a preposition
+fn next_asset(clause: &[String], from: usize, vocab: &ParserVocabulary, recognized: &[bool]) -> Option<usize> {
+    let mut i = from;
+    while i < clause.len() && FILLERS.contains(&clause[i].as_str()) {
+        i += 1;
+    }
+    clause.get(i).filter(|word| !recognized[i] && is_asset_like(word, vocab)).map(|_| i)
+}
+
+fn mark(recognized: &mut [bool], from: usize, count: usize) {
+    for flag in recognized.iter_mut().skip(from).take(count) {
+        *flag = true;
+    }
+}
+
+fn verb_class(word: &str) -> Option<&'static str> {
+    if MOVE_VERBS.contains(&word) {
+        Some("move")
+    } else if REDUCE_VERBS.contains(&word) {
+        Some("reduce")
+    } else if INCREASE_VERBS.contains(&word) {
+        Some("increase")
+    } else if SET_VERBS.contains(&word) {
+        Some("set")
+    } else if PROTECT_VERBS.contains(&word) {
+        Some("protect")
+    } else {
+        None
+    }
+}
+
+fn strategy_of(word: &str) -> Option<&'static str> {
+    STRATEGY_WORDS
+        .iter()
+        .find(|(_, words)| words.contains(&word))
+        .map(|(strategy, _)| *strategy)
+}
+
+fn is_keyword(word: &str) -> bool {
+    verb_class(word).is_some()
+        || NEUTRAL_VERBS.contains(&word)
+        || NEGATIONS.contains(&word)
+        || FILLERS.contains(&word)
+        || PREPOSITIONS.contains(&word)
+        || USD_WORDS.contains(&word)
+        || strategy_of(word).is_some()
+}
+
+// Supported symbols and their aliases name assets; unknown words stay unrecognized
+fn is_asset_like(word: &str, vocab: &ParserVocabulary) -> bool {
+    !is_keyword(word) && vocab.assets.contains(&asset_symbol(word))
+}
+
+fn asset_symbol(word: &str) -> String {
+    ASSET_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == word)
+        .map_or_else(|| word.to_uppercase(), |(_, symbol)| symbol.to_string())
+}
+
+fn push_unique(list: &mut Vec<String>, value: String) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+// Turns parsed actions into USD deltas per asset. Every delta is balanced by the counter asset
+// or, without one, spread over the other holdings in proportion to their value, excluding
+// protected assets. Amounts are capped at what is held; proceeds with nowhere else to go are
+// parked in `stable_asset`.
+pub(crate) fn resolve_target_deltas(
+    parsed: &ParsedIntent,
+    portfolio: &[PortfolioAsset],
+    stable_asset: &str,
+    price: impl Fn(&str) -> Option<f64>,
+) -> (Vec<TargetDelta>, Vec<String>) {
+    let mut holdings: Vec<(String, f64)> = Vec::new();
+    for asset in portfolio {
+        let value: f64 = asset.value_usd.parse().unwrap_or(0.0);
+        match holdings.iter_mut().find(|(symbol, _)| *symbol == asset.token_symbol) {
+            Some((_, total)) => *total += value,
+            None => holdings.push((asset.token_symbol.clone(), value)),
+        }
+    }
+    let initial = holdings.clone();
+    let total: f64 = holdings.iter().map(|(_, value)| value).sum();
+    let mut chains: Vec<(String, String)> = Vec::new();
+    let mut warnings = Vec::new();
+
+    for action in &parsed.actions {
+        let held = holding(&holdings, &action.asset);
+        let requested = match &action.quantity {
+            None if action.verb == "move" => Some(held),
+            None => {
+                warnings.push(format!("{} {} needs an amount; skipped", action.verb, action.asset));
+                continue;
+            }
+            Some(q) => {
+                let amount: f64 = q.amount.parse().unwrap_or(0.0);
+                match q.unit.as_str() {
+                    "percent_of_holding" => Some(held * amount / 100.0),
+                    "usd" => Some(amount),
+                    "units" => price(&action.asset).map(|p| amount * p),
+                    _ => Some((total * amount / 100.0 - held).abs()), // target_percent
+                }
+            }
+        };
+        let Some(amount_usd) = requested else {
+            warnings.push(format!("No price for {}; {} skipped", action.asset, action.verb));
+            continue;
+        };
+
+        // Which side of the action gains value
+        let target_percent = action
+            .quantity
+            .as_ref()
+            .filter(|q| q.unit == "target_percent")
+            .map(|q| q.amount.parse::<f64>().unwrap_or(0.0));
+        let buys_asset = match target_percent {
+            Some(pct) => total * pct / 100.0 > held,
+            None => action.verb == "increase" || action.verb == "set",
+        };
+        if let Some(chain) = &action.chain {
+            let gaining = if buys_asset { Some(&action.asset) } else { action.counter_asset.as_ref() };
+            if let Some(gaining) = gaining {
+                chains.retain(|(symbol, _)| symbol != gaining);
+                chains.push((gaining.clone(), chain.clone()));
+            }
+        }
+
+        let (from, to) = if buys_asset {
+            (action.counter_asset.clone(), Some(action.asset.clone()))
+        } else {
+            (Some(action.asset.clone()), action.counter_asset.clone())
+        };
+        let protected: Vec<&str> = parsed
+            .excluded_assets
+            .iter()
+            .map(String::as_str)
+            .chain([action.asset.as_str()])
+            .chain(action.counter_asset.as_deref())
+            .collect();
+
+        // Take value from the source side, capped at what is held
+        let taken = match &from {
+            Some(symbol) => {
+                let available = holding(&holdings, symbol);
+                if amount_usd > available + 0.005 {
+                    warnings.push(format!("Only ${:.2} of {} held; capped", available, symbol));
+                }
+                let taken = amount_usd.min(available);
+                add_holding(&mut holdings, symbol, -taken);
+                taken
+            }
+            None => spread(&mut holdings, -amount_usd, &protected),
+        };
+        if taken <= 0.0 {
+            warnings.push(format!("Nothing available to fund {} {}", action.verb, action.asset));
+            continue;
+        }
+
+        match &to {
+            Some(symbol) => add_holding(&mut holdings, symbol, taken),
+            None => {
+                let placed = spread(&mut holdings, taken, &protected);
+                if placed < taken {
+                    // Nowhere else to put the proceeds: park them in the stablecoin
+                    add_holding(&mut holdings, stable_asset, taken - placed);
+                }
+            }
+        }
+    }
+
+    let deltas = holdings
+        .iter()
+        .filter_map(|(symbol, value)| {
+            let current = holding(&initial, symbol);
+            let delta = value - current;
+            (delta.abs() >= 0.005).then(|| TargetDelta {
+                asset: symbol.clone(),
+                chain: chains.iter().find(|(s, _)| s == symbol).map(|(_, chain)| chain.clone()),
+                current_usd: format!("{:.2}", current),
+                target_usd: format!("{:.2}", value),
+                delta_usd: format!("{:.2}", delta),
+            })
+        })
+        .collect();
+    (deltas, warnings)
+}
+
+// Applies the deltas to the portfolio to produce the target allocations
+pub(crate) fn apply_target_deltas(
+    portfolio: &[PortfolioAsset],
+    deltas: &[TargetDelta],
+    price: impl Fn(&str) -> Option<f64>,
+) -> Vec<PortfolioAsset> {
+    let mut allocations: Vec<PortfolioAsset> = portfolio.to_vec();
+    for delta in deltas {
+        let mut change: f64 = delta.delta_usd.parse().unwrap_or(0.0);
+        if change > 0.0 {
+            let chain = delta.chain.clone().or_else(|| {
+                allocations.iter().find(|a| a.token_symbol == delta.asset).map(|a| a.chain.clone())
+            });
+            let chain = chain.unwrap_or_else(|| "ethereum".to_string());
+            match allocations.iter_mut().find(|a| a.token_symbol == delta.asset && a.chain == chain) {
+                Some(existing) => set_value(existing, existing.value_usd.parse::<f64>().unwrap_or(0.0) + change, &price),
+                None => {
+                    let mut asset = PortfolioAsset {
+                        token_symbol: delta.asset.clone(),
+                        token_address: delta.asset.to_lowercase(),
+                        balance: "0.0".to_string(),
+                        chain,
+                        value_usd: "0.0".to_string(),
+                        percentage: "0.0".to_string(),
+                    };
+                    set_value(&mut asset, change, &price);
+                    allocations.push(asset);
+                }
+            }
+        } else {
+            for existing in allocations.iter_mut().filter(|a| a.token_symbol == delta.asset) {
+                let value: f64 = existing.value_usd.parse().unwrap_or(0.0);
+                let reduced = value.min(-change);
+                change += reduced;
+                set_value(existing, value - reduced, &price);
+            }
+        }
+    }
+
+    allocations.retain(|a| a.value_usd.parse::<f64>().unwrap_or(0.0) >= 0.005);
+    let total: f64 = allocations.iter().map(|a| a.value_usd.parse::<f64>().unwrap_or(0.0)).sum();
+    for asset in &mut allocations {
+        let value: f64 = asset.value_usd.parse().unwrap_or(0.0);
+        asset.value_usd = format!("{:.2}", value);
+        asset.percentage = format!("{:.2}", if total > 0.0 { value / total * 100.0 } else { 0.0 });
+    }
+    allocations
+}
+
+fn set_value(asset: &mut PortfolioAsset, value: f64, price: &impl Fn(&str) -> Option<f64>) {
+    let old_value: f64 = asset.value_usd.parse().unwrap_or(0.0);
+    let old_balance: f64 = asset.balance.parse().unwrap_or(0.0);
+    let balance = match price(&asset.token_symbol) {
+        Some(p) => value / p,
+        None if old_value > 0.0 => old_balance * value / old_value,
+        None => 0.0,
+    };
+    asset.balance = format!("{:.8}", balance);
+    asset.value_usd = format!("{:.2}", value);
+}
+
+fn holding(holdings: &[(String, f64)], symbol: &str) -> f64 {
+    holdings.iter().find(|(s, _)| s == symbol).map_or(0.0, |(_, value)| *value)
+}
+
+fn add_holding(holdings: &mut Vec<(String, f64)>, symbol: &str, amount: f64) {
+    match holdings.iter_mut().find(|(s, _)| s == symbol) {
+        Some((_, value)) => *value += amount,
+        None => holdings.push((symbol.to_string(), amount)),
+    }
+}
+
+// Adds (or, when negative, removes) `amount` across unprotected holdings by value weight;
+// returns how much was actually moved
+fn spread(holdings: &mut [(String, f64)], amount: f64, protected: &[&str]) -> f64 {
+    let weight: f64 = holdings
+        .iter()
+        .filter(|(symbol, value)| !protected.contains(&symbol.as_str()) && *value > 0.0)
+        .map(|(_, value)| value)
+        .sum();
+    if weight <= 0.0 {
+        return 0.0;
+    }
+    let moved = if amount < 0.0 { amount.max(-weight) } else { amount };
+    for (symbol, value) in holdings.iter_mut() {
+        if !protected.contains(&symbol.as_str()) && *value > 0.0 {
+            *value += moved * *value / weight;
+        }
+    }
+    moved.abs()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const USER: &str = "alice.near";
+
+    // (intent text, expected (asset, delta_usd) pairs, expected warnings)
+    type DeltaCase = (&'static str, &'static [(&'static str, &'static str)], &'static [&'static str]);
+
+    fn action(verb: &str, asset: &str, counter: Option<&str>, chain: Option<&str>, quantity: Option<(&str, &str)>) -> IntentAction {
+        IntentAction {
+            verb: verb.to_string(),
+            asset: asset.to_string(),
+            counter_asset: counter.map(str::to_string),
+            chain: chain.map(str::to_string),
+            quantity: quantity.map(|(unit, amount)| IntentQuantity { unit: unit.to_string(), amount: amount.to_string() }),
+        }
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn parse(contract: &AIPortfolioRebalancer, text: &str) -> ParsedIntent {
+        contract.parse_intent(text.to_string(), None).parsed
+    }
+
+    // Alice holds ETH 2800, USDC 2000, NEAR 2000 and BTC 1200 USD at the default prices. NEAR is
+    // bridged because holdings on the near chain come from the vault.
+    fn portfolio_setup() -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        let holding = |symbol: &str, balance: &str, chain: &str, value_usd: &str| PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: balance.to_string(),
+            chain: chain.to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        };
+        contract.set_user_portfolio(vec![
+            holding("ETH", "1.0", "ethereum", "2800.0"),
+            holding("USDC", "2000.0", "ethereum", "2000.0"),
+            holding("NEAR", "800.0", "arbitrum", "2000.0"),
+            holding("BTC", "0.0285714", "ethereum", "1200.0"),
+        ]);
+        contract
+    }
+
+    #[test]
+    fn parses_actions() {
+        let contract = setup();
+        let pct = |amount| Some(("percent_of_holding", amount));
+        let target = |amount| Some(("target_percent", amount));
+        let cases = [
+            ("move 20% of my ETH to USDC on arbitrum", vec![action("move", "ETH", Some("USDC"), Some("arbitrum"), pct("20"))]),
+            ("swap 100 usdc for near", vec![action("move", "USDC", Some("NEAR"), None, Some(("units", "100")))]),
+            ("convert 300 dollars of eth to usdt", vec![action("move", "ETH", Some("USDT"), None, Some(("usd", "300")))]),
+            ("sell half my btc", vec![action("move", "BTC", None, None, pct("50"))]),
+            ("sell all my btc for usdc", vec![action("move", "BTC", Some("USDC"), None, pct("100"))]),
+            ("swap bitcoin for ethereum", vec![action("move", "BTC", Some("ETH"), None, None)]),
+            ("Move 25 percent of my NEAR into stablecoins.", vec![action("move", "NEAR", Some("USDC"), None, pct("25"))]),
+            ("buy $500 of NEAR", vec![action("increase", "NEAR", None, None, Some(("usd", "500")))]),
+            ("buy 2 eth with usdc", vec![action("increase", "ETH", Some("USDC"), None, Some(("units", "2")))]),
+            ("increase near by 10%", vec![action("increase", "NEAR", None, None, pct("10"))]),
+            ("increase NEAR to 50% using USDC", vec![action("increase", "NEAR", Some("USDC"), None, target("50"))]),
+            ("reduce ETH to 20%", vec![action("reduce", "ETH", None, None, target("20"))]),
+            ("reduce ETH by 50%", vec![action("reduce", "ETH", None, None, pct("50"))]),
+            ("put 30% in usdc", vec![action("set", "USDC", None, None, target("30"))]),
+            ("allocate 40% to eth", vec![action("set", "ETH", None, None, target("40"))]),
+            ("set eth to 10%", vec![action("set", "ETH", None, None, target("10"))]),
+            (
+                "move 1,000 usdc to eth; then swap 0.5 eth into near on near",
+                vec![
+                    action("move", "USDC", Some("ETH"), None, Some(("units", "1000"))),
+                    action("move", "ETH", Some("NEAR"), Some("near"), Some(("units", "0.5"))),
+                ],
+            ),
+        ];
+        for (text, actions) in cases {
+            let parsed = parse(&contract, text);
+            assert_eq!(parsed.actions, actions, "{}", text);
+            assert_eq!(parsed.strategy, None, "{}", text);
+            assert!(parsed.excluded_assets.is_empty(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn negations_protect_assets() {
+        let contract = setup();
+        let cases = [
+            ("keep my BTC and move 50% of ETH to NEAR", vec![action("move", "ETH", Some("NEAR"), None, Some(("percent_of_holding", "50")))], "BTC"),
+            ("sell ETH but not BTC", vec![action("move", "ETH", None, None, None)], "BTC"),
+            ("buy eth not btc", vec![action("increase", "ETH", None, None, None)], "BTC"),
+            ("don't touch BTC", vec![], "BTC"),
+            ("I don't want to sell ETH", vec![], "ETH"),
+            ("never sell near", vec![], "NEAR"),
+        ];
+        for (text, actions, excluded) in cases {
+            let parsed = parse(&contract, text);
+            assert_eq!(parsed.actions, actions, "{}", text);
+            assert_eq!(parsed.excluded_assets, strings(&[excluded]), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_strategies() {
+        let contract = setup();
+        let cases = [
+            ("I am not conservative at all", None, vec!["conservative"]),
+            ("avoid risky assets", None, vec!["aggressive"]),
+            ("make my portfolio conservative", Some("conservative"), vec![]),
+            ("be more aggressive", Some("aggressive"), vec![]),
+            ("I want safe yield", Some("conservative"), vec![]),
+            ("go defi", Some("defi_focused"), vec![]),
+            ("rebalance to cross-chain", Some("cross_chain"), vec![]),
+            ("hello world", None, vec![]),
+        ];
+        for (text, strategy, negated) in cases {
+            let parsed = parse(&contract, text);
+            assert!(parsed.actions.is_empty(), "{}", text);
+            assert_eq!(parsed.strategy.as_deref(), strategy, "{}", text);
+            assert_eq!(parsed.negated_strategies, strings(&negated), "{}", text);
+        }
+    }
+
+    #[test]
+    fn unknown_words_are_reported_and_lower_coverage() {
+        let contract = setup();
+        let parsed = parse(&contract, "hello world, move 20% of eth to usdc");
+        assert_eq!(parsed.unrecognized, strings(&["hello", "world"]));
+        assert_eq!((parsed.recognized_tokens, parsed.total_tokens), (6, 8));
+    }
+
+    #[test]
+    fn unsupported_assets_are_not_parsed() {
+        let contract = setup();
+        let parsed = parse(&contract, "swap 100 pepe for usdc");
+        assert!(parsed.actions.is_empty());
+        assert_eq!(parsed.unrecognized, strings(&["100", "pepe"]));
+
+        let parsed = parse(&contract, "sell my doge and buy $50 of eth");
+        assert_eq!(parsed.actions, vec![action("increase", "ETH", None, None, Some(("usd", "50")))]);
+        assert!(parsed.unrecognized.contains(&"doge".to_string()));
+
+        let parsed = parse(&contract, "don't touch shib");
+        assert!(parsed.excluded_assets.is_empty());
+        assert_eq!(parsed.unrecognized, strings(&["shib"]));
+    }
+
+    #[test]
+    fn unsupported_chains_are_not_parsed() {
+        let contract = setup();
+        let parsed = parse(&contract, "move 20% of my ETH to USDC on solana");
+        assert_eq!(parsed.actions, vec![action("move", "ETH", Some("USDC"), None, Some(("percent_of_holding", "20")))]);
+        assert_eq!(parsed.unrecognized, strings(&["solana"]));
+
+        // A supported asset is still not a chain
+        let parsed = parse(&contract, "swap 100 usdc for near on btc");
+        assert_eq!(parsed.actions[0].chain, None);
+    }
+
+    #[test]
+    fn assets_added_later_are_parsed() {
+        let mut contract = setup();
+        assert!(parse(&contract, "buy $10 of pepe").actions.is_empty());
+        call_as(OWNER);
+        contract.add_supported_asset("PEPE".to_string());
+        assert_eq!(parse(&contract, "buy $10 of pepe").actions, vec![action("increase", "PEPE", None, None, Some(("usd", "10")))]);
+    }
+
+    #[test]
+    fn resolves_deltas_against_the_portfolio() {
+        let contract = portfolio_setup();
+        let cases: [DeltaCase; 11] = [
+            ("move 20% of my ETH to USDC on arbitrum", &[("ETH", "-560.00"), ("USDC", "560.00")], &[]),
+            ("swap 100 usdc for near", &[("USDC", "-100.00"), ("NEAR", "100.00")], &[]),
+            ("buy $500 of NEAR", &[("ETH", "-233.33"), ("USDC", "-166.67"), ("NEAR", "500.00"), ("BTC", "-100.00")], &[]),
+            ("buy 2 eth with usdc", &[("ETH", "2000.00"), ("USDC", "-2000.00")], &["Only $2000.00 of USDC held; capped"]),
+            ("sell all my btc for usdc", &[("USDC", "1200.00"), ("BTC", "-1200.00")], &[]),
+            ("reduce ETH to 20%", &[("ETH", "-1200.00"), ("USDC", "461.54"), ("NEAR", "461.54"), ("BTC", "276.92")], &[]),
+            ("put 30% in usdc", &[("ETH", "-186.67"), ("USDC", "400.00"), ("NEAR", "-133.33"), ("BTC", "-80.00")], &[]),
+            ("increase NEAR to 50% using USDC", &[("USDC", "-2000.00"), ("NEAR", "2000.00")], &[]),
+            ("sell ETH but not BTC", &[("ETH", "-2800.00"), ("USDC", "1400.00"), ("NEAR", "1400.00")], &[]),
+            (
+                "move 1,000 usdc to eth; then swap 0.5 eth into near on near",
+                &[("ETH", "-400.00"), ("USDC", "-1000.00"), ("NEAR", "1400.00")],
+                &[],
+            ),
+            ("reduce eth", &[], &["reduce ETH needs an amount; skipped"]),
+        ];
+        for (text, deltas, warnings) in cases {
+            let result = contract.parse_intent(text.to_string(), Some(USER.to_string()));
+            let mut resolved: Vec<(&str, &str)> = result.deltas.iter().map(|d| (d.asset.as_str(), d.delta_usd.as_str())).collect();
+            let mut expected = deltas.to_vec();
+            resolved.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(resolved, expected, "{}", text);
+            assert_eq!(result.warnings, strings(warnings), "{}", text);
+        }
+    }
+}
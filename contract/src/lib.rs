@@ -1,20 +1,69 @@
-use hex;
 use near_sdk::{
     env::{self, block_timestamp},
     log, near, require,
     serde::{Deserialize, Serialize},
-    store::{IterableMap, IterableSet},
-    AccountId, PanicOnDefault, Promise,
+    store::{IterableMap, IterableSet, Vector},
+    AccountId, PanicOnDefault, Promise, PromiseOrValue,
     borsh::{BorshDeserialize, BorshSerialize},
 };
 use schemars::JsonSchema;
 
+mod asset_registry;
+mod conditional_orders;
+mod confidence;
+mod dao_portfolio;
+mod dao_voting;
+mod fees;
+mod governance;
+mod health_history;
+mod health_projection;
+mod health_scoring;
+mod intent_approval;
+mod intent_lifecycle;
+mod intent_parser;
+mod price_history;
+mod risk_limits;
+mod schedules;
+mod share_token;
+mod simulation;
+mod slippage;
+mod storage;
+mod strategy_pool;
+mod swap;
+#[cfg(test)]
+mod test_utils;
+mod vault;
+mod voting;
+
+pub use asset_registry::*;
+pub use conditional_orders::*;
+pub use confidence::*;
+pub use dao_voting::*;
+pub use fees::*;
+pub use governance::*;
+pub use health_history::*;
+pub use health_scoring::*;
+pub use intent_lifecycle::*;
+pub use intent_parser::*;
+pub use price_history::*;
+pub use risk_limits::*;
+pub use schedules::*;
+pub use share_token::*;
+pub use simulation::*;
+pub use slippage::*;
+pub use storage::*;
+pub use strategy_pool::*;
+pub use swap::*;
+pub use vault::*;
+pub use voting::*;
+
 // External imports for MPC and TEE attestation
 mod external {
     use near_sdk::{ext_contract, Gas, NearToken, Promise};
     
-    const MPC_CONTRACT: &str = "v1.signer-prod.testnet";
+    use crate::MpcConfig;
     
+    #[allow(dead_code)]
     #[ext_contract(mpc_contract)]
     pub trait MpcContract {
         fn sign(&self, request: SignRequest) -> Promise;
@@ -28,16 +77,16 @@ mod external {
         pub key_version: u32,
     }
     
-    pub fn get_sig(payload: Vec<u8>, derivation_path: String, key_version: u32) -> Promise {
+    pub fn get_sig(config: &MpcConfig, payload: Vec<u8>, derivation_path: String, key_version: u32) -> Promise {
         let request = SignRequest {
             payload,
             path: derivation_path,
             key_version,
         };
         
-        mpc_contract::ext(MPC_CONTRACT.parse().unwrap())
-            .with_static_gas(Gas::from_tgas(250))
-            .with_attached_deposit(NearToken::from_millinear(1))
+        mpc_contract::ext(config.contract_id.parse().unwrap())
+            .with_static_gas(Gas::from_tgas(config.sign_gas_tgas))
+            .with_attached_deposit(NearToken::from_millinear(config.sign_deposit_millinear as u128))
             .sign(request)
     }
 }
@@ -63,6 +112,10 @@ pub struct UserPreferences {
     pub excluded_assets: Vec<String>,
     pub rebalance_threshold: String, // percentage
     pub auto_rebalance: bool,
+    #[serde(default = "default_max_slippage_bps")]
+    pub max_slippage_bps: u16,
+    #[serde(default)]
+    pub min_health_score: Option<u8>, // plans may not lower health below this
 }
 
 #[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
@@ -74,11 +127,23 @@ pub struct RebalanceIntent {
     pub timestamp: u64,
     pub classification: String,
     pub confidence_score: u8,
+    pub confidence_factors: Vec<ConfidenceFactor>, // empty for worker-submitted analyses
     pub target_allocations: Vec<PortfolioAsset>,
-    pub status: String, // "pending", "analyzing", "ready", "executing", "completed", "failed"
+    pub status: String, // "pending_dao_approval", "analyzing", "awaiting_approval", "ready", "rejected", "executing", "completed", "failed", "cancelled", "superseded", "expired"
     pub ai_analysis: String,
     pub estimated_gas_cost: String,
     pub execution_steps: Vec<String>,
+    pub dao_proposal_id: Option<u64>, // approving proposal for DAO-owned portfolios
+    pub max_slippage_bps: Option<u16>, // overrides the user's preference
+    pub rejection_reason: Option<String>, // set when a risk limit or the user rejects the intent
+    pub approved_plan_hash: Option<String>, // see get_intent_plan_hash
+    pub valid_until: u64,
+    pub revision_of: Option<u64>,
+    pub superseded_by: Option<u64>,
+    pub schedule_id: Option<u64>, // set for intents spawned by run_due_schedules
+    pub amount_usd: Option<String>, // most USD one execution may move; the schedule's amount_usd_per_run
+    pub health_before: Option<PortfolioHealth>, // current holdings at analysis time
+    pub health_after: Option<PortfolioHealth>, // projected from target_allocations
 }
 
 #[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
@@ -91,12 +156,16 @@ pub struct Trade {
     pub from_chain: String,
     pub to_chain: String,
     pub amount: String,
-    pub expected_output: String,
+    pub expected_output: String, // in to_asset units, quoted at creation
     pub actual_output: String,
-    pub status: String, // "pending", "confirmed", "failed"
+    pub status: String, // "pending", "executing", "confirmed", "flagged", "failed", "withdraw_pending"
     pub tx_hash: String,
     pub timestamp: u64,
     pub gas_used: String,
+    #[serde(default)]
+    pub min_output: String,
+    #[serde(default)]
+    pub max_slippage_bps: u16,
 }
 
 #[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
@@ -132,10 +201,30 @@ pub struct PortfolioHealth {
     pub diversification_score: u8,
     pub risk_score: u8,
     pub concentration_risk: u8,
+    pub volatility_pct: Option<String>, // daily, from price history and correlations
+    pub value_at_risk_usd: Option<String>, // one-day loss not exceeded with 95% confidence
+    pub score_change_7d: Option<i16>, // vs. the user's health snapshot from 7 / 30 days earlier
+    pub score_change_30d: Option<i16>,
     pub recommendations: Vec<String>,
     pub last_updated: u64,
 }
 
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MpcConfig {
+    pub contract_id: String,
+    pub sign_gas_tgas: u64,
+    pub sign_deposit_millinear: u64,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeParams {
+    pub management_fee_bps: u16, // annualized
+    pub performance_fee_bps: u16,
+    pub fee_recipient: String,
+}
+
 #[near(contract_state)]
 #[derive(PanicOnDefault)]
 pub struct AIPortfolioRebalancer {
@@ -147,27 +236,97 @@ pub struct AIPortfolioRebalancer {
     pub user_portfolios: IterableMap<AccountId, Vec<PortfolioAsset>>,
     pub user_preferences: IterableMap<AccountId, UserPreferences>,
     pub user_health: IterableMap<AccountId, PortfolioHealth>,
+    pub health_history: IterableMap<AccountId, Vec<HealthSnapshot>>,
+    pub health_scoring_config: HealthScoringConfig,
     
     // Intent and analysis management
     pub intents: IterableMap<u64, RebalanceIntent>,
     pub next_intent_id: u64,
     pub user_intents: IterableMap<AccountId, Vec<u64>>,
+    pub schedules: IterableMap<u64, Schedule>,
+    pub next_schedule_id: u64,
+    pub user_schedules: IterableMap<AccountId, Vec<u64>>,
+    pub schedule_queue: Vector<(u64, u64)>, // min-heap of (next_run_at, schedule_id)
     
     // Trading and execution
     pub trades: IterableMap<u64, Trade>,
     pub next_trade_id: u64,
-    pub active_rebalances: IterableSet<u64>,
+    pub active_rebalances: IterableSet<u64>, // intents in "executing" with trades left to settle
+    pub intent_trades: IterableMap<u64, Vec<u64>>, // intent_id -> trade ids
+    pub intent_ttl_sec: u64,
+    pub flagged_trades: IterableSet<u64>,
+    pub slippage_stats: IterableMap<String, SlippageStats>, // "asset:<symbol>" / "chain:<name>"
+    pub conditional_orders: IterableMap<u64, ConditionalOrder>,
+    pub next_order_id: u64,
+    pub user_orders: IterableMap<AccountId, Vec<u64>>,
+    pub active_orders_by_asset: IterableMap<String, AssetOrderBook>,
+    pub active_order_counts: IterableMap<AccountId, u32>,
+    pub order_triggers: IterableMap<AccountId, Vec<OrderTrigger>>,
     
     // Worker and MPC management
     pub approved_codehashes: IterableSet<String>,
     pub worker_by_account_id: IterableMap<AccountId, Worker>,
     pub trusted_workers: IterableSet<AccountId>,
+    pub mpc_config: MpcConfig,
+    
+    // Governance
+    pub governance_config: GovernanceConfig,
+    pub proposals: IterableMap<u64, Proposal>,
+    pub next_proposal_id: u64,
+    pub proposal_votes: IterableMap<(u64, AccountId), Vote>,
+    pub timelock_queue: IterableMap<u64, QueuedAction>,
+    pub guardian_id: Option<AccountId>,
+    pub bootstrap_open: bool, // owner may set protocol parameters directly until end_bootstrap
+    pub upgrade_code: IterableMap<String, Vec<u8>>, // sha256 hex -> wasm
+    pub voting_stakes: IterableMap<AccountId, u128>,
+    pub delegations: IterableMap<AccountId, AccountId>,
+    pub voting_power_checkpoints: IterableMap<AccountId, Vector<Checkpoint>>, // per-account vectors under b"J"
+    pub total_voting_power_checkpoints: Vector<Checkpoint>,
+    pub fee_params: FeeParams,
+    pub fee_accounts: IterableMap<AccountId, FeeAccount>,
+    
+    // Risk limits
+    pub user_risk_limits: IterableMap<AccountId, RiskLimits>,
+    pub protocol_risk_limits: RiskLimits,
+    pub daily_rebalance_volume: IterableMap<AccountId, DailyVolume>,
+    pub pool_fee_account: FeeAccount,
+    
+    // External DAO voting
+    pub user_daos: IterableMap<AccountId, Vec<DaoRegistration>>,
+    pub dao_votes: IterableMap<u64, ExternalDaoVote>,
+    pub next_dao_vote_id: u64,
+    pub dao_delegates: IterableMap<AccountId, AccountId>, // dao -> account whose policy the agent votes by
+    pub dao_vote_index: IterableMap<(String, u64), u64>, // (dao, proposal) -> vote id
+    pub user_dao_votes: IterableMap<AccountId, Vec<u64>>,
+    pub proposal_analyses: IterableMap<u64, ProposalAnalysis>,
+    pub next_proposal_analysis_id: u64,
+    pub dao_proposal_analyses: IterableMap<String, Vec<u64>>, // dao_id -> analysis ids
+    pub dao_portfolios: IterableSet<AccountId>,
+    pub used_dao_proposals: IterableSet<(AccountId, u64)>,
+    
+    // Custodial vault
+    pub vault_tokens: IterableMap<String, VaultToken>,
+    pub vault_balances: IterableMap<(AccountId, String), u128>, // (user, token_id) -> raw balance
+    
+    // NEAR-side swap execution
+    pub swap_exchange_id: Option<AccountId>,
+    pub swap_pools: IterableMap<(String, String), u64>, // (token_in, token_out) -> pool id
+    pub swap_lock: Option<SwapLock>,
+    
+    // Pooled strategy vault and its NEP-141 share token
+    pub pool_holdings: IterableMap<String, u128>, // token_id -> raw amount
+    pub share_balances: IterableMap<AccountId, u128>,
+    pub share_total_supply: u128,
+    pub storage_accounts: IterableMap<AccountId, StorageAccount>,
+    pub account_registration_bytes: u64,
     
     // Market and analytics - simplified to avoid Vector issues  
     pub latest_market_analysis_json: String,
     pub supported_chains: IterableSet<String>,
     pub supported_assets: IterableSet<String>,
     pub asset_prices: IterableMap<String, String>, // asset_symbol -> price_usd
+    pub asset_metadata: IterableMap<String, AssetMetadata>, // asset_symbol -> risk tier, depeg flag
+    pub price_history: IterableMap<String, Vec<PricePoint>>, // asset_symbol -> last MAX_PRICE_POINTS prices
     
     // Analytics and metrics
     pub total_volume_usd: String,
@@ -191,27 +350,110 @@ impl AIPortfolioRebalancer {
             user_portfolios: IterableMap::new(b"p"),
             user_preferences: IterableMap::new(b"r"),
             user_health: IterableMap::new(b"h"),
+            health_history: IterableMap::new(b"B"),
+            health_scoring_config: HealthScoringConfig::default(),
             
             // Intent management
             intents: IterableMap::new(b"i"),
             next_intent_id: 1,
             user_intents: IterableMap::new(b"I"),
+            schedules: IterableMap::new(b"n"),
+            next_schedule_id: 1,
+            user_schedules: IterableMap::new(b"N"),
+            schedule_queue: Vector::new(b"1q".to_vec()),
             
             // Trading
             trades: IterableMap::new(b"t"),
             next_trade_id: 1,
             active_rebalances: IterableSet::new(b"a"),
+            intent_trades: IterableMap::new(b"1i".to_vec()), // every single-byte prefix is taken
+            intent_ttl_sec: DEFAULT_INTENT_TTL_SEC,
+            flagged_trades: IterableSet::new(b"f"),
+            slippage_stats: IterableMap::new(b"L"),
+            conditional_orders: IterableMap::new(b"m"),
+            next_order_id: 1,
+            user_orders: IterableMap::new(b"M"),
+            active_orders_by_asset: IterableMap::new(b"G"),
+            active_order_counts: IterableMap::new(b"0"),
+            order_triggers: IterableMap::new(b"E"),
             
             // Worker management
             approved_codehashes: IterableSet::new(b"c"),
             worker_by_account_id: IterableMap::new(b"w"),
             trusted_workers: IterableSet::new(b"T"),
+            mpc_config: MpcConfig {
+                contract_id: "v1.signer-prod.testnet".to_string(),
+                sign_gas_tgas: 250,
+                sign_deposit_millinear: 1,
+            },
+            
+            // Governance
+            governance_config: GovernanceConfig {
+                voting_period_sec: 3 * 24 * 60 * 60,
+                quorum_bps: 2000,
+                threshold_bps: 5000,
+                timelock_delay_sec: 2 * 24 * 60 * 60,
+            },
+            proposals: IterableMap::new(b"g"),
+            next_proposal_id: 1,
+            proposal_votes: IterableMap::new(b"v"),
+            timelock_queue: IterableMap::new(b"Q"),
+            guardian_id: None,
+            bootstrap_open: true,
+            upgrade_code: IterableMap::new(b"C"),
+            voting_stakes: IterableMap::new(b"k"),
+            delegations: IterableMap::new(b"d"),
+            voting_power_checkpoints: IterableMap::new(b"K"),
+            total_voting_power_checkpoints: Vector::new(b"q"),
+            fee_params: FeeParams {
+                management_fee_bps: 0,
+                performance_fee_bps: 0,
+                fee_recipient: owner_id.clone(),
+            },
+            fee_accounts: IterableMap::new(b"F"),
+            pool_fee_account: new_fee_account(POOL_FEE_ACCOUNT_ID, 0.0),
+            
+            // Risk limits
+            user_risk_limits: IterableMap::new(b"l"),
+            protocol_risk_limits: RiskLimits::default(),
+            daily_rebalance_volume: IterableMap::new(b"j"),
+            
+            // External DAO voting
+            user_daos: IterableMap::new(b"D"),
+            dao_votes: IterableMap::new(b"x"),
+            next_dao_vote_id: 1,
+            dao_delegates: IterableMap::new(b"U"),
+            dao_vote_index: IterableMap::new(b"X"),
+            user_dao_votes: IterableMap::new(b"y"),
+            proposal_analyses: IterableMap::new(b"z"),
+            next_proposal_analysis_id: 1,
+            dao_proposal_analyses: IterableMap::new(b"Z"),
+            dao_portfolios: IterableSet::new(b"o"),
+            used_dao_proposals: IterableSet::new(b"O"),
+            
+            // Custodial vault
+            vault_tokens: IterableMap::new(b"V"),
+            vault_balances: IterableMap::new(b"b"),
+            
+            // Swap execution
+            swap_exchange_id: None,
+            swap_pools: IterableMap::new(b"R"),
+            swap_lock: None,
+            
+            // Pooled strategy vault
+            pool_holdings: IterableMap::new(b"H"),
+            share_balances: IterableMap::new(b"e"),
+            share_total_supply: 0,
+            storage_accounts: IterableMap::new(b"A"),
+            account_registration_bytes: 0,
             
             // Market data - simplified
             latest_market_analysis_json: "{}".to_string(),
             supported_chains: IterableSet::new(b"s"),
             supported_assets: IterableSet::new(b"S"),
             asset_prices: IterableMap::new(b"P"),
+            asset_metadata: IterableMap::new(b"W"),
+            price_history: IterableMap::new(b"Y"),
             
             // Analytics
             total_volume_usd: "0.0".to_string(),
@@ -252,6 +494,20 @@ impl AIPortfolioRebalancer {
         contract.asset_prices.insert("USDC".to_string(), "1.0".to_string());
         contract.asset_prices.insert("USDT".to_string(), "1.0".to_string());
         
+        // Initialize asset risk tiers
+        for metadata in default_asset_registry() {
+            contract.asset_metadata.insert(metadata.symbol.clone(), metadata);
+        }
+        
+        // Native NEAR is always accepted by the vault
+        contract.vault_tokens.insert(NEAR_TOKEN_ID.to_string(), VaultToken {
+            token_id: NEAR_TOKEN_ID.to_string(),
+            symbol: "NEAR".to_string(),
+            decimals: 24,
+        });
+        
+        contract.measure_account_registration_bytes();
+        
         contract
     }
 
@@ -273,43 +529,26 @@ impl AIPortfolioRebalancer {
     }
 
     // User management
+    #[payable]
     pub fn register_user(&mut self) -> String {
         let user_id = env::predecessor_account_id();
-        
-        if !self.users.contains(&user_id) {
-            self.users.insert(user_id.clone());
-            self.total_users += 1;
-            
-            // Initialize empty user data
-            self.user_portfolios.insert(user_id.clone(), Vec::new());
-            self.user_intents.insert(user_id.clone(), Vec::new());
-            
-            // Set default preferences
-            let default_preferences = UserPreferences {
-                risk_tolerance: "medium".to_string(),
-                investment_horizon: "medium".to_string(),
-                preferred_chains: vec!["ethereum".to_string(), "near".to_string()],
-                excluded_assets: Vec::new(),
-                rebalance_threshold: "5.0".to_string(),
-                auto_rebalance: false,
-            };
-            self.user_preferences.insert(user_id.clone(), default_preferences);
-            
-            log!("User registered: {}", user_id);
-            format!("User {} registered successfully. Total users: {}", user_id, self.total_users)
-        } else {
-            format!("User {} already registered", user_id)
-        }
+        let initial_storage = self.begin_storage_charge(&user_id);
+        let result = self.internal_register_user(user_id.clone());
+        self.settle_storage_charge(&user_id, initial_storage);
+        result
     }
 
+    #[payable]
     pub fn set_user_preferences(&mut self, preferences: UserPreferences) -> String {
         let user_id = env::predecessor_account_id();
+        let initial_storage = self.begin_storage_charge(&user_id);
         
         if !self.users.contains(&user_id) {
-            self.register_user();
+            self.internal_register_user(user_id.clone());
         }
         
         self.user_preferences.insert(user_id.clone(), preferences);
+        self.settle_storage_charge(&user_id, initial_storage);
         log!("Preferences updated for user {}", user_id);
         "User preferences updated successfully".to_string()
     }
@@ -319,17 +558,21 @@ impl AIPortfolioRebalancer {
         self.user_preferences.get(&account_id).cloned()
     }
 
+    #[payable]
     pub fn set_user_portfolio(&mut self, portfolio: Vec<PortfolioAsset>) -> String {
         let user_id = env::predecessor_account_id();
+        let initial_storage = self.begin_storage_charge(&user_id);
         
         if !self.users.contains(&user_id) {
-            self.register_user();
+            self.internal_register_user(user_id.clone());
         }
         
+        let portfolio = self.merge_vault_assets(&user_id, portfolio);
         self.user_portfolios.insert(user_id.clone(), portfolio);
         
         // Update portfolio health
         self.update_portfolio_health(user_id.clone());
+        self.settle_storage_charge(&user_id, initial_storage);
         
         log!("Portfolio updated for user {}", user_id);
         "Portfolio updated successfully".to_string()
@@ -341,68 +584,63 @@ impl AIPortfolioRebalancer {
     }
 
     // AI-powered intent processing
-    pub fn submit_intent(&mut self, intent_text: String) -> u64 {
+    #[payable]
+    pub fn submit_intent(&mut self, intent_text: String, valid_for_sec: Option<u64>) -> u64 {
         let user_id = env::predecessor_account_id();
+        require!(
+            !self.dao_portfolios.contains(&user_id),
+            "DAO portfolios submit intents through submit_dao_intent"
+        );
+        let initial_storage = self.begin_storage_charge(&user_id);
         
         if !self.users.contains(&user_id) {
-            self.register_user();
+            self.internal_register_user(user_id.clone());
         }
         
-        let intent_id = self.next_intent_id;
-        self.next_intent_id += 1;
-        self.total_intents += 1;
-        
-        // Create initial intent
-        let intent = RebalanceIntent {
-            id: intent_id,
-            user_id: user_id.to_string(),
-            intent_text: intent_text.clone(),
-            timestamp: block_timestamp(),
-            classification: "analyzing".to_string(),
-            confidence_score: 0,
-            target_allocations: Vec::new(),
-            status: "analyzing".to_string(),
-            ai_analysis: "Processing intent with AI...".to_string(),
-            estimated_gas_cost: "0.0".to_string(),
-            execution_steps: Vec::new(),
-        };
-        
-        self.intents.insert(intent_id, intent);
-        
-        // Add to user's intent list
-        let mut user_intent_list = self.user_intents.get(&user_id).cloned().unwrap_or_default();
-        user_intent_list.push(intent_id);
-        self.user_intents.insert(user_id.clone(), user_intent_list);
-        
-        log!("Intent {} submitted by {}: {}", intent_id, user_id, intent_text);
+        let intent_id = self.internal_submit_intent(user_id.clone(), intent_text, valid_for_sec);
+        self.settle_storage_charge(&user_id, initial_storage);
         intent_id
     }
 
+    // The analysis is stored with the intent, so its owner pays for the bytes; any deposit
+    // attached here is credited to the owner's storage balance
+    #[payable]
     pub fn analyze_intent(&mut self, intent_id: u64) -> String {
-        let mut intent = self.intents.get(&intent_id).unwrap().clone();
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        let caller = env::predecessor_account_id();
+        require!(
+            caller.as_str() == intent.user_id || caller == self.owner_id || self.trusted_workers.contains(&caller),
+            "Only the intent creator, the owner or a trusted worker can analyze an intent"
+        );
+        require!(intent.status == "analyzing", "Intent is not awaiting analysis");
+        self.require_not_expired(&intent);
         let user_account_id: AccountId = intent.user_id.parse().unwrap();
+        let initial_storage = self.begin_storage_charge(&user_account_id);
         let user_portfolio = self.user_portfolios.get(&user_account_id).cloned().unwrap_or_default();
+        let preferences = self.user_preferences.get(&user_account_id);
         
         // AI analysis simulation based on intent text
-        let analysis = self.perform_ai_analysis(&intent.intent_text, &user_portfolio);
+        let analysis = self.perform_ai_analysis(&intent.intent_text, &user_portfolio, preferences);
         
         intent.classification = analysis.classification;
         intent.confidence_score = analysis.confidence_score;
+        intent.confidence_factors = analysis.confidence_factors;
         let reasoning = analysis.reasoning.clone();
         intent.ai_analysis = analysis.reasoning;
         intent.target_allocations = analysis.target_allocations;
         intent.estimated_gas_cost = analysis.estimated_gas_cost;
         intent.execution_steps = analysis.execution_steps;
-        intent.status = "ready".to_string();
+        self.complete_intent_analysis(&mut intent);
         
         self.intents.insert(intent_id, intent);
+        self.settle_storage_charge(&user_account_id, initial_storage);
         
         log!("Intent {} analyzed: {} confidence", intent_id, analysis.confidence_score);
         reasoning
     }
 
     pub fn get_intent(&self, intent_id: u64) -> Option<RebalanceIntent> {
-        self.intents.get(&intent_id).cloned()
+        self.intents.get(&intent_id).cloned().map(with_expiry)
     }
 
     pub fn get_user_intents(&self, user_id: String) -> Vec<u64> {
@@ -411,13 +649,22 @@ impl AIPortfolioRebalancer {
     }
 
     // Portfolio health analysis
+    #[payable]
     pub fn analyze_portfolio_health(&mut self, user_id: String) -> PortfolioHealth {
         let account_id: AccountId = user_id.parse().unwrap();
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == account_id || caller == self.owner_id || self.trusted_workers.contains(&caller),
+            "Only the account itself, the owner or a trusted worker can analyze portfolio health"
+        );
+        let initial_storage = self.begin_storage_charge(&account_id);
         let portfolio = self.user_portfolios.get(&account_id).cloned().unwrap_or_default();
-        
-        let health = self.calculate_portfolio_health(&portfolio, &account_id);
-        self.user_health.insert(account_id, health.clone());
-        
+
+        let mut health = self.calculate_portfolio_health(&portfolio, &account_id);
+        self.apply_health_trend(&account_id, &mut health);
+        self.user_health.insert(account_id.clone(), health.clone());
+        self.settle_storage_charge(&account_id, initial_storage);
+
         health
     }
 
@@ -427,60 +674,68 @@ impl AIPortfolioRebalancer {
     }
 
     // Trade execution and MPC integration
-    pub fn execute_rebalance(&mut self, intent_id: u64) -> String {
-        let mut intent = self.intents.get(&intent_id).unwrap().clone();
+    #[payable]
+    pub fn execute_rebalance(&mut self, intent_id: u64) -> PromiseOrValue<String> {
+        let intent = self.intents.get(&intent_id).unwrap().clone();
+        let intent_user_id: AccountId = intent.user_id.parse().unwrap();
+        
+        if intent.status != "ready" {
+            return PromiseOrValue::Value("Intent not ready for execution".to_string());
+        }
+        require!(self.plan_matches_approval(&intent), "Intent plan differs from the approved plan");
+        
+        // DAO portfolios re-check their approving proposal before any trades are generated
+        if self.dao_portfolios.contains(&intent_user_id) {
+            return PromiseOrValue::Promise(self.verify_dao_proposal_for_execution(&intent));
+        }
         
         // Check if user is owner or if they own the intent
         let caller = env::predecessor_account_id();
-        let intent_user_id: AccountId = intent.user_id.parse().unwrap();
         require!(
             caller == self.owner_id || caller == intent_user_id,
             "Only owner or intent creator can execute rebalance"
         );
         
-        if intent.status != "ready" {
-            return "Intent not ready for execution".to_string();
-        }
-        
-        intent.status = "executing".to_string();
-        self.intents.insert(intent_id, intent.clone());
-        self.active_rebalances.insert(intent_id);
-        
-        // Generate trades from target allocations
-        self.generate_trades_from_intent(&intent);
-        
-        log!("Executing rebalance for intent: {}", intent_id);
-        format!("Rebalance execution initiated for intent {}. Trades will be processed automatically.", intent_id)
+        let initial_storage = self.begin_storage_charge(&intent_user_id);
+        let result = self.internal_execute_rebalance(intent);
+        self.settle_storage_charge(&intent_user_id, initial_storage);
+        PromiseOrValue::Value(result)
     }
 
     pub fn create_trade(&mut self, intent_id: u64, trade_data: Trade) -> u64 {
         self.require_owner();
-        
-        let trade_id = self.next_trade_id;
-        self.next_trade_id += 1;
-        self.total_trades += 1;
-        
-        let mut trade = trade_data;
-        trade.id = trade_id;
-        trade.intent_id = intent_id;
-        trade.timestamp = block_timestamp();
-        trade.status = "pending".to_string();
-        
-        self.trades.insert(trade_id, trade.clone());
-        
-        log!("Trade {} created for intent {}", trade_id, intent_id);
-        trade_id
+        self.internal_create_trade(intent_id, trade_data)
     }
 
     pub fn update_trade_status(&mut self, trade_id: u64, status: String, tx_hash: String, actual_output: String) -> String {
         self.require_owner();
         
         let mut trade = self.trades.get(&trade_id).unwrap().clone();
-        trade.status = status.clone();
+        // Settled trades only accept a repeat of the same report, which changes nothing
+        if TERMINAL_TRADE_STATUSES.contains(&trade.status.as_str()) {
+            let same_status = trade.status == status || (trade.status == "flagged" && status == "confirmed");
+            require!(
+                same_status && trade.actual_output == actual_output,
+                format!("Trade {} is already {}", trade_id, trade.status)
+            );
+            return format!("Trade {} is already {}", trade_id, trade.status);
+        }
+        let violation = status == "confirmed" && self.violates_min_output(&trade, &actual_output);
+        trade.status = if violation { "flagged".to_string() } else { status.clone() };
         trade.tx_hash = tx_hash;
         trade.actual_output = actual_output;
         
-        self.trades.insert(trade_id, trade);
+        if status == "confirmed" {
+            self.record_trade_slippage(&trade, violation);
+        }
+        self.trades.insert(trade_id, trade.clone());
+        self.settle_rebalance(trade.intent_id);
+        
+        if violation {
+            self.flagged_trades.insert(trade_id);
+            log!("Trade {} output {} is below min_output {}; flagged for review", trade_id, trade.actual_output, trade.min_output);
+            return format!("Trade {} rejected: output below min_output {}. Flagged for review", trade_id, trade.min_output);
+        }
         
         log!("Trade {} status updated to {}", trade_id, status);
         "Trade status updated successfully".to_string()
@@ -492,40 +747,26 @@ impl AIPortfolioRebalancer {
 
     // MPC and Worker management
     pub fn approve_codehash(&mut self, codehash: String) {
-        self.require_owner();
+        self.require_bootstrap_owner();
         self.approved_codehashes.insert(codehash);
     }
 
-    pub fn register_worker(&mut self, quote_hex: String, _collateral: String, checksum: String) -> bool {
+    // Workers gate votes, swaps and analyses, so only the owner (during bootstrap) adds them;
+    // afterwards they are added through a RegisterWorker proposal
+    pub fn register_worker(&mut self, quote_hex: String, _collateral: String, checksum: String, account_id: Option<String>) -> bool {
+        self.require_bootstrap_owner();
         // Simplified worker registration - in production, this would include full TEE attestation
-        let worker_id = env::predecessor_account_id();
-        
-        let worker = Worker {
-            checksum,
-            codehash: quote_hex, // Simplified - would be extracted from quote
-            registration_timestamp: block_timestamp(),
-            last_activity: block_timestamp(),
-            tasks_completed: 0,
-            success_rate: 100,
-        };
-        
-        self.worker_by_account_id.insert(worker_id.clone(), worker);
-        self.trusted_workers.insert(worker_id.clone());
-        
-        log!("Worker registered: {}", worker_id);
+        let worker_id = account_id.map_or_else(env::predecessor_account_id, |id| id.parse().unwrap());
+        self.internal_register_worker(worker_id, quote_hex, checksum);
         true
     }
 
     pub fn sign_transaction(&mut self, payload: Vec<u8>, derivation_path: String, key_version: u32) -> Promise {
         // Verify worker is registered
-        let worker_id = env::predecessor_account_id();
-        require!(
-            self.trusted_workers.contains(&worker_id),
-            "Worker not registered or not trusted"
-        );
+        self.require_trusted_worker();
         
         // Call MPC contract for signing
-        external::get_sig(payload, derivation_path, key_version)
+        external::get_sig(&self.mpc_config, payload, derivation_path, key_version)
     }
 
     // Market analysis and insights
@@ -576,7 +817,9 @@ impl AIPortfolioRebalancer {
     // Asset price management
     pub fn update_asset_price(&mut self, asset_symbol: String, price_usd: String) {
         self.require_owner();
-        self.asset_prices.insert(asset_symbol, price_usd);
+        self.asset_prices.insert(asset_symbol.clone(), price_usd.clone());
+        self.record_price_point(&asset_symbol, &price_usd);
+        self.evaluate_conditional_orders(&asset_symbol, &price_usd);
     }
 
     pub fn get_asset_price(&self, asset_symbol: String) -> Option<String> {
@@ -616,15 +859,36 @@ impl AIPortfolioRebalancer {
 
     // Admin functions
     pub fn add_supported_chain(&mut self, chain: String) -> String {
-        self.require_owner();
-        self.supported_chains.insert(chain.clone());
-        format!("Chain {} added to supported chains", chain)
+        self.require_bootstrap_owner();
+        self.internal_add_supported_chain(chain)
     }
 
     pub fn add_supported_asset(&mut self, asset: String) -> String {
+        self.require_bootstrap_owner();
+        self.internal_add_supported_asset(asset)
+    }
+
+    pub fn revoke_worker(&mut self, account_id: String) -> String {
         self.require_owner();
-        self.supported_assets.insert(asset.clone());
-        format!("Asset {} added to supported assets", asset)
+        self.internal_revoke_worker(account_id.parse().unwrap())
+    }
+
+    pub fn set_mpc_config(&mut self, config: MpcConfig) {
+        self.require_bootstrap_owner();
+        self.mpc_config = config;
+    }
+
+    pub fn get_mpc_config(&self) -> MpcConfig {
+        self.mpc_config.clone()
+    }
+
+    pub fn set_fee_params(&mut self, params: FeeParams) {
+        self.require_bootstrap_owner();
+        self.internal_set_fee_params(params);
+    }
+
+    pub fn get_fee_params(&self) -> FeeParams {
+        self.fee_params.clone()
     }
 
     pub fn update_success_rate(&mut self, rate: u8) {
@@ -645,24 +909,236 @@ impl AIPortfolioRebalancer {
         );
     }
 
-    fn perform_ai_analysis(&self, intent_text: &str, portfolio: &[PortfolioAsset]) -> AIAnalysisResult {
-        let intent_lower = intent_text.to_lowercase();
+    fn require_bootstrap_owner(&self) {
+        self.require_owner();
+        require!(self.bootstrap_open, "Bootstrap has ended; submit a governance proposal instead");
+    }
+
+    fn require_trusted_worker(&self) -> AccountId {
+        let worker_id = env::predecessor_account_id();
+        require!(
+            self.trusted_workers.contains(&worker_id),
+            "Worker not registered or not trusted"
+        );
+        worker_id
+    }
+
+    pub(crate) fn internal_register_user(&mut self, user_id: AccountId) -> String {
+        if !self.users.contains(&user_id) {
+            self.users.insert(user_id.clone());
+            self.total_users += 1;
+            
+            // Initialize empty user data
+            self.user_portfolios.insert(user_id.clone(), Vec::new());
+            self.user_intents.insert(user_id.clone(), Vec::new());
+            
+            // Set default preferences
+            let default_preferences = UserPreferences {
+                risk_tolerance: "medium".to_string(),
+                investment_horizon: "medium".to_string(),
+                preferred_chains: vec!["ethereum".to_string(), "near".to_string()],
+                excluded_assets: Vec::new(),
+                rebalance_threshold: "5.0".to_string(),
+                auto_rebalance: false,
+                max_slippage_bps: DEFAULT_MAX_SLIPPAGE_BPS,
+                min_health_score: None,
+            };
+            self.user_preferences.insert(user_id.clone(), default_preferences);
+            
+            log!("User registered: {}", user_id);
+            format!("User {} registered successfully. Total users: {}", user_id, self.total_users)
+        } else {
+            format!("User {} already registered", user_id)
+        }
+    }
+
+
+    fn internal_submit_intent(&mut self, user_id: AccountId, intent_text: String, valid_for_sec: Option<u64>) -> u64 {
+        let valid_until = self.intent_valid_until(valid_for_sec);
+        let intent_id = self.next_intent_id;
+        self.next_intent_id += 1;
+        self.total_intents += 1;
+        
+        // Create initial intent
+        let intent = RebalanceIntent {
+            id: intent_id,
+            user_id: user_id.to_string(),
+            intent_text: intent_text.clone(),
+            timestamp: block_timestamp(),
+            classification: "analyzing".to_string(),
+            confidence_score: 0,
+            confidence_factors: Vec::new(),
+            target_allocations: Vec::new(),
+            status: "analyzing".to_string(),
+            ai_analysis: "Processing intent with AI...".to_string(),
+            estimated_gas_cost: "0.0".to_string(),
+            execution_steps: Vec::new(),
+            dao_proposal_id: None,
+            max_slippage_bps: None,
+            rejection_reason: None,
+            approved_plan_hash: None,
+            valid_until,
+            revision_of: None,
+            superseded_by: None,
+            schedule_id: None,
+            amount_usd: None,
+            health_before: None,
+            health_after: None,
+        };
+        
+        self.intents.insert(intent_id, intent);
+        
+        // Add to user's intent list
+        let mut user_intent_list = self.user_intents.get(&user_id).cloned().unwrap_or_default();
+        user_intent_list.push(intent_id);
+        self.user_intents.insert(user_id.clone(), user_intent_list);
+        
+        log!("Intent {} submitted by {}: {}", intent_id, user_id, intent_text);
+        intent_id
+    }
+
+    fn internal_execute_rebalance(&mut self, mut intent: RebalanceIntent) -> String {
+        let intent_id = intent.id;
+        if is_expired(&intent) {
+            intent.status = "expired".to_string();
+            self.intents.insert(intent_id, intent);
+            return format!("Intent {} has expired; rebalance not executed", intent_id);
+        }
+        if !self.plan_matches_approval(&intent) {
+            return format!("Intent {} plan differs from the approved plan; rebalance not executed", intent_id);
+        }
+        if let Err(reason) = self.check_execution_limits(&intent) {
+            intent.status = "rejected".to_string();
+            intent.rejection_reason = Some(reason.clone());
+            self.intents.insert(intent_id, intent);
+            log!("Rebalance for intent {} rejected: {}", intent_id, reason);
+            return format!("Rebalance rejected for intent {}: {}", intent_id, reason);
+        }
+        
+        intent.status = "executing".to_string();
+        self.intents.insert(intent_id, intent.clone());
+        self.active_rebalances.insert(intent_id);
+        
+        // Fees accrue up to each rebalance against the pre-trade portfolio value
+        self.internal_accrue_user_fees(&intent.user_id.parse().unwrap());
+        
+        // Generate trades from target allocations
+        let moved_usd = self.generate_trades_from_intent(&intent);
+        self.record_rebalance_volume(&intent.user_id.parse().unwrap(), moved_usd);
+        if let Some(schedule_id) = intent.schedule_id {
+            self.record_schedule_spend(schedule_id, moved_usd);
+        }
+        // A plan with nothing to trade is done right away
+        self.settle_rebalance(intent_id);
+        
+        log!("Executing rebalance for intent: {}", intent_id);
+        format!("Rebalance execution initiated for intent {}. Trades will be processed automatically.", intent_id)
+    }
+
+    fn internal_create_trade(&mut self, intent_id: u64, trade_data: Trade) -> u64 {
+        let trade_id = self.next_trade_id;
+        self.next_trade_id += 1;
+        self.total_trades += 1;
+        
+        let mut trade = trade_data;
+        trade.id = trade_id;
+        trade.intent_id = intent_id;
+        trade.timestamp = block_timestamp();
+        trade.status = "pending".to_string();
+        self.apply_slippage_bounds(&mut trade);
+        
+        self.trades.insert(trade_id, trade.clone());
+        let mut trade_ids = self.intent_trades.get(&intent_id).cloned().unwrap_or_default();
+        trade_ids.push(trade_id);
+        self.intent_trades.insert(intent_id, trade_ids);
+        
+        log!("Trade {} created for intent {}", trade_id, intent_id);
+        trade_id
+    }
+
+    fn internal_add_supported_chain(&mut self, chain: String) -> String {
+        self.supported_chains.insert(chain.clone());
+        format!("Chain {} added to supported chains", chain)
+    }
+
+    fn internal_add_supported_asset(&mut self, asset: String) -> String {
+        self.supported_assets.insert(asset.clone());
+        format!("Asset {} added to supported assets", asset)
+    }
+
+    fn internal_register_worker(&mut self, worker_id: AccountId, codehash: String, checksum: String) {
+        let worker = Worker {
+            checksum,
+            codehash, // Simplified - would be extracted from quote
+            registration_timestamp: block_timestamp(),
+            last_activity: block_timestamp(),
+            tasks_completed: 0,
+            success_rate: 100,
+        };
         
-        // AI analysis simulation
-        let (classification, confidence_score, reasoning) = if intent_lower.contains("conservative") || intent_lower.contains("safe") {
-            ("conservative", 88, "Conservative strategy detected. Recommending increased allocation to stablecoins and established assets.")
-        } else if intent_lower.contains("aggressive") || intent_lower.contains("risky") {
-            ("aggressive", 82, "Aggressive strategy detected. Recommending higher allocation to growth assets and emerging protocols.")
-        } else if intent_lower.contains("defi") || intent_lower.contains("yield") {
-            ("defi_focused", 85, "DeFi strategy detected. Recommending diversified DeFi portfolio with yield farming focus.")
-        } else if intent_lower.contains("cross") || intent_lower.contains("chain") {
-            ("cross_chain", 90, "Cross-chain strategy detected. Recommending multi-chain diversification approach.")
+        self.worker_by_account_id.insert(worker_id.clone(), worker);
+        self.trusted_workers.insert(worker_id.clone());
+        
+        log!("Worker registered: {}", worker_id);
+    }
+
+    fn internal_revoke_worker(&mut self, worker_id: AccountId) -> String {
+        self.trusted_workers.remove(&worker_id);
+        self.worker_by_account_id.remove(&worker_id);
+        log!("Worker revoked: {}", worker_id);
+        format!("Worker {} revoked", worker_id)
+    }
+
+    fn internal_set_fee_params(&mut self, params: FeeParams) {
+        require!(params.management_fee_bps <= 10_000 && params.performance_fee_bps <= 10_000, "Fee bps must be at most 10000");
+        params.fee_recipient.parse::<AccountId>().expect("Invalid fee recipient");
+        self.fee_params = params;
+    }
+
+    fn perform_ai_analysis(&self, intent_text: &str, portfolio: &[PortfolioAsset], preferences: Option<&UserPreferences>) -> AIAnalysisResult {
+        let parsed = parse_intent_text(intent_text, &self.parser_vocabulary());
+        let mut infeasible_actions = 0;
+        
+        // Structured actions take precedence; strategy keywords are the fallback
+        let (classification, reasoning, target_allocations) = if !parsed.actions.is_empty() {
+            let (deltas, warnings) = resolve_target_deltas(&parsed, portfolio, &self.preferred_stable(), |symbol| self.price_of(symbol));
+            let mut reasoning = format!(
+                "Structured intent with {} action(s). Target deltas: {}.",
+                parsed.actions.len(),
+                if deltas.is_empty() {
+                    "none".to_string()
+                } else {
+                    deltas.iter().map(|d| format!("{} {} USD", d.asset, d.delta_usd)).collect::<Vec<_>>().join(", ")
+                }
+            );
+            infeasible_actions = warnings.len();
+            if !warnings.is_empty() {
+                reasoning.push_str(&format!(" Warnings: {}.", warnings.join("; ")));
+            }
+            let allocations = apply_target_deltas(portfolio, &deltas, |symbol| self.price_of(symbol));
+            ("structured".to_string(), reasoning, allocations)
         } else {
-            ("balanced", 75, "Balanced rebalancing strategy detected. Recommending risk-adjusted portfolio optimization.")
+            let (classification, reasoning) = match parsed.strategy.as_deref() {
+                Some("conservative") => ("conservative", "Conservative strategy detected. Recommending increased allocation to stablecoins and established assets."),
+                Some("aggressive") => ("aggressive", "Aggressive strategy detected. Recommending higher allocation to growth assets and emerging protocols."),
+                Some("defi_focused") => ("defi_focused", "DeFi strategy detected. Recommending diversified DeFi portfolio with yield farming focus."),
+                Some("cross_chain") => ("cross_chain", "Cross-chain strategy detected. Recommending multi-chain diversification approach."),
+                _ => ("balanced", "Balanced rebalancing strategy detected. Recommending risk-adjusted portfolio optimization."),
+            };
+            
+            // Generate target allocations based on strategy
+            let target_allocations = self.generate_target_allocations(classification, portfolio);
+            (classification.to_string(), reasoning.to_string(), target_allocations)
         };
         
-        // Generate target allocations based on strategy
-        let target_allocations = self.generate_target_allocations(classification, portfolio);
+        let (confidence_score, confidence_factors) = self.score_confidence(&ConfidenceInputs {
+            parsed: &parsed,
+            classification: &classification,
+            portfolio,
+            target_allocations: &target_allocations,
+            infeasible_actions,
+            preferences,
+        });
         
         // Estimate gas costs
         let estimated_gas_cost = self.estimate_gas_costs(&target_allocations);
@@ -671,9 +1147,10 @@ impl AIPortfolioRebalancer {
         let execution_steps = self.generate_execution_steps(&target_allocations);
         
         AIAnalysisResult {
-            classification: classification.to_string(),
+            classification,
             confidence_score,
-            reasoning: reasoning.to_string(),
+            confidence_factors,
+            reasoning,
             target_allocations,
             estimated_gas_cost,
             execution_steps,
@@ -765,7 +1242,7 @@ impl AIPortfolioRebalancer {
             }
         }
         
-        allocations
+        allocations.into_iter().map(|allocation| self.replace_depegged(allocation)).collect()
     }
 
     fn estimate_gas_costs(&self, allocations: &[PortfolioAsset]) -> String {
@@ -777,16 +1254,16 @@ impl AIPortfolioRebalancer {
         format!("{:.2}", total_cost)
     }
 
-    fn generate_execution_steps(&self, allocations: &[PortfolioAsset]) -> Vec<String> {
-        let mut steps = Vec::new();
-        
-        steps.push("1. Analyze current portfolio positions".to_string());
-        steps.push("2. Calculate required trades for rebalancing".to_string());
-        steps.push("3. Optimize trade execution order".to_string());
-        steps.push("4. Execute cross-chain trades via MPC".to_string());
-        steps.push("5. Monitor trade execution and update status".to_string());
-        steps.push("6. Verify final portfolio allocations".to_string());
-        steps.push("7. Update user portfolio and health metrics".to_string());
+    pub(crate) fn generate_execution_steps(&self, allocations: &[PortfolioAsset]) -> Vec<String> {
+        let mut steps = vec![
+            "1. Analyze current portfolio positions".to_string(),
+            "2. Calculate required trades for rebalancing".to_string(),
+            "3. Optimize trade execution order".to_string(),
+            "4. Execute cross-chain trades via MPC".to_string(),
+            "5. Monitor trade execution and update status".to_string(),
+            "6. Verify final portfolio allocations".to_string(),
+            "7. Update user portfolio and health metrics".to_string(),
+        ];
         
         for allocation in allocations {
             steps.push(format!("Trade to achieve {}% allocation in {}", allocation.percentage, allocation.token_symbol));
@@ -797,7 +1274,9 @@ impl AIPortfolioRebalancer {
 
     fn update_portfolio_health(&mut self, user_id: AccountId) {
         let portfolio = self.user_portfolios.get(&user_id).cloned().unwrap_or_default();
-        let health = self.calculate_portfolio_health(&portfolio, &user_id);
+        let mut health = self.calculate_portfolio_health(&portfolio, &user_id);
+        self.apply_health_trend(&user_id, &mut health);
+        self.record_health_snapshot(&user_id, &health, &portfolio);
         self.user_health.insert(user_id, health);
     }
 
@@ -805,16 +1284,10 @@ impl AIPortfolioRebalancer {
         let diversification_score = self.calculate_diversification_score(portfolio);
         let risk_score = self.calculate_risk_score(portfolio);
         let concentration_risk = self.calculate_concentration_risk(portfolio);
+        let (volatility_pct, value_at_risk_usd) = self.portfolio_risk_estimates(portfolio);
         
-        let overall_score = (diversification_score + risk_score + (100 - concentration_risk)) / 3;
-        
-        let grade = match overall_score {
-            90..=100 => "A",
-            80..=89 => "B",
-            70..=79 => "C",
-            60..=69 => "D",
-            _ => "F",
-        };
+        let overall_score = self.combine_health_scores(diversification_score, risk_score, concentration_risk);
+        let grade = self.health_grade(overall_score);
         
         let recommendations = self.generate_health_recommendations(portfolio, overall_score);
         
@@ -825,40 +1298,31 @@ impl AIPortfolioRebalancer {
             diversification_score,
             risk_score,
             concentration_risk,
+            volatility_pct,
+            value_at_risk_usd,
+            score_change_7d: None,
+            score_change_30d: None,
             recommendations,
             last_updated: block_timestamp(),
         }
     }
 
-    fn calculate_diversification_score(&self, portfolio: &[PortfolioAsset]) -> u8 {
-        let num_assets = portfolio.len();
-        let num_chains = portfolio.iter().map(|a| &a.chain).collect::<std::collections::HashSet<_>>().len();
-        
-        let asset_diversity = std::cmp::min(num_assets * 10, 50) as u8;
-        let chain_diversity = std::cmp::min(num_chains * 15, 50) as u8;
-        
-        asset_diversity + chain_diversity
-    }
-
     fn calculate_risk_score(&self, portfolio: &[PortfolioAsset]) -> u8 {
-        let mut stable_percentage = 0.0;
+        let mut weighted_safety = 0.0;
         let mut total_value = 0.0;
         
         for asset in portfolio {
             let value: f64 = asset.value_usd.parse().unwrap_or(0.0);
             total_value += value;
-            
-            if asset.token_symbol == "USDC" || asset.token_symbol == "USDT" || asset.token_symbol == "DAI" {
-                stable_percentage += value;
-            }
+            weighted_safety += value * self.asset_safety(&asset.token_symbol);
         }
         
+        // Value-weighted safety of the holdings' risk tiers; higher = lower risk
         if total_value > 0.0 {
-            stable_percentage = (stable_percentage / total_value) * 100.0;
+            (weighted_safety / total_value).min(100.0) as u8
+        } else {
+            30
         }
-        
-        // Higher stable percentage = lower risk = higher score
-        std::cmp::min(stable_percentage as u8 + 30, 100)
     }
 
     fn calculate_concentration_risk(&self, portfolio: &[PortfolioAsset]) -> u8 {
@@ -903,37 +1367,151 @@ impl AIPortfolioRebalancer {
             recommendations.push("Consider cross-chain diversification".to_string());
         }
         
+        for asset in portfolio {
+            if self.asset_info(&asset.token_symbol).depegged {
+                recommendations.push(format!("{} has lost its peg; consider moving to another stablecoin", asset.token_symbol));
+            }
+        }
+        
+        let long_tail_share = self.tier_share(portfolio, "long_tail");
+        if long_tail_share > 20.0 {
+            recommendations.push(format!("{:.0}% of the portfolio is in long-tail assets; consider blue-chip exposure", long_tail_share));
+        }
+        
         recommendations
     }
 
-    fn generate_trades_from_intent(&mut self, intent: &RebalanceIntent) {
-        // Generate trades based on target allocations
-        for allocation in &intent.target_allocations {
-            let trade = Trade {
-                id: 0, // Will be set by create_trade
-                intent_id: intent.id,
-                from_asset: "CURRENT".to_string(),
-                to_asset: allocation.token_symbol.clone(),
-                from_chain: "ethereum".to_string(),
-                to_chain: allocation.chain.clone(),
-                amount: allocation.balance.clone(),
-                expected_output: allocation.value_usd.clone(),
-                actual_output: "0.0".to_string(),
-                status: "pending".to_string(),
-                tx_hash: "".to_string(),
-                timestamp: 0,
-                gas_used: "0.0".to_string(),
+    // Returns the USD value the created trades move
+    fn generate_trades_from_intent(&mut self, intent: &RebalanceIntent) -> f64 {
+        let trades = self.plan_intent_trades(intent);
+        let moved_usd = planned_volume_usd(&trades);
+        for trade in trades {
+            self.internal_create_trade(intent.id, trade);
+        }
+        moved_usd
+    }
+
+    pub(crate) fn plan_intent_trades(&self, intent: &RebalanceIntent) -> Vec<Trade> {
+        let user_id: AccountId = intent.user_id.parse().unwrap();
+        let portfolio = self.user_portfolios.get(&user_id).cloned().unwrap_or_default();
+        plan_trades(intent.id, &portfolio, &intent.target_allocations)
+    }
+}
+
+// Current holding and target for one (symbol, chain) while planning trades
+struct PlannedPosition {
+    symbol: String,
+    chain: String,
+    current_usd: f64,
+    balance: f64,
+    balance_text: String,
+    target_usd: f64,
+}
+
+// Trades that move the current holdings to the target allocations. Each (symbol, chain) held
+// above its target funds the ones held below it, same-chain first. Amounts are in from_asset
+// units; ids, quotes and slippage bounds are filled in when each trade is created.
+pub(crate) fn plan_trades(intent_id: u64, portfolio: &[PortfolioAsset], allocations: &[PortfolioAsset]) -> Vec<Trade> {
+    const MIN_TRADE_USD: f64 = 0.01;
+    let parse = |value: &str| value.parse::<f64>().unwrap_or(0.0);
+
+    let mut positions: Vec<PlannedPosition> = Vec::new();
+    for (asset, is_target) in portfolio.iter().map(|a| (a, false)).chain(allocations.iter().map(|a| (a, true))) {
+        let symbol = asset.token_symbol.to_uppercase();
+        let index = match positions.iter().position(|p| p.symbol == symbol && p.chain == asset.chain) {
+            Some(index) => index,
+            None => {
+                positions.push(PlannedPosition {
+                    symbol,
+                    chain: asset.chain.clone(),
+                    current_usd: 0.0,
+                    balance: 0.0,
+                    balance_text: "0".to_string(),
+                    target_usd: 0.0,
+                });
+                positions.len() - 1
+            }
+        };
+        let position = &mut positions[index];
+        if is_target {
+            position.target_usd += parse(&asset.value_usd);
+        } else {
+            position.current_usd += parse(&asset.value_usd);
+            position.balance += parse(&asset.balance);
+            position.balance_text = if position.balance == parse(&asset.balance) {
+                asset.balance.clone()
+            } else {
+                format!("{:.8}", position.balance)
             };
-            
-            self.create_trade(intent.id, trade);
         }
     }
+
+    // (position, usd left to sell, balance already sold)
+    let mut surpluses: Vec<(usize, f64, f64)> = positions
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.balance > 0.0 && p.current_usd - p.target_usd >= MIN_TRADE_USD)
+        .map(|(index, p)| (index, p.current_usd - p.target_usd, 0.0))
+        .collect();
+    let mut deficits: Vec<(usize, f64)> = positions
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.target_usd - p.current_usd >= MIN_TRADE_USD)
+        .map(|(index, p)| (index, p.target_usd - p.current_usd))
+        .collect();
+
+    let mut trades = Vec::new();
+    for same_chain in [true, false] {
+        for (from_index, surplus, sold) in surpluses.iter_mut() {
+            for (to_index, deficit) in deficits.iter_mut() {
+                let (from, to) = (&positions[*from_index], &positions[*to_index]);
+                if (from.chain == to.chain) != same_chain || *surplus < MIN_TRADE_USD || *deficit < MIN_TRADE_USD {
+                    continue;
+                }
+                let moved = surplus.min(*deficit);
+                *surplus -= moved;
+                *deficit -= moved;
+                // Selling out of a holding uses its exact remaining balance
+                let amount = if from.target_usd < MIN_TRADE_USD && *surplus < MIN_TRADE_USD {
+                    if *sold == 0.0 { from.balance_text.clone() } else { format!("{:.8}", from.balance - *sold) }
+                } else {
+                    let amount = format!("{:.8}", moved * from.balance / from.current_usd);
+                    *sold += amount.parse::<f64>().unwrap_or(0.0);
+                    amount
+                };
+                trades.push(Trade {
+                    id: 0, // Will be set by create_trade
+                    intent_id,
+                    from_asset: from.symbol.clone(),
+                    to_asset: to.symbol.clone(),
+                    from_chain: from.chain.clone(),
+                    to_chain: to.chain.clone(),
+                    amount,
+                    expected_output: format!("{:.2}", moved), // USD until quoted in to_asset units
+                    actual_output: "0.0".to_string(),
+                    status: "pending".to_string(),
+                    tx_hash: "".to_string(),
+                    timestamp: 0,
+                    gas_used: "0.0".to_string(),
+                    min_output: "0".to_string(), // set from current prices by internal_create_trade
+                    max_slippage_bps: 0,
+                });
+            }
+        }
+    }
+    trades
+}
+
+// USD moved by trades from plan_trades, before their outputs are quoted
+pub(crate) fn planned_volume_usd(trades: &[Trade]) -> f64 {
+    trades.iter().map(|trade| trade.expected_output.parse::<f64>().unwrap_or(0.0)).sum()
 }
 
 // Helper struct for AI analysis results
 struct AIAnalysisResult {
     classification: String,
     confidence_score: u8,
+    confidence_factors: Vec<ConfidenceFactor>,
     reasoning: String,
     target_allocations: Vec<PortfolioAsset>,
     estimated_gas_cost: String,
@@ -0,0 +1,322 @@
+use near_sdk::{
+    env, near,
+    serde::{Deserialize, Serialize},
+    borsh::{BorshDeserialize, BorshSerialize},
+    AccountId,
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset};
+
+// Rolling window kept per asset; the oldest point is dropped once full
+pub const MAX_PRICE_POINTS: usize = 60;
+// Fewer returns than this and realized volatility is too noisy to use
+const MIN_RETURNS: usize = 5;
+const NANOS_PER_DAY: f64 = 86_400_000_000_000.0;
+// One-sided 95% quantile of the normal distribution, for parametric value-at-risk
+const VAR_95_Z: f64 = 1.645;
+
+// (timestamp, price) points in time order
+type PriceSeries = Vec<(u64, f64)>;
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PricePoint {
+    pub price_usd: String,
+    pub timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AssetCorrelation {
+    pub asset_a: String,
+    pub asset_b: String,
+    pub correlation: String, // -1.0 to 1.0
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    pub fn get_price_history(&self, asset_symbol: String) -> Vec<PricePoint> {
+        self.price_history.get(&asset_symbol).cloned().unwrap_or_default()
+    }
+
+    // Daily realized volatility in percent, None until enough price updates have been seen
+    pub fn get_asset_volatility(&self, asset_symbol: String) -> Option<String> {
+        self.realized_volatility(&asset_symbol).map(|vol| format!("{:.2}", vol * 100.0))
+    }
+
+    // Pairwise correlations of the user's holdings that have enough price history
+    pub fn get_asset_correlations(&self, user_id: String) -> Vec<AssetCorrelation> {
+        let account_id: AccountId = user_id.parse().unwrap();
+        let portfolio = self.user_portfolios.get(&account_id).cloned().unwrap_or_default();
+        let mut symbols: Vec<&str> = portfolio.iter().map(|asset| asset.token_symbol.as_str()).collect();
+        symbols.sort_unstable();
+        symbols.dedup();
+
+        let mut correlations = Vec::new();
+        for (i, a) in symbols.iter().enumerate() {
+            for b in &symbols[i + 1..] {
+                if let Some(correlation) = self.correlation(a, b) {
+                    correlations.push(AssetCorrelation {
+                        asset_a: a.to_string(),
+                        asset_b: b.to_string(),
+                        correlation: format!("{:.4}", correlation),
+                    });
+                }
+            }
+        }
+        correlations
+    }
+
+    // Price history helpers
+    pub(crate) fn record_price_point(&mut self, asset_symbol: &str, price_usd: &str) {
+        if price_usd.parse::<f64>().map_or(true, |price| price <= 0.0) {
+            return;
+        }
+        let mut history = self.price_history.get(asset_symbol).cloned().unwrap_or_default();
+        let timestamp = env::block_timestamp();
+        // A second update in the same block replaces the first; a zero-length interval has no return
+        if history.last().is_some_and(|last| last.timestamp == timestamp) {
+            history.pop();
+        }
+        history.push(PricePoint {
+            price_usd: price_usd.to_string(),
+            timestamp,
+        });
+        if history.len() > MAX_PRICE_POINTS {
+            history.drain(..history.len() - MAX_PRICE_POINTS);
+        }
+        self.price_history.insert(asset_symbol.to_string(), history);
+    }
+
+    // None if any stored price fails to parse
+    fn price_points(&self, asset_symbol: &str) -> Option<PriceSeries> {
+        let history = self.price_history.get(asset_symbol)?;
+        history
+            .iter()
+            .map(|point| point.price_usd.parse::<f64>().ok().map(|price| (point.timestamp, price)))
+            .collect()
+    }
+
+    // Standard deviation of one-day returns, as a fraction
+    pub(crate) fn realized_volatility(&self, asset_symbol: &str) -> Option<f64> {
+        let returns = daily_returns(&self.price_points(asset_symbol)?);
+        (returns.len() >= MIN_RETURNS).then(|| std_dev(&returns))
+    }
+
+    // Pearson correlation of the two assets' returns over common sampling times; stable pairs
+    // with no movement have no defined correlation
+    pub(crate) fn correlation(&self, a: &str, b: &str) -> Option<f64> {
+        let (prices_a, prices_b) = align_prices(&self.price_points(a)?, &self.price_points(b)?);
+        let (xs, ys) = (daily_returns(&prices_a), daily_returns(&prices_b));
+        if xs.len() < MIN_RETURNS {
+            return None;
+        }
+        let (mean_x, mean_y) = (mean(&xs), mean(&ys));
+        let covariance: f64 = xs.iter().zip(&ys).map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+        let denominator = (xs.iter().map(|x| (x - mean_x).powi(2)).sum::<f64>()
+            * ys.iter().map(|y| (y - mean_y).powi(2)).sum::<f64>())
+        .sqrt();
+        (denominator > 0.0).then(|| (covariance / denominator).clamp(-1.0, 1.0))
+    }
+
+    // Daily portfolio volatility from the holdings' weights, volatilities and correlations.
+    // Assets without price history fall back to the registry's annualized estimate; pairs
+    // without a measured correlation are treated as uncorrelated. None if nothing is known.
+    pub(crate) fn portfolio_volatility(&self, portfolio: &[PortfolioAsset]) -> Option<f64> {
+        let mut positions: Vec<(&str, f64, f64)> = Vec::new(); // symbol, value, daily volatility
+        let mut total_value = 0.0;
+        for asset in portfolio {
+            let value: f64 = asset.value_usd.parse().unwrap_or(0.0);
+            total_value += value;
+            let volatility = self.realized_volatility(&asset.token_symbol).or_else(|| {
+                self.asset_info(&asset.token_symbol)
+                    .volatility_pct
+                    .and_then(|vol| vol.parse::<f64>().ok())
+                    .map(|annual| annual / 100.0 / 365f64.sqrt())
+            });
+            if let Some(volatility) = volatility {
+                positions.push((&asset.token_symbol, value, volatility));
+            }
+        }
+        if positions.is_empty() || total_value <= 0.0 {
+            return None;
+        }
+
+        let mut variance = 0.0;
+        for (i, (symbol_a, value_a, vol_a)) in positions.iter().enumerate() {
+            for (symbol_b, value_b, vol_b) in &positions[i..] {
+                let correlation = if symbol_a == symbol_b { 1.0 } else { self.correlation(symbol_a, symbol_b).unwrap_or(0.0) };
+                let term = (value_a / total_value) * (value_b / total_value) * vol_a * vol_b * correlation;
+                variance += if symbol_a == symbol_b { term } else { 2.0 * term };
+            }
+        }
+        Some(variance.max(0.0).sqrt())
+    }
+
+    // (daily volatility %, one-day 95% value-at-risk in USD), both as display strings
+    pub(crate) fn portfolio_risk_estimates(&self, portfolio: &[PortfolioAsset]) -> (Option<String>, Option<String>) {
+        let total_value: f64 = portfolio.iter().map(|asset| asset.value_usd.parse::<f64>().unwrap_or(0.0)).sum();
+        match self.portfolio_volatility(portfolio) {
+            Some(volatility) => (
+                Some(format!("{:.2}", volatility * 100.0)),
+                Some(format!("{:.2}", value_at_risk(total_value, volatility))),
+            ),
+            None => (None, None),
+        }
+    }
+}
+
+// Log returns between consecutive points, each scaled to one day by the square root of its
+// own interval so irregular update spacing does not bias the estimate
+fn daily_returns(points: &[(u64, f64)]) -> Vec<f64> {
+    points
+        .windows(2)
+        .filter(|pair| pair[1].0 > pair[0].0 && pair[0].1 > 0.0 && pair[1].1 > 0.0)
+        .map(|pair| {
+            let days = (pair[1].0 - pair[0].0) as f64 / NANOS_PER_DAY;
+            (pair[1].1 / pair[0].1).ln() / days.sqrt()
+        })
+        .collect()
+}
+
+// Samples both series at refresh times: once both assets have a price, and then each time both
+// have updated since the previous sample. Each sample carries the last observation of each
+// asset at that time forward, so updates landing in different blocks still pair up.
+fn align_prices(a: &[(u64, f64)], b: &[(u64, f64)]) -> (PriceSeries, PriceSeries) {
+    let (mut aligned_a, mut aligned_b) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let time = a[i].0.max(b[j].0);
+        // Last observation at or before the refresh time
+        while i + 1 < a.len() && a[i + 1].0 <= time {
+            i += 1;
+        }
+        while j + 1 < b.len() && b[j + 1].0 <= time {
+            j += 1;
+        }
+        aligned_a.push((time, a[i].1));
+        aligned_b.push((time, b[j].1));
+        i += 1;
+        j += 1;
+    }
+    (aligned_a, aligned_b)
+}
+
+// One-day 95% value-at-risk under log-normal returns; never more than the portfolio is worth
+fn value_at_risk(total_value: f64, daily_volatility: f64) -> f64 {
+    total_value * (1.0 - (-VAR_95_Z * daily_volatility).exp())
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+fn std_dev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(values);
+    (values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::*, AssetMetadata};
+    use near_sdk::testing_env;
+
+    const NANOS_PER_SEC: u64 = 1_000_000_000;
+    const DAY_SECS: u64 = 86_400;
+
+    fn update_at(contract: &mut AIPortfolioRebalancer, asset: &str, price: f64, seconds: u64) {
+        testing_env!(context(OWNER).block_timestamp(seconds * NANOS_PER_SEC).build());
+        contract.update_asset_price(asset.to_string(), format!("{:.6}", price));
+    }
+
+    // Prices whose daily returns alternate +2% / -2% over irregular intervals (in days)
+    fn irregular_path(contract: &mut AIPortfolioRebalancer, asset: &str) {
+        let mut price = 100.0;
+        let mut day = 0;
+        update_at(contract, asset, price, day);
+        for (i, gap) in [1u64, 4, 1, 9, 1, 4].iter().enumerate() {
+            let daily_return = if i % 2 == 0 { 0.02 } else { -0.02 };
+            price *= (daily_return * (*gap as f64).sqrt()).exp();
+            day += gap;
+            update_at(contract, asset, price, day * DAY_SECS);
+        }
+    }
+
+    fn holding(symbol: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: "1".to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    #[test]
+    fn volatility_scales_each_return_by_its_own_interval() {
+        let mut contract = setup();
+        irregular_path(&mut contract, "ETH");
+        // Sample deviation of six returns of +-2%: 0.02 * sqrt(6 / 5)
+        assert_eq!(contract.get_asset_volatility("ETH".to_string()).as_deref(), Some("2.19"));
+        assert_eq!(contract.get_asset_volatility("BTC".to_string()), None);
+    }
+
+    #[test]
+    fn updates_in_the_same_block_replace_the_last_point() {
+        let mut contract = setup();
+        update_at(&mut contract, "ETH", 2800.0, 10);
+        update_at(&mut contract, "ETH", 2810.0, 10);
+        update_at(&mut contract, "ETH", 2820.0, 11);
+        let history = contract.get_price_history("ETH".to_string());
+        let points: Vec<(&str, u64)> = history.iter().map(|p| (p.price_usd.as_str(), p.timestamp / NANOS_PER_SEC)).collect();
+        assert_eq!(points, [("2810.000000", 10), ("2820.000000", 11)]);
+    }
+
+    #[test]
+    fn correlation_pairs_returns_by_time() {
+        let mut contract = setup();
+        // ETH updates daily; BTC every other day, a second later, moving with ETH's two-day return.
+        // Paired by index the series would be misaligned.
+        let eth = [2800.0, 2900.0, 2850.0, 2700.0, 2750.0, 2950.0, 2900.0, 3000.0, 2800.0, 2850.0, 2950.0, 2900.0, 3100.0];
+        for (day, price) in eth.iter().enumerate() {
+            let at = day as u64 * DAY_SECS;
+            update_at(&mut contract, "ETH", *price, at);
+            if day % 2 == 0 {
+                update_at(&mut contract, "BTC", price * 15.0, at + 1);
+                update_at(&mut contract, "USDT", 3000.0 / price, at + 1);
+            }
+        }
+        assert!((contract.correlation("ETH", "BTC").unwrap() - 1.0).abs() < 1e-9);
+        assert!((contract.correlation("BTC", "USDT").unwrap() + 1.0).abs() < 1e-9);
+        // Sampled at refresh times, ETH's returns are its two-day moves
+        assert!((contract.correlation("ETH", "USDT").unwrap() + 1.0).abs() < 1e-9);
+        assert_eq!(contract.correlation("ETH", "NEAR"), None);
+    }
+
+    #[test]
+    fn value_at_risk_is_log_normal_and_bounded_by_the_portfolio() {
+        let mut contract = setup();
+        for (symbol, volatility_pct) in [("ETH", "65"), ("COMP", "5000")] {
+            contract.set_asset_metadata(AssetMetadata {
+                symbol: symbol.to_string(),
+                risk_tier: "blue_chip".to_string(),
+                volatility_pct: Some(volatility_pct.to_string()),
+                depegged: false,
+            });
+        }
+
+        // 3.40% a day: 1000 * (1 - e^(-1.645 * 0.0340))
+        let (volatility, var) = contract.portfolio_risk_estimates(&[holding("ETH", "1000")]);
+        assert_eq!((volatility.as_deref(), var.as_deref()), (Some("3.40"), Some("54.43")));
+
+        // 261.7% a day would put a linear VaR at 4.3x the portfolio
+        let (_, var) = contract.portfolio_risk_estimates(&[holding("COMP", "1000")]);
+        assert_eq!(var.as_deref(), Some("986.50"));
+    }
+}
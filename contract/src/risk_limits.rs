@@ -0,0 +1,482 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+use std::collections::HashMap;
+
+use crate::{planned_volume_usd, AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset, RebalanceIntent, Trade};
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+// USD values and percentages are decimal strings; None means no limit
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RiskLimits {
+    pub max_trade_usd: Option<String>,
+    pub max_daily_volume_usd: Option<String>,
+    pub max_asset_allocation_pct: Option<String>,
+    pub max_chain_allocation_pct: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DailyVolume {
+    pub day: u64, // days since the unix epoch
+    pub volume_usd: String,
+}
+
+// A worker's analysis of an intent, checked against risk limits before the user reviews it
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntentAnalysis {
+    pub classification: String,
+    pub confidence_score: u8,
+    pub ai_analysis: String,
+    pub target_allocations: Vec<PortfolioAsset>,
+    pub estimated_gas_cost: String,
+    pub execution_steps: Vec<String>,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Risk limit configuration
+    #[payable]
+    pub fn set_user_risk_limits(&mut self, limits: RiskLimits) {
+        let user_id = env::predecessor_account_id();
+        validate_risk_limits(&limits);
+        let initial_storage = self.begin_storage_charge(&user_id);
+        self.user_risk_limits.insert(user_id.clone(), limits);
+        self.settle_storage_charge(&user_id, initial_storage);
+        log!("Risk limits updated for {}", user_id);
+    }
+
+    pub fn set_protocol_risk_limits(&mut self, limits: RiskLimits) {
+        self.require_bootstrap_owner();
+        validate_risk_limits(&limits);
+        self.protocol_risk_limits = limits;
+    }
+
+    pub fn get_user_risk_limits(&self, user_id: String) -> Option<RiskLimits> {
+        let user_id: AccountId = user_id.parse().unwrap();
+        self.user_risk_limits.get(&user_id).cloned()
+    }
+
+    pub fn get_protocol_risk_limits(&self) -> RiskLimits {
+        self.protocol_risk_limits.clone()
+    }
+
+    // The stricter of the user's and the protocol's limit applies to each field
+    pub fn get_effective_risk_limits(&self, user_id: String) -> RiskLimits {
+        let user_id: AccountId = user_id.parse().unwrap();
+        self.effective_risk_limits(&user_id)
+    }
+
+    pub fn get_daily_rebalance_volume(&self, user_id: String) -> String {
+        let user_id: AccountId = user_id.parse().unwrap();
+        format!("{:.2}", self.daily_volume_usd(&user_id))
+    }
+
+    // Worker-submitted analysis; replaces the simulated analysis in analyze_intent.
+    // The intent's owner pays for the stored analysis.
+    pub fn submit_intent_analysis(&mut self, intent_id: u64, analysis: IntentAnalysis) -> String {
+        self.require_trusted_worker();
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        require!(intent.status == "analyzing", "Intent is not awaiting analysis");
+        self.require_not_expired(&intent);
+        let user_id: AccountId = intent.user_id.parse().unwrap();
+        let initial_storage = self.begin_storage_charge(&user_id);
+
+        intent.classification = analysis.classification;
+        intent.confidence_score = analysis.confidence_score;
+        intent.confidence_factors = Vec::new();
+        intent.ai_analysis = analysis.ai_analysis;
+        intent.target_allocations = analysis.target_allocations;
+        intent.estimated_gas_cost = analysis.estimated_gas_cost;
+        intent.execution_steps = analysis.execution_steps;
+        self.complete_intent_analysis(&mut intent);
+
+        let status = intent.status.clone();
+        self.intents.insert(intent_id, intent);
+        self.settle_storage_charge(&user_id, initial_storage);
+        log!("Worker analysis stored for intent {}: {}", intent_id, status);
+        status
+    }
+
+    // Risk limit helpers
+    // Sends the analyzed plan to the user for approval, or rejects it with the reason when its
+    // allocations or planned trades break a limit or it lowers health below the user's floor
+    pub(crate) fn complete_intent_analysis(&self, intent: &mut RebalanceIntent) {
+        let user_id: AccountId = intent.user_id.parse().unwrap();
+        let (health_before, health_after) = self.project_health(&user_id, &intent.target_allocations);
+        let checked = self
+            .check_allocation_limits(&user_id, &intent.target_allocations)
+            .and_then(|_| self.check_trade_sizes(&user_id, &self.plan_intent_trades(intent)))
+            .and_then(|_| self.check_health_floor(&user_id, &health_before, &health_after));
+        intent.health_before = Some(health_before);
+        intent.health_after = Some(health_after);
+        match checked {
+            Ok(()) => {
+                intent.status = "awaiting_approval".to_string();
+                intent.rejection_reason = None;
+            }
+            Err(reason) => {
+                intent.status = "rejected".to_string();
+                intent.rejection_reason = Some(reason);
+            }
+        }
+    }
+
+    // Full check before execution: allocation caps, schedule caps, trade size and daily volume.
+    // Trade size and volume come from the trades execution would create, as in check_schedule_limits.
+    pub(crate) fn check_execution_limits(&self, intent: &RebalanceIntent) -> Result<(), String> {
+        let user_id: AccountId = intent.user_id.parse().unwrap();
+        self.check_allocation_limits(&user_id, &intent.target_allocations)?;
+        self.check_schedule_limits(intent)?;
+
+        let trades = self.plan_intent_trades(intent);
+        self.check_trade_sizes(&user_id, &trades)?;
+        let limits = self.effective_risk_limits(&user_id);
+        let volume = planned_volume_usd(&trades);
+        if let Some(max_daily) = parse_limit(&limits.max_daily_volume_usd) {
+            let today = self.daily_volume_usd(&user_id);
+            if today + volume > max_daily {
+                return Err(format!(
+                    "Rebalance volume ${:.2} plus ${:.2} already traded today exceeds the daily cap of ${:.2}",
+                    volume, today, max_daily
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // `moved_usd` is the planned volume of the trades an execution created
+    pub(crate) fn record_rebalance_volume(&mut self, user_id: &AccountId, moved_usd: f64) {
+        let today = block_timestamp() / NANOS_PER_DAY;
+        let volume = self.daily_volume_usd(user_id) + moved_usd;
+        self.daily_rebalance_volume.insert(user_id.clone(), DailyVolume {
+            day: today,
+            volume_usd: format!("{:.2}", volume),
+        });
+    }
+
+    // Trades from plan_intent_trades carry their USD value in expected_output until quoted
+    pub(crate) fn check_trade_sizes(&self, user_id: &AccountId, trades: &[Trade]) -> Result<(), String> {
+        let Some(max_trade) = parse_limit(&self.effective_risk_limits(user_id).max_trade_usd) else {
+            return Ok(());
+        };
+        for trade in trades {
+            let value: f64 = trade.expected_output.parse().unwrap_or(0.0);
+            if value > max_trade {
+                return Err(format!(
+                    "Trade from {} into {} worth ${:.2} exceeds the max trade size of ${:.2}",
+                    trade.from_asset, trade.to_asset, value, max_trade
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Shares are each allocation's value_usd over the plan's total; the percentage field is
+    // reported by the analysis and not trusted
+    pub(crate) fn check_allocation_limits(&self, user_id: &AccountId, allocations: &[PortfolioAsset]) -> Result<(), String> {
+        let limits = self.effective_risk_limits(user_id);
+        let mut values = Vec::with_capacity(allocations.len());
+        for allocation in allocations {
+            match allocation.value_usd.parse::<f64>() {
+                Ok(value) if value.is_finite() && value >= 0.0 => values.push(value),
+                _ => {
+                    return Err(format!(
+                        "Allocation to {} has an invalid USD value: {}",
+                        allocation.token_symbol, allocation.value_usd
+                    ))
+                }
+            }
+        }
+        let total: f64 = values.iter().sum();
+        let share = |value: f64| if total > 0.0 { value / total * 100.0 } else { 0.0 };
+
+        if let Some(max_asset) = parse_limit(&limits.max_asset_allocation_pct) {
+            let mut by_asset: HashMap<&str, f64> = HashMap::new();
+            for (allocation, value) in allocations.iter().zip(&values) {
+                *by_asset.entry(allocation.token_symbol.as_str()).or_default() += share(*value);
+            }
+            if let Some((asset, pct)) = by_asset.into_iter().find(|(_, pct)| *pct > max_asset) {
+                return Err(format!("Allocation of {:.2}% to {} exceeds the per-asset cap of {:.2}%", pct, asset, max_asset));
+            }
+        }
+
+        if let Some(max_chain) = parse_limit(&limits.max_chain_allocation_pct) {
+            let mut by_chain: HashMap<&str, f64> = HashMap::new();
+            for (allocation, value) in allocations.iter().zip(&values) {
+                *by_chain.entry(allocation.chain.as_str()).or_default() += share(*value);
+            }
+            if let Some((chain, pct)) = by_chain.into_iter().find(|(_, pct)| *pct > max_chain) {
+                return Err(format!("Allocation of {:.2}% on {} exceeds the per-chain cap of {:.2}%", pct, chain, max_chain));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn effective_risk_limits(&self, user_id: &AccountId) -> RiskLimits {
+        let user = self.user_risk_limits.get(user_id).cloned().unwrap_or_default();
+        let protocol = &self.protocol_risk_limits;
+        RiskLimits {
+            max_trade_usd: stricter(&user.max_trade_usd, &protocol.max_trade_usd),
+            max_daily_volume_usd: stricter(&user.max_daily_volume_usd, &protocol.max_daily_volume_usd),
+            max_asset_allocation_pct: stricter(&user.max_asset_allocation_pct, &protocol.max_asset_allocation_pct),
+            max_chain_allocation_pct: stricter(&user.max_chain_allocation_pct, &protocol.max_chain_allocation_pct),
+        }
+    }
+
+    fn daily_volume_usd(&self, user_id: &AccountId) -> f64 {
+        let today = block_timestamp() / NANOS_PER_DAY;
+        self.daily_rebalance_volume
+            .get(user_id)
+            .filter(|daily| daily.day == today)
+            .map_or(0.0, |daily| daily.volume_usd.parse().unwrap_or(0.0))
+    }
+}
+
+pub(crate) fn validate_risk_limits(limits: &RiskLimits) {
+    for value in [
+        &limits.max_trade_usd,
+        &limits.max_daily_volume_usd,
+        &limits.max_asset_allocation_pct,
+        &limits.max_chain_allocation_pct,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let parsed: f64 = value.parse().unwrap_or_else(|_| env::panic_str("Invalid risk limit value"));
+        require!(parsed > 0.0, "Risk limits must be positive");
+    }
+    for pct in [&limits.max_asset_allocation_pct, &limits.max_chain_allocation_pct].into_iter().flatten() {
+        require!(pct.parse::<f64>().unwrap() <= 100.0, "Allocation caps must be at most 100%");
+    }
+}
+
+fn parse_limit(limit: &Option<String>) -> Option<f64> {
+    limit.as_ref().and_then(|value| value.parse().ok())
+}
+
+fn stricter(a: &Option<String>, b: &Option<String>) -> Option<String> {
+    match (parse_limit(a), parse_limit(b)) {
+        (Some(x), Some(y)) => Some(if x <= y { a.clone().unwrap() } else { b.clone().unwrap() }),
+        (Some(_), None) => a.clone(),
+        (None, _) => b.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    const USER: &str = "alice.near";
+    const WORKER: &str = "worker.near";
+
+    fn allocation(symbol: &str, chain: &str, value_usd: &str, percentage: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: "0".to_string(),
+            chain: chain.to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: percentage.to_string(),
+        }
+    }
+
+    fn limits(max_trade: Option<&str>, max_daily: Option<&str>, max_asset: Option<&str>, max_chain: Option<&str>) -> RiskLimits {
+        RiskLimits {
+            max_trade_usd: max_trade.map(str::to_string),
+            max_daily_volume_usd: max_daily.map(str::to_string),
+            max_asset_allocation_pct: max_asset.map(str::to_string),
+            max_chain_allocation_pct: max_chain.map(str::to_string),
+        }
+    }
+
+    // Alice holds $1500 of USDC on ethereum, so plans buying anything else trade out of it
+    fn limits_setup() -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(USER);
+        let mut usdc = allocation("USDC", "ethereum", "1500", "100");
+        usdc.balance = "1500".to_string();
+        contract.set_user_portfolio(vec![usdc]);
+        call_as(OWNER);
+        contract.register_worker("codehash".to_string(), String::new(), "checksum".to_string(), Some(WORKER.to_string()));
+        contract
+    }
+
+    // Submits an intent, stores a worker analysis with `allocations` and returns the intent id
+    fn analyzed_intent(contract: &mut AIPortfolioRebalancer, allocations: Vec<PortfolioAsset>) -> u64 {
+        call_as(USER);
+        let intent_id = contract.submit_intent("rebalance".to_string(), None);
+        call_as(WORKER);
+        contract.submit_intent_analysis(
+            intent_id,
+            IntentAnalysis {
+                classification: "structured".to_string(),
+                confidence_score: 90,
+                ai_analysis: "worker plan".to_string(),
+                target_allocations: allocations,
+                estimated_gas_cost: "1.00".to_string(),
+                execution_steps: Vec::new(),
+            },
+        );
+        intent_id
+    }
+
+    fn approve_and_execute(contract: &mut AIPortfolioRebalancer, intent_id: u64) {
+        call_as(USER);
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash);
+        let _ = contract.execute_rebalance(intent_id);
+    }
+
+    fn status_and_reason(contract: &AIPortfolioRebalancer, intent_id: u64) -> (String, Option<String>) {
+        let intent = contract.get_intent(intent_id).unwrap();
+        (intent.status, intent.rejection_reason)
+    }
+
+    #[test]
+    fn the_stricter_limit_applies_per_field() {
+        let mut contract = limits_setup();
+        call_as(OWNER);
+        contract.set_protocol_risk_limits(limits(Some("1000"), None, Some("60"), None));
+        call_as(USER);
+        contract.set_user_risk_limits(limits(Some("5000"), Some("2000"), Some("40"), None));
+
+        let effective = contract.get_effective_risk_limits(USER.to_string());
+        assert_eq!(effective.max_trade_usd.as_deref(), Some("1000"));
+        assert_eq!(effective.max_daily_volume_usd.as_deref(), Some("2000"));
+        assert_eq!(effective.max_asset_allocation_pct.as_deref(), Some("40"));
+        assert_eq!(effective.max_chain_allocation_pct, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Allocation caps must be at most 100%")]
+    fn allocation_caps_above_100_are_rejected() {
+        validate_risk_limits(&limits(None, None, Some("120"), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Risk limits must be positive")]
+    fn non_positive_limits_are_rejected() {
+        validate_risk_limits(&limits(Some("0"), None, None, None));
+    }
+
+    #[test]
+    fn worker_analyses_over_an_asset_or_chain_cap_are_rejected_with_the_reason() {
+        let mut contract = limits_setup();
+        call_as(USER);
+        contract.set_user_risk_limits(limits(None, None, Some("50"), Some("70")));
+
+        let over_asset = analyzed_intent(
+            &mut contract,
+            vec![allocation("ETH", "ethereum", "600", "60"), allocation("USDC", "near", "400", "40")],
+        );
+        assert_eq!(
+            status_and_reason(&contract, over_asset),
+            ("rejected".to_string(), Some("Allocation of 60.00% to ETH exceeds the per-asset cap of 50.00%".to_string()))
+        );
+
+        let over_chain = analyzed_intent(
+            &mut contract,
+            vec![allocation("ETH", "ethereum", "400", "40"), allocation("USDC", "ethereum", "400", "40"), allocation("NEAR", "near", "200", "20")],
+        );
+        assert_eq!(
+            status_and_reason(&contract, over_chain),
+            ("rejected".to_string(), Some("Allocation of 80.00% on ethereum exceeds the per-chain cap of 70.00%".to_string()))
+        );
+
+        let within = analyzed_intent(
+            &mut contract,
+            vec![allocation("ETH", "ethereum", "500", "50"), allocation("NEAR", "near", "500", "50")],
+        );
+        assert_eq!(status_and_reason(&contract, within), ("awaiting_approval".to_string(), None));
+    }
+
+    #[test]
+    fn oversized_trades_are_rejected_at_execution() {
+        let mut contract = limits_setup();
+        let intent_id = analyzed_intent(&mut contract, vec![allocation("ETH", "ethereum", "1500", "100")]);
+        call_as(OWNER);
+        contract.set_protocol_risk_limits(limits(Some("1000"), None, None, None));
+
+        approve_and_execute(&mut contract, intent_id);
+        assert_eq!(
+            status_and_reason(&contract, intent_id),
+            ("rejected".to_string(), Some("Trade from USDC into ETH worth $1500.00 exceeds the max trade size of $1000.00".to_string()))
+        );
+    }
+
+    #[test]
+    fn oversized_trades_are_rejected_at_analysis() {
+        let mut contract = limits_setup();
+        call_as(USER);
+        contract.set_user_risk_limits(limits(Some("1000"), None, None, None));
+
+        // Keeping $1000 of USDC leaves a $500 trade, whatever the target's own size
+        let within = analyzed_intent(
+            &mut contract,
+            vec![allocation("USDC", "ethereum", "1000", "0"), allocation("ETH", "ethereum", "500", "0")],
+        );
+        assert_eq!(status_and_reason(&contract, within), ("awaiting_approval".to_string(), None));
+
+        let oversized = analyzed_intent(&mut contract, vec![allocation("ETH", "ethereum", "1200", "100")]);
+        assert_eq!(
+            status_and_reason(&contract, oversized),
+            ("rejected".to_string(), Some("Trade from USDC into ETH worth $1200.00 exceeds the max trade size of $1000.00".to_string()))
+        );
+    }
+
+    #[test]
+    fn allocation_shares_come_from_values_not_reported_percentages() {
+        let mut contract = limits_setup();
+        call_as(USER);
+        contract.set_user_risk_limits(limits(None, None, Some("50"), None));
+
+        let understated = analyzed_intent(
+            &mut contract,
+            vec![allocation("ETH", "ethereum", "900", "10"), allocation("USDC", "ethereum", "100", "90")],
+        );
+        assert_eq!(
+            status_and_reason(&contract, understated),
+            ("rejected".to_string(), Some("Allocation of 90.00% to ETH exceeds the per-asset cap of 50.00%".to_string()))
+        );
+
+        let unparsable = analyzed_intent(&mut contract, vec![allocation("ETH", "ethereum", "lots", "10")]);
+        assert_eq!(
+            status_and_reason(&contract, unparsable),
+            ("rejected".to_string(), Some("Allocation to ETH has an invalid USD value: lots".to_string()))
+        );
+    }
+
+    #[test]
+    fn daily_volume_accumulates_until_the_cap() {
+        let mut contract = limits_setup();
+        call_as(USER);
+        contract.set_user_risk_limits(limits(None, Some("1000"), None, None));
+
+        let first = analyzed_intent(&mut contract, vec![allocation("ETH", "ethereum", "700", "100")]);
+        approve_and_execute(&mut contract, first);
+        assert_eq!(contract.get_intent(first).unwrap().status, "executing");
+        assert_eq!(contract.get_daily_rebalance_volume(USER.to_string()), "700.00");
+
+        let second = analyzed_intent(&mut contract, vec![allocation("NEAR", "near", "400", "100")]);
+        approve_and_execute(&mut contract, second);
+        assert_eq!(
+            status_and_reason(&contract, second),
+            (
+                "rejected".to_string(),
+                Some("Rebalance volume $400.00 plus $700.00 already traded today exceeds the daily cap of $1000.00".to_string())
+            )
+        );
+    }
+}
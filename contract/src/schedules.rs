@@ -0,0 +1,594 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{planned_volume_usd, AIPortfolioRebalancer, AIPortfolioRebalancerExt, RebalanceIntent};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const MIN_INTERVAL_SEC: u64 = 60 * 60;
+// Upper bound on the bytes one spawned intent adds, on top of its text
+const SPAWNED_INTENT_BYTES: u64 = 1_000;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScheduleInput {
+    pub intent_text: String, // e.g. "rebalance to balanced" or "buy $100 of ETH"
+    pub interval_sec: u64, // runs at start_at + n * interval_sec
+    pub start_at: u64, // nanoseconds; 0 starts now
+    pub end_at: Option<u64>,
+    pub max_executions: Option<u64>,
+    pub amount_usd_per_run: Option<String>, // caps what each run's intent may move
+    pub budget_usd: Option<String>, // caps the total moved by executed runs
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Schedule {
+    pub id: u64,
+    pub user_id: String,
+    pub intent_text: String,
+    pub interval_sec: u64,
+    pub start_at: u64,
+    pub end_at: Option<u64>,
+    pub max_executions: Option<u64>,
+    pub amount_usd_per_run: Option<String>,
+    pub budget_usd: Option<String>,
+    pub spent_usd: String, // moved by executed runs
+    pub executions: u64, // runs spawned
+    pub next_run_at: u64,
+    pub queued_run_at: Option<u64>, // run time of this schedule's entry in schedule_queue, if any
+    pub last_intent_id: Option<u64>,
+    pub status: String, // "active", "paused", "completed", "cancelled"
+    pub status_reason: Option<String>,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UpcomingRun {
+    pub schedule_id: u64,
+    pub run_at: u64,
+    pub intent_text: String,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Schedule management
+    #[payable]
+    pub fn create_schedule(&mut self, schedule: ScheduleInput) -> u64 {
+        let user_id = env::predecessor_account_id();
+        require!(!schedule.intent_text.trim().is_empty(), "Schedule needs an intent text");
+        require!(schedule.interval_sec >= MIN_INTERVAL_SEC, "Interval must be at least one hour");
+        require!(schedule.interval_sec.checked_mul(NANOS_PER_SEC).is_some(), "Interval is too long");
+        require!(schedule.max_executions != Some(0), "max_executions must be positive");
+        for value in [&schedule.amount_usd_per_run, &schedule.budget_usd].into_iter().flatten() {
+            require!(value.parse::<f64>().is_ok_and(|v| v > 0.0), "USD amounts must be positive numbers");
+        }
+        require!(
+            schedule.budget_usd.is_none() || schedule.amount_usd_per_run.is_some(),
+            "A budget needs amount_usd_per_run"
+        );
+
+        let now = block_timestamp();
+        let start_at = if schedule.start_at == 0 { now } else { schedule.start_at };
+        if let Some(end_at) = schedule.end_at {
+            require!(end_at > start_at, "end_at must be after start_at");
+        }
+
+        let initial_storage = self.begin_storage_charge(&user_id);
+        if !self.users.contains(&user_id) {
+            self.internal_register_user(user_id.clone());
+        }
+
+        let schedule_id = self.next_schedule_id;
+        self.next_schedule_id += 1;
+        let mut new_schedule = Schedule {
+            id: schedule_id,
+            user_id: user_id.to_string(),
+            intent_text: schedule.intent_text,
+            interval_sec: schedule.interval_sec,
+            start_at,
+            end_at: schedule.end_at,
+            max_executions: schedule.max_executions,
+            amount_usd_per_run: schedule.amount_usd_per_run,
+            budget_usd: schedule.budget_usd,
+            spent_usd: "0.0".to_string(),
+            executions: 0,
+            next_run_at: start_at,
+            queued_run_at: None,
+            last_intent_id: None,
+            status: "active".to_string(),
+            status_reason: None,
+            created_at: now,
+        };
+        self.queue_schedule_run(&mut new_schedule);
+        self.schedules.insert(schedule_id, new_schedule);
+        let mut ids = self.user_schedules.get(&user_id).cloned().unwrap_or_default();
+        ids.push(schedule_id);
+        self.user_schedules.insert(user_id.clone(), ids);
+        self.settle_storage_charge(&user_id, initial_storage);
+
+        log!("Schedule {} created by {}", schedule_id, user_id);
+        schedule_id
+    }
+
+    #[payable]
+    pub fn set_schedule_paused(&mut self, schedule_id: u64, paused: bool) {
+        let mut schedule = self.owned_schedule(schedule_id);
+        require!(schedule.status == "active" || schedule.status == "paused", "Schedule has ended");
+        let user_id: AccountId = schedule.user_id.parse().unwrap();
+        let initial_storage = self.begin_storage_charge(&user_id);
+        schedule.status = if paused { "paused" } else { "active" }.to_string();
+        schedule.status_reason = None;
+        if !paused {
+            // Resume from the next slot instead of replaying missed runs
+            schedule.next_run_at = next_slot(&schedule, block_timestamp());
+            self.queue_schedule_run(&mut schedule);
+        }
+        self.schedules.insert(schedule_id, schedule);
+        self.settle_storage_charge(&user_id, initial_storage);
+    }
+
+    pub fn cancel_schedule(&mut self, schedule_id: u64) {
+        let mut schedule = self.owned_schedule(schedule_id);
+        require!(schedule.status == "active" || schedule.status == "paused", "Schedule has ended");
+        schedule.status = "cancelled".to_string();
+        self.schedules.insert(schedule_id, schedule);
+        log!("Schedule {} cancelled", schedule_id);
+    }
+
+    // Spawns an intent for each due schedule, soonest first, taking at most `limit` entries off
+    // the run queue; returns the new intent ids
+    pub fn run_due_schedules(&mut self, limit: u64) -> Vec<u64> {
+        self.require_trusted_worker();
+        let now = block_timestamp();
+
+        let mut spawned = Vec::new();
+        for _ in 0..limit {
+            let Some((run_at, schedule_id)) = self.pop_due_schedule_run(now) else {
+                break;
+            };
+            // Each schedule has at most one entry; ones for schedules that ended are dropped here
+            let mut schedule = match self.schedules.get(&schedule_id) {
+                Some(schedule) if schedule.queued_run_at == Some(run_at) => schedule.clone(),
+                _ => continue,
+            };
+            schedule.queued_run_at = None;
+            if schedule.status != "active" {
+                self.schedules.insert(schedule.id, schedule);
+                continue;
+            }
+            if schedule.next_run_at > now {
+                // Resumed after a pause: the entry moves on to the schedule's next slot
+                self.queue_schedule_run(&mut schedule);
+                self.schedules.insert(schedule.id, schedule);
+                continue;
+            }
+            if let Some(reason) = self.schedule_end_reason(&schedule, now) {
+                schedule.status = "completed".to_string();
+                schedule.status_reason = Some(reason);
+                self.schedules.insert(schedule.id, schedule);
+                continue;
+            }
+
+            let user_id: AccountId = schedule.user_id.parse().unwrap();
+            let estimate = SPAWNED_INTENT_BYTES + schedule.intent_text.len() as u64;
+            if !self.can_cover_storage(&user_id, estimate) {
+                schedule.status = "paused".to_string();
+                schedule.status_reason = Some("Insufficient storage balance to create the intent".to_string());
+                self.schedules.insert(schedule.id, schedule);
+                continue;
+            }
+
+            // Each run's intent expires before the next run is due. Earlier entries' writes are
+            // flushed first so this user is charged only for their own run.
+            self.flush_collections();
+            let initial_storage = env::storage_usage();
+            let valid_for_sec = schedule.interval_sec.min(self.intent_ttl_sec);
+            let intent_id = self.internal_submit_intent(user_id.clone(), schedule.intent_text.clone(), Some(valid_for_sec));
+            let mut intent = self.intents.get(&intent_id).cloned().unwrap();
+            intent.schedule_id = Some(schedule.id);
+            intent.amount_usd = schedule.amount_usd_per_run.clone();
+            self.intents.insert(intent_id, intent);
+
+            // Spend is counted when the intent executes, in record_schedule_spend
+            schedule.executions += 1;
+            schedule.last_intent_id = Some(intent_id);
+            schedule.next_run_at = next_slot(&schedule, now);
+            if let Some(reason) = self.schedule_end_reason(&schedule, schedule.next_run_at) {
+                schedule.status = "completed".to_string();
+                schedule.status_reason = Some(reason);
+            } else {
+                self.queue_schedule_run(&mut schedule);
+            }
+            self.schedules.insert(schedule.id, schedule);
+            self.settle_storage_charge(&user_id, initial_storage);
+            spawned.push(intent_id);
+        }
+
+        log!("Spawned {} scheduled intents", spawned.len());
+        spawned
+    }
+
+    // Schedule views
+    pub fn get_schedule(&self, schedule_id: u64) -> Option<Schedule> {
+        self.schedules.get(&schedule_id).cloned()
+    }
+
+    pub fn get_user_schedules(&self, user_id: String) -> Vec<Schedule> {
+        let user_id: AccountId = user_id.parse().unwrap();
+        self.user_schedules
+            .get(&user_id)
+            .map(|ids| ids.iter().filter_map(|id| self.schedules.get(id).cloned()).collect())
+            .unwrap_or_default()
+    }
+
+    // The next `count` runs across the user's active schedules, soonest first
+    pub fn get_upcoming_runs(&self, user_id: String, count: u64) -> Vec<UpcomingRun> {
+        let now = block_timestamp();
+        let mut runs: Vec<UpcomingRun> = Vec::new();
+        for mut schedule in self.get_user_schedules(user_id).into_iter().filter(|s| s.status == "active") {
+            for _ in 0..count {
+                if self.schedule_end_reason(&schedule, schedule.next_run_at).is_some() {
+                    break;
+                }
+                runs.push(UpcomingRun {
+                    schedule_id: schedule.id,
+                    run_at: schedule.next_run_at.max(now),
+                    intent_text: schedule.intent_text.clone(),
+                });
+                schedule.executions += 1;
+                if let Some(amount) = &schedule.amount_usd_per_run {
+                    let spent = schedule.spent_usd.parse::<f64>().unwrap_or(0.0) + amount.parse::<f64>().unwrap_or(0.0);
+                    schedule.spent_usd = format!("{:.2}", spent);
+                }
+                schedule.next_run_at = next_slot(&schedule, schedule.next_run_at.max(now));
+            }
+        }
+        runs.sort_by_key(|run| run.run_at);
+        runs.truncate(count as usize);
+        runs
+    }
+
+    // Schedule helpers
+    // A scheduled intent may move at most its run's amount, and only what is left of the budget
+    pub(crate) fn check_schedule_limits(&self, intent: &RebalanceIntent) -> Result<(), String> {
+        let Some(schedule) = intent.schedule_id.and_then(|id| self.schedules.get(&id)) else {
+            return Ok(());
+        };
+        let moved = planned_volume_usd(&self.plan_intent_trades(intent));
+        if let Some(max_run) = intent.amount_usd.as_ref().and_then(|amount| amount.parse::<f64>().ok()) {
+            if moved > max_run {
+                return Err(format!(
+                    "Scheduled run moves ${:.2}, above the schedule's ${:.2} per run",
+                    moved, max_run
+                ));
+            }
+        }
+        if let Some(budget) = schedule.budget_usd.as_ref().and_then(|budget| budget.parse::<f64>().ok()) {
+            let spent: f64 = schedule.spent_usd.parse().unwrap_or(0.0);
+            if spent + moved > budget {
+                return Err(format!(
+                    "Scheduled run moves ${:.2}, but only ${:.2} of the schedule's budget is left",
+                    moved,
+                    (budget - spent).max(0.0)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_schedule_spend(&mut self, schedule_id: u64, moved_usd: f64) {
+        if let Some(mut schedule) = self.schedules.get(&schedule_id).cloned() {
+            let spent = schedule.spent_usd.parse::<f64>().unwrap_or(0.0) + moved_usd;
+            schedule.spent_usd = format!("{:.2}", spent);
+            self.schedules.insert(schedule_id, schedule);
+        }
+    }
+
+    // Queues the schedule's next run unless it already has an entry, which run_due_schedules
+    // moves to next_run_at when popped
+    fn queue_schedule_run(&mut self, schedule: &mut Schedule) {
+        if schedule.queued_run_at.is_none() {
+            self.push_schedule_run(schedule.next_run_at, schedule.id);
+            schedule.queued_run_at = Some(schedule.next_run_at);
+        }
+    }
+
+    // schedule_queue is a binary min-heap ordered by run time
+    fn push_schedule_run(&mut self, run_at: u64, schedule_id: u64) {
+        self.schedule_queue.push((run_at, schedule_id));
+        let mut index = self.schedule_queue.len() - 1;
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if self.schedule_queue[parent] <= self.schedule_queue[index] {
+                break;
+            }
+            self.swap_queue_entries(parent, index);
+            index = parent;
+        }
+    }
+
+    fn pop_due_schedule_run(&mut self, now: u64) -> Option<(u64, u64)> {
+        let first = *self.schedule_queue.get(0)?;
+        if first.0 > now {
+            return None;
+        }
+        let last = self.schedule_queue.pop().unwrap();
+        let len = self.schedule_queue.len();
+        if len == 0 {
+            return Some(first);
+        }
+        self.schedule_queue.set(0, last);
+        let mut index = 0;
+        loop {
+            let mut smallest = index;
+            for child in [2 * index + 1, 2 * index + 2] {
+                if child < len && self.schedule_queue[child] < self.schedule_queue[smallest] {
+                    smallest = child;
+                }
+            }
+            if smallest == index {
+                break;
+            }
+            self.swap_queue_entries(index, smallest);
+            index = smallest;
+        }
+        Some(first)
+    }
+
+    fn swap_queue_entries(&mut self, a: u32, b: u32) {
+        let entry = self.schedule_queue[a];
+        let other = self.schedule_queue.replace(b, entry);
+        self.schedule_queue.set(a, other);
+    }
+
+    fn owned_schedule(&self, schedule_id: u64) -> Schedule {
+        let schedule = self.schedules.get(&schedule_id).cloned().expect("Schedule not found");
+        require!(
+            env::predecessor_account_id().as_str() == schedule.user_id,
+            "Only the schedule owner can change it"
+        );
+        schedule
+    }
+
+    // Why a run at `run_at` should not happen, if it should not
+    fn schedule_end_reason(&self, schedule: &Schedule, run_at: u64) -> Option<String> {
+        if schedule.end_at.is_some_and(|end_at| run_at > end_at) {
+            return Some("End time reached".to_string());
+        }
+        if schedule.max_executions.is_some_and(|max| schedule.executions >= max) {
+            return Some("Maximum executions reached".to_string());
+        }
+        if let (Some(budget), Some(amount)) = (&schedule.budget_usd, &schedule.amount_usd_per_run) {
+            let spent: f64 = schedule.spent_usd.parse().unwrap_or(0.0);
+            if spent + amount.parse::<f64>().unwrap_or(0.0) > budget.parse::<f64>().unwrap_or(0.0) {
+                return Some("Budget exhausted".to_string());
+            }
+        }
+        None
+    }
+}
+
+// First slot start_at + n * interval strictly after `after`; slots past u64::MAX never come
+fn next_slot(schedule: &Schedule, after: u64) -> u64 {
+    let interval = schedule.interval_sec.saturating_mul(NANOS_PER_SEC);
+    if after < schedule.start_at {
+        return schedule.start_at;
+    }
+    let elapsed_slots = (after - schedule.start_at) / interval + 1;
+    elapsed_slots
+        .checked_mul(interval)
+        .and_then(|offset| schedule.start_at.checked_add(offset))
+        .unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{IntentAnalysis, PortfolioAsset};
+    use near_sdk::testing_env;
+
+    const USER: &str = "alice.near";
+    const WORKER: &str = "worker.near";
+    const HOUR: u64 = 60 * 60;
+
+    fn at(seconds: u64, predecessor: &str) {
+        testing_env!(context(predecessor).block_timestamp(seconds * NANOS_PER_SEC).build());
+    }
+
+    fn asset(symbol: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: value_usd.to_string(),
+            chain: "ethereum".to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    fn schedule_input(interval_sec: u64, start_at: u64) -> ScheduleInput {
+        ScheduleInput {
+            intent_text: "buy $100 of ETH".to_string(),
+            interval_sec,
+            start_at,
+            end_at: None,
+            max_executions: None,
+            amount_usd_per_run: None,
+            budget_usd: None,
+        }
+    }
+
+    fn schedules_setup() -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(OWNER);
+        contract.register_worker("codehash".to_string(), String::new(), "checksum".to_string(), Some(WORKER.to_string()));
+        call_as(USER);
+        contract.set_user_portfolio(vec![asset("USDC", "1000")]);
+        contract
+    }
+
+    // Worker analysis moving `moved` USD of USDC into ETH, then the user approves and executes
+    fn execute_run(contract: &mut AIPortfolioRebalancer, intent_id: u64, moved: u64, now: u64) -> String {
+        at(now, WORKER);
+        contract.submit_intent_analysis(
+            intent_id,
+            IntentAnalysis {
+                classification: "structured".to_string(),
+                confidence_score: 90,
+                ai_analysis: "buy ETH".to_string(),
+                target_allocations: vec![asset("USDC", &(1000 - moved).to_string()), asset("ETH", &moved.to_string())],
+                estimated_gas_cost: "1.00".to_string(),
+                execution_steps: Vec::new(),
+            },
+        );
+        at(now, USER);
+        let plan_hash = contract.get_intent_plan_hash(intent_id);
+        contract.approve_intent(intent_id, plan_hash);
+        let _ = contract.execute_rebalance(intent_id);
+        contract.get_intent(intent_id).unwrap().status
+    }
+
+    #[test]
+    fn due_schedules_run_soonest_first_and_skip_stale_entries() {
+        let mut contract = schedules_setup();
+        at(1, USER);
+        let late = contract.create_schedule(schedule_input(HOUR, 300 * NANOS_PER_SEC));
+        let early = contract.create_schedule(schedule_input(HOUR, 100 * NANOS_PER_SEC));
+        let cancelled = contract.create_schedule(schedule_input(HOUR, 50 * NANOS_PER_SEC));
+        let future = contract.create_schedule(schedule_input(HOUR, 10 * HOUR * NANOS_PER_SEC));
+        contract.cancel_schedule(cancelled);
+
+        // Only one live entry fits in the first call; the cancelled one is dropped on the way
+        at(400, WORKER);
+        let first = contract.run_due_schedules(2);
+        assert_eq!(first.len(), 1);
+        assert_eq!(contract.get_schedule(early).unwrap().last_intent_id, first.first().copied());
+
+        let second = contract.run_due_schedules(10);
+        assert_eq!(second.len(), 1);
+        assert_eq!(contract.get_schedule(late).unwrap().last_intent_id, second.first().copied());
+        assert_eq!(contract.get_schedule(future).unwrap().executions, 0);
+
+        // Each ran schedule is queued again for its next slot
+        assert!(contract.run_due_schedules(10).is_empty());
+        at(100 + HOUR, WORKER);
+        assert_eq!(contract.run_due_schedules(10).len(), 1);
+        assert_eq!(contract.get_schedule(early).unwrap().executions, 2);
+    }
+
+    #[test]
+    fn resumed_schedules_are_queued_again() {
+        let mut contract = schedules_setup();
+        at(1, USER);
+        let schedule_id = contract.create_schedule(schedule_input(HOUR, 0));
+        contract.set_schedule_paused(schedule_id, true);
+        at(10, WORKER);
+        assert!(contract.run_due_schedules(10).is_empty());
+
+        at(20, USER);
+        contract.set_schedule_paused(schedule_id, false);
+        at(1 + HOUR, WORKER);
+        assert_eq!(contract.run_due_schedules(10).len(), 1);
+    }
+
+    #[test]
+    fn each_schedule_keeps_one_queue_entry() {
+        let mut contract = schedules_setup();
+        at(1, USER);
+        let schedule_id = contract.create_schedule(schedule_input(HOUR, 0));
+        for _ in 0..3 {
+            contract.set_schedule_paused(schedule_id, true);
+            contract.set_schedule_paused(schedule_id, false);
+        }
+        assert_eq!(contract.schedule_queue.len(), 1);
+
+        // The entry from before the pause moves on to the resumed schedule's next slot
+        at(30, WORKER);
+        assert!(contract.run_due_schedules(10).is_empty());
+        assert_eq!(contract.get_schedule(schedule_id).unwrap().queued_run_at, Some((1 + HOUR) * NANOS_PER_SEC));
+        at(1 + HOUR, WORKER);
+        assert_eq!(contract.run_due_schedules(10).len(), 1);
+        assert_eq!(contract.schedule_queue.len(), 1);
+
+        // A cancelled schedule's entry is dropped when it comes due
+        at(2 + HOUR, USER);
+        contract.cancel_schedule(schedule_id);
+        at(1 + 2 * HOUR, WORKER);
+        assert!(contract.run_due_schedules(10).is_empty());
+        assert_eq!(contract.schedule_queue.len(), 0);
+        assert_eq!(contract.get_schedule(schedule_id).unwrap().queued_run_at, None);
+    }
+
+    #[test]
+    fn runs_are_capped_at_their_amount_and_spend_counts_at_execution() {
+        let mut contract = schedules_setup();
+        at(1, USER);
+        let mut input = schedule_input(HOUR, 0);
+        input.amount_usd_per_run = Some("100".to_string());
+        input.budget_usd = Some("250".to_string());
+        let schedule_id = contract.create_schedule(input);
+
+        at(10, WORKER);
+        let first = contract.run_due_schedules(1)[0];
+        assert_eq!(contract.get_intent(first).unwrap().amount_usd.as_deref(), Some("100"));
+        // Spawning alone spends nothing
+        assert_eq!(contract.get_schedule(schedule_id).unwrap().spent_usd, "0.0");
+
+        assert_eq!(execute_run(&mut contract, first, 150, 20), "rejected");
+        assert_eq!(
+            contract.get_intent(first).unwrap().rejection_reason.as_deref(),
+            Some("Scheduled run moves $150.00, above the schedule's $100.00 per run")
+        );
+        assert_eq!(contract.get_schedule(schedule_id).unwrap().spent_usd, "0.0");
+
+        at(1 + HOUR, WORKER);
+        let second = contract.run_due_schedules(1)[0];
+        assert_eq!(execute_run(&mut contract, second, 100, 10 + HOUR), "executing");
+        assert_eq!(contract.get_schedule(schedule_id).unwrap().spent_usd, "100.00");
+    }
+
+    #[test]
+    fn executions_stop_at_the_budget() {
+        let mut contract = schedules_setup();
+        at(1, USER);
+        let mut input = schedule_input(HOUR, 0);
+        input.amount_usd_per_run = Some("100".to_string());
+        input.budget_usd = Some("150".to_string());
+        let schedule_id = contract.create_schedule(input);
+
+        // Both runs spawn because nothing has been spent yet; only one fits the budget
+        at(10, WORKER);
+        let first = contract.run_due_schedules(1)[0];
+        at(1 + HOUR, WORKER);
+        let second = contract.run_due_schedules(1)[0];
+        assert_eq!(execute_run(&mut contract, first, 100, 10 + HOUR), "executing");
+        contract.set_user_portfolio(vec![asset("USDC", "1000")]);
+        assert_eq!(execute_run(&mut contract, second, 100, 20 + HOUR), "rejected");
+        assert_eq!(
+            contract.get_intent(second).unwrap().rejection_reason.as_deref(),
+            Some("Scheduled run moves $100.00, but only $50.00 of the schedule's budget is left")
+        );
+        assert_eq!(contract.get_schedule(schedule_id).unwrap().spent_usd, "100.00");
+
+        // The next spawn sees the exhausted budget and completes the schedule
+        at(1 + 2 * HOUR, WORKER);
+        assert!(contract.run_due_schedules(1).is_empty());
+        assert_eq!(contract.get_schedule(schedule_id).unwrap().status, "completed");
+    }
+
+    #[test]
+    #[should_panic(expected = "Interval is too long")]
+    fn intervals_that_overflow_nanoseconds_are_rejected() {
+        let mut contract = schedules_setup();
+        contract.create_schedule(schedule_input(u64::MAX / 10, 0));
+    }
+}
@@ -0,0 +1,173 @@
+use near_sdk::{
+    assert_one_yocto, env, ext_contract,
+    json_types::U128,
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId, Gas, PromiseOrValue, PromiseResult,
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt};
+
+pub const SHARE_DECIMALS: u8 = 18;
+const FT_ON_TRANSFER_GAS: Gas = Gas::from_tgas(30);
+const FT_RESOLVE_TRANSFER_GAS: Gas = Gas::from_tgas(10);
+
+#[allow(dead_code)]
+#[ext_contract(ext_ft_receiver)]
+pub trait FungibleTokenReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128>;
+}
+
+// NEP-148 metadata for pool shares
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FungibleTokenMetadata {
+    pub spec: String,
+    pub name: String,
+    pub symbol: String,
+    pub icon: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<String>,
+    pub decimals: u8,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // NEP-141 core
+    #[payable]
+    pub fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        self.internal_share_transfer(&sender_id, &receiver_id, amount.0, memo);
+    }
+
+    #[payable]
+    pub fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        let sender_id = env::predecessor_account_id();
+        self.internal_share_transfer(&sender_id, &receiver_id, amount.0, memo);
+
+        ext_ft_receiver::ext(receiver_id.clone())
+            .with_static_gas(FT_ON_TRANSFER_GAS)
+            .ft_on_transfer(sender_id.clone(), amount, msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(FT_RESOLVE_TRANSFER_GAS)
+                    .ft_resolve_transfer(sender_id, receiver_id, amount),
+            )
+            .into()
+    }
+
+    #[private]
+    pub fn ft_resolve_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> U128 {
+        let amount = amount.0;
+        let unused_amount = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map_or(amount, |unused| std::cmp::min(amount, unused.0)),
+            PromiseResult::Failed => amount,
+        };
+
+        if unused_amount == 0 {
+            return U128(amount);
+        }
+
+        // Refund what the receiver did not use, bounded by what it still holds
+        let receiver_balance = self.share_balances.get(&receiver_id).copied().unwrap_or(0);
+        let refund = std::cmp::min(receiver_balance, unused_amount);
+        if refund > 0 {
+            self.share_balances.insert(receiver_id.clone(), receiver_balance - refund);
+            if let Some(sender_balance) = self.share_balances.get(&sender_id).copied() {
+                self.share_balances.insert(sender_id.clone(), sender_balance + refund);
+                emit_ft_event("ft_transfer", &receiver_id, Some(&sender_id), refund, Some("refund"));
+            } else {
+                // Sender unregistered in the meantime; the refund is burned
+                self.share_total_supply -= refund;
+                emit_ft_event("ft_burn", &receiver_id, None, refund, Some("refund"));
+            }
+        }
+
+        U128(amount - refund)
+    }
+
+    pub fn ft_total_supply(&self) -> U128 {
+        U128(self.share_total_supply)
+    }
+
+    pub fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        U128(self.share_balances.get(&account_id).copied().unwrap_or(0))
+    }
+
+    pub fn ft_metadata(&self) -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: "ft-1.0.0".to_string(),
+            name: "AI Portfolio Pool Share".to_string(),
+            symbol: "AIPS".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: SHARE_DECIMALS,
+        }
+    }
+
+    // Share token helpers
+    pub(crate) fn internal_share_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128, memo: Option<String>) {
+        require!(sender_id != receiver_id, "Sender and receiver should be different");
+        require!(amount > 0, "The amount should be a positive number");
+
+        let sender_balance = self.share_balances.get(sender_id).copied().expect("Sender is not registered");
+        let receiver_balance = self.share_balances.get(receiver_id).copied().expect("Receiver is not registered");
+        require!(sender_balance >= amount, "The account doesn't have enough balance");
+
+        self.share_balances.insert(sender_id.clone(), sender_balance - amount);
+        self.share_balances.insert(receiver_id.clone(), receiver_balance + amount);
+        emit_ft_event("ft_transfer", sender_id, Some(receiver_id), amount, memo.as_deref());
+    }
+
+    pub(crate) fn internal_mint_shares(&mut self, account_id: &AccountId, amount: u128, memo: &str) {
+        let balance = self.share_balances.get(account_id).copied().expect("Account is not registered for pool shares");
+        self.share_balances.insert(account_id.clone(), balance + amount);
+        self.share_total_supply += amount;
+        emit_ft_event("ft_mint", account_id, None, amount, Some(memo));
+    }
+
+    pub(crate) fn internal_burn_shares(&mut self, account_id: &AccountId, amount: u128, memo: &str) {
+        let balance = self.share_balances.get(account_id).copied().unwrap_or(0);
+        require!(balance >= amount, "Insufficient pool shares");
+        self.share_balances.insert(account_id.clone(), balance - amount);
+        self.share_total_supply -= amount;
+        emit_ft_event("ft_burn", account_id, None, amount, Some(memo));
+    }
+}
+
+// NEP-297 event for share mint, burn and transfer
+pub(crate) fn emit_ft_event(event: &str, account_id: &AccountId, counterparty: Option<&AccountId>, amount: u128, memo: Option<&str>) {
+    let mut data = match (event, counterparty) {
+        ("ft_transfer", Some(receiver_id)) => near_sdk::serde_json::json!({
+            "old_owner_id": account_id,
+            "new_owner_id": receiver_id,
+            "amount": amount.to_string(),
+        }),
+        _ => near_sdk::serde_json::json!({
+            "owner_id": account_id,
+            "amount": amount.to_string(),
+        }),
+    };
+    if let Some(memo) = memo {
+        data["memo"] = memo.into();
+    }
+
+    let event = near_sdk::serde_json::json!({
+        "standard": "nep141",
+        "version": "1.0.0",
+        "event": event,
+        "data": [data],
+    });
+    log!("EVENT_JSON:{}", event);
+}
@@ -0,0 +1,181 @@
+use near_sdk::{
+    near,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+use schemars::JsonSchema;
+
+use crate::{
+    plan_trades, AIPortfolioRebalancer, AIPortfolioRebalancerExt, ConfidenceFactor, PortfolioAsset, PortfolioHealth,
+    Trade, DEFAULT_MAX_SLIPPAGE_BPS,
+};
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct IntentSimulation {
+    pub classification: String,
+    pub confidence_score: u8,
+    pub confidence_factors: Vec<ConfidenceFactor>,
+    pub ai_analysis: String,
+    pub target_allocations: Vec<PortfolioAsset>,
+    pub execution_steps: Vec<String>,
+    pub estimated_gas_cost: String,
+    pub current_health: PortfolioHealth,
+    pub projected_health: PortfolioHealth,
+    pub trades: Vec<Trade>, // quoted at current prices; ids are 0 until created
+    pub rejection_reason: Option<String>, // set when the plan would break a risk limit or the health floor
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Dry run of submit_intent + analyze_intent + execute_rebalance; writes nothing
+    pub fn simulate_intent(&self, user_id: String, intent_text: String) -> IntentSimulation {
+        let account_id: AccountId = user_id.parse().unwrap();
+        let portfolio = self.user_portfolios.get(&account_id).cloned().unwrap_or_default();
+        let preferences = self.user_preferences.get(&account_id);
+        let max_slippage_bps = preferences.map_or(DEFAULT_MAX_SLIPPAGE_BPS, |prefs| prefs.max_slippage_bps);
+
+        let analysis = self.perform_ai_analysis(&intent_text, &portfolio, preferences);
+        let planned = plan_trades(0, &portfolio, &analysis.target_allocations);
+        let trade_size_check = self.check_trade_sizes(&account_id, &planned);
+        let trades = planned
+            .into_iter()
+            .map(|mut trade| {
+                self.quote_trade(&mut trade, max_slippage_bps);
+                trade
+            })
+            .collect();
+
+        let (current_health, projected_health) = self.project_health(&account_id, &analysis.target_allocations);
+        let rejection_reason = self
+            .check_allocation_limits(&account_id, &analysis.target_allocations)
+            .and(trade_size_check)
+            .and_then(|_| self.check_health_floor(&account_id, &current_health, &projected_health))
+            .err();
+
+        IntentSimulation {
+            classification: analysis.classification,
+            confidence_score: analysis.confidence_score,
+            confidence_factors: analysis.confidence_factors,
+            ai_analysis: analysis.reasoning,
+            current_health,
+            projected_health,
+            rejection_reason,
+            target_allocations: analysis.target_allocations,
+            execution_steps: analysis.execution_steps,
+            estimated_gas_cost: analysis.estimated_gas_cost,
+            trades,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use crate::RiskLimits;
+
+    const USER: &str = "alice.near";
+
+    fn asset(symbol: &str, balance: &str, chain: &str, value_usd: &str) -> PortfolioAsset {
+        PortfolioAsset {
+            token_symbol: symbol.to_string(),
+            token_address: symbol.to_lowercase(),
+            balance: balance.to_string(),
+            chain: chain.to_string(),
+            value_usd: value_usd.to_string(),
+            percentage: "0".to_string(),
+        }
+    }
+
+    fn legs(trades: &[Trade]) -> Vec<(&str, &str, &str, &str, &str)> {
+        trades
+            .iter()
+            .map(|t| (t.from_asset.as_str(), t.from_chain.as_str(), t.to_asset.as_str(), t.to_chain.as_str(), t.amount.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn trades_sell_real_holdings_into_the_targets() {
+        let portfolio = [
+            asset("ETH", "1.0", "ethereum", "2800.0"),
+            asset("USDC", "2000.0", "ethereum", "2000.0"),
+            asset("BTC", "0.0285714", "ethereum", "1200.0"),
+        ];
+        let targets = [asset("ETH", "", "ethereum", "2800.00"), asset("USDC", "", "ethereum", "3200.00")];
+
+        let trades = plan_trades(7, &portfolio, &targets);
+        assert_eq!(legs(&trades), vec![("BTC", "ethereum", "USDC", "ethereum", "0.0285714")]);
+        assert_eq!(trades[0].intent_id, 7);
+        assert_eq!(trades[0].expected_output, "1200.00");
+    }
+
+    #[test]
+    fn surpluses_fund_same_chain_targets_first() {
+        let portfolio = [asset("ETH", "2.0", "ethereum", "4000.0"), asset("NEAR", "1000.0", "near", "2500.0")];
+        let targets = [
+            asset("ETH", "", "ethereum", "1000.00"),
+            asset("USDC", "", "near", "1000.00"),
+            asset("USDC", "", "ethereum", "1000.00"),
+            asset("NEAR", "", "near", "3500.00"),
+        ];
+
+        let trades = plan_trades(0, &portfolio, &targets);
+        assert_eq!(
+            legs(&trades),
+            vec![
+                ("ETH", "ethereum", "USDC", "ethereum", "0.50000000"),
+                ("ETH", "ethereum", "NEAR", "near", "0.50000000"),
+                ("ETH", "ethereum", "USDC", "near", "0.50000000"),
+            ]
+        );
+        let moved: Vec<&str> = trades.iter().map(|t| t.expected_output.as_str()).collect();
+        assert_eq!(moved, vec!["1000.00", "1000.00", "1000.00"]);
+    }
+
+    #[test]
+    fn balanced_portfolios_need_no_trades() {
+        let portfolio = [asset("ETH", "1.0", "ethereum", "2800.0")];
+        assert!(plan_trades(0, &portfolio, &[asset("eth", "", "ethereum", "2800.00")]).is_empty());
+    }
+
+    fn simulation_setup() -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(USER);
+        contract.set_user_portfolio(vec![asset("ETH", "1.0", "ethereum", "3000.0"), asset("USDC", "1000.0", "ethereum", "1000.0")]);
+        contract
+    }
+
+    #[test]
+    fn simulation_matches_analysis_without_writing_anything() {
+        let contract = simulation_setup();
+        let next_intent_id = contract.next_intent_id;
+        let simulation = contract.simulate_intent(USER.to_string(), "move 50% of ETH to USDC".to_string());
+
+        assert_eq!(contract.next_intent_id, next_intent_id);
+        assert!(contract.get_user_intents(USER.to_string()).is_empty());
+        assert_eq!(simulation.rejection_reason, None);
+        assert_eq!(legs(&simulation.trades), vec![("ETH", "ethereum", "USDC", "ethereum", "0.50000000")]);
+        // Trades are quoted like created ones: output in USDC units with a slippage floor
+        assert_ne!(simulation.trades[0].min_output, "0");
+        assert!(simulation.projected_health.diversification_score >= simulation.current_health.diversification_score);
+    }
+
+    #[test]
+    fn simulation_reports_the_limit_execution_would_hit() {
+        let mut contract = simulation_setup();
+        contract.set_user_risk_limits(RiskLimits {
+            max_trade_usd: Some("1000".to_string()),
+            max_daily_volume_usd: None,
+            max_asset_allocation_pct: None,
+            max_chain_allocation_pct: None,
+        });
+        let simulation = contract.simulate_intent(USER.to_string(), "move 50% of ETH to USDC".to_string());
+        assert_eq!(
+            simulation.rejection_reason.as_deref(),
+            Some("Trade from ETH into USDC worth $1500.00 exceeds the max trade size of $1000.00")
+        );
+        assert_eq!(simulation.trades.len(), 1);
+    }
+}
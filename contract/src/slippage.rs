@@ -0,0 +1,246 @@
+use near_sdk::{
+    env, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, RebalanceIntent, Trade};
+
+pub const DEFAULT_MAX_SLIPPAGE_BPS: u16 = 100;
+// Statuses whose output has been recorded in the slippage stats
+pub(crate) const TERMINAL_TRADE_STATUSES: [&str; 3] = ["confirmed", "failed", "flagged"];
+
+pub(crate) fn default_max_slippage_bps() -> u16 {
+    DEFAULT_MAX_SLIPPAGE_BPS
+}
+
+// Realized slippage per asset or chain; positive bps means worse than quoted
+#[derive(BorshSerialize, BorshDeserialize, Clone, Default)]
+pub struct SlippageStats {
+    pub trades: u64,
+    pub violations: u64,
+    pub total_slippage_bps: i64,
+    pub worst_slippage_bps: i64,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SlippageReport {
+    pub key: String, // "asset:<symbol>" or "chain:<name>"
+    pub trades: u64,
+    pub violations: u64,
+    pub average_slippage_bps: String,
+    pub worst_slippage_bps: i64,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Overrides the user's preference for one intent's trades
+    pub fn set_intent_max_slippage(&mut self, intent_id: u64, max_slippage_bps: u16) {
+        require!(max_slippage_bps <= 10_000, "Slippage must be at most 10000 bps");
+        let mut intent = self.intents.get(&intent_id).cloned().expect("Intent not found");
+        require!(
+            env::predecessor_account_id().as_str() == intent.user_id,
+            "Only the intent creator can set its slippage"
+        );
+        require!(
+            intent.status != "executing" && intent.status != "completed",
+            "Intent trades have already been created"
+        );
+        intent.max_slippage_bps = Some(max_slippage_bps);
+        // The slippage bound is part of the approved plan, so a ready intent needs approving again
+        if intent.status == "ready" {
+            intent.status = "awaiting_approval".to_string();
+            intent.approved_plan_hash = None;
+        }
+        self.intents.insert(intent_id, intent);
+    }
+
+    // Confirmed reports below min_output wait here until the owner accepts or rejects them
+    pub fn review_flagged_trade(&mut self, trade_id: u64, accept: bool) -> String {
+        self.require_owner();
+        require!(self.flagged_trades.remove(&trade_id), "Trade is not flagged for review");
+        let mut trade = self.trades.get(&trade_id).cloned().unwrap();
+        trade.status = if accept { "confirmed" } else { "failed" }.to_string();
+        self.trades.insert(trade_id, trade);
+        format!("Trade {} marked {}", trade_id, if accept { "confirmed" } else { "failed" })
+    }
+
+    // Slippage views
+    pub fn get_flagged_trades(&self) -> Vec<Trade> {
+        self.flagged_trades
+            .iter()
+            .filter_map(|trade_id| self.trades.get(trade_id).cloned())
+            .collect()
+    }
+
+    pub fn get_asset_slippage_stats(&self, asset: String) -> SlippageReport {
+        self.slippage_report(format!("asset:{}", asset))
+    }
+
+    pub fn get_chain_slippage_stats(&self, chain: String) -> SlippageReport {
+        self.slippage_report(format!("chain:{}", chain))
+    }
+
+    pub fn get_slippage_stats(&self) -> Vec<SlippageReport> {
+        self.slippage_stats.keys().map(|key| self.slippage_report(key.clone())).collect()
+    }
+
+    // Slippage helpers
+    pub(crate) fn effective_max_slippage_bps(&self, intent_id: u64) -> u16 {
+        self.intents
+            .get(&intent_id)
+            .map_or(DEFAULT_MAX_SLIPPAGE_BPS, |intent| self.intent_max_slippage_bps(intent))
+    }
+
+    // The intent's override, else its owner's preference
+    pub(crate) fn intent_max_slippage_bps(&self, intent: &RebalanceIntent) -> u16 {
+        if let Some(bps) = intent.max_slippage_bps {
+            return bps;
+        }
+        let user_id: AccountId = intent.user_id.parse().unwrap();
+        self.user_preferences
+            .get(&user_id)
+            .map_or(DEFAULT_MAX_SLIPPAGE_BPS, |prefs| prefs.max_slippage_bps)
+    }
+
+    // Quotes expected_output in to_asset units from current prices and derives min_output.
+    // Without a from_asset price the caller's expected_output is taken as a USD value.
+    pub(crate) fn apply_slippage_bounds(&self, trade: &mut Trade) {
+        let max_slippage_bps = self.effective_max_slippage_bps(trade.intent_id);
+        self.quote_trade(trade, max_slippage_bps);
+    }
+
+    pub(crate) fn quote_trade(&self, trade: &mut Trade, max_slippage_bps: u16) {
+        let price = |symbol: &str| self.asset_prices.get(symbol).and_then(|p| p.parse::<f64>().ok()).filter(|p| *p > 0.0);
+
+        let value_usd = match (price(&trade.from_asset), trade.amount.parse::<f64>()) {
+            (Some(from_price), Ok(amount)) => Some(amount * from_price),
+            _ => trade.expected_output.parse::<f64>().ok(),
+        };
+        let expected = match (value_usd, price(&trade.to_asset)) {
+            (Some(value_usd), Some(to_price)) => value_usd / to_price,
+            _ => trade.expected_output.parse::<f64>().unwrap_or(0.0),
+        };
+
+        trade.max_slippage_bps = max_slippage_bps;
+        trade.expected_output = format!("{:.8}", expected);
+        trade.min_output = format!("{:.8}", expected * (10_000 - max_slippage_bps) as f64 / 10_000.0);
+    }
+
+    pub(crate) fn violates_min_output(&self, trade: &Trade, actual_output: &str) -> bool {
+        let min_output: f64 = trade.min_output.parse().unwrap_or(0.0);
+        let actual: f64 = actual_output.parse().unwrap_or(0.0);
+        actual < min_output
+    }
+
+    pub(crate) fn record_trade_slippage(&mut self, trade: &Trade, violation: bool) {
+        let expected: f64 = trade.expected_output.parse().unwrap_or(0.0);
+        if expected <= 0.0 {
+            return;
+        }
+        let actual: f64 = trade.actual_output.parse().unwrap_or(0.0);
+        let slippage_bps = ((expected - actual) / expected * 10_000.0).round() as i64;
+
+        for key in [format!("asset:{}", trade.to_asset), format!("chain:{}", trade.to_chain)] {
+            let mut stats = self.slippage_stats.get(&key).cloned().unwrap_or_default();
+            stats.worst_slippage_bps = if stats.trades == 0 {
+                slippage_bps
+            } else {
+                stats.worst_slippage_bps.max(slippage_bps)
+            };
+            stats.trades += 1;
+            stats.total_slippage_bps += slippage_bps;
+            if violation {
+                stats.violations += 1;
+            }
+            self.slippage_stats.insert(key, stats);
+        }
+    }
+
+    fn slippage_report(&self, key: String) -> SlippageReport {
+        let stats = self.slippage_stats.get(&key).cloned().unwrap_or_default();
+        let average = if stats.trades > 0 {
+            stats.total_slippage_bps as f64 / stats.trades as f64
+        } else {
+            0.0
+        };
+        SlippageReport {
+            key,
+            trades: stats.trades,
+            violations: stats.violations,
+            average_slippage_bps: format!("{:.2}", average),
+            worst_slippage_bps: stats.worst_slippage_bps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    // A pending 1 ETH -> USDC trade quoted at 2800 with a 2772 floor
+    fn trade_setup() -> (AIPortfolioRebalancer, u64) {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        call_as("alice.near");
+        let intent_id = contract.submit_intent("swap 1 eth for usdc".to_string(), None);
+        call_as(OWNER);
+        let trade_id = contract.create_trade(
+            intent_id,
+            Trade {
+                id: 0,
+                intent_id,
+                from_asset: "ETH".to_string(),
+                to_asset: "USDC".to_string(),
+                from_chain: "ethereum".to_string(),
+                to_chain: "ethereum".to_string(),
+                amount: "1".to_string(),
+                expected_output: "0".to_string(),
+                actual_output: "0".to_string(),
+                status: "pending".to_string(),
+                tx_hash: String::new(),
+                timestamp: 0,
+                gas_used: "0".to_string(),
+                min_output: "0".to_string(),
+                max_slippage_bps: 0,
+            },
+        );
+        (contract, trade_id)
+    }
+
+    #[test]
+    fn repeated_confirmation_is_recorded_once() {
+        let (mut contract, trade_id) = trade_setup();
+        contract.update_trade_status(trade_id, "confirmed".to_string(), "0xabc".to_string(), "2790".to_string());
+        let repeat = contract.update_trade_status(trade_id, "confirmed".to_string(), "0xabc".to_string(), "2790".to_string());
+        assert_eq!(repeat, format!("Trade {} is already confirmed", trade_id));
+
+        let stats = contract.get_asset_slippage_stats("USDC".to_string());
+        assert_eq!(stats.trades, 1);
+        assert_eq!(stats.average_slippage_bps, "36.00");
+    }
+
+    #[test]
+    fn repeated_violation_stays_flagged_once() {
+        let (mut contract, trade_id) = trade_setup();
+        contract.update_trade_status(trade_id, "confirmed".to_string(), "0xabc".to_string(), "2700".to_string());
+        contract.update_trade_status(trade_id, "confirmed".to_string(), "0xabc".to_string(), "2700".to_string());
+
+        assert_eq!(contract.get_trade(trade_id).unwrap().status, "flagged");
+        let stats = contract.get_asset_slippage_stats("USDC".to_string());
+        assert_eq!((stats.trades, stats.violations), (1, 1));
+        assert_eq!(contract.get_flagged_trades().len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "is already confirmed")]
+    fn settled_trades_reject_a_different_report() {
+        let (mut contract, trade_id) = trade_setup();
+        contract.update_trade_status(trade_id, "confirmed".to_string(), "0xabc".to_string(), "2790".to_string());
+        contract.update_trade_status(trade_id, "failed".to_string(), "0xabc".to_string(), "0".to_string());
+    }
+}
@@ -0,0 +1,459 @@
+use near_sdk::{
+    assert_one_yocto, env,
+    json_types::U128,
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId, NearToken, Promise,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{emit_ft_event, AIPortfolioRebalancer, AIPortfolioRebalancerExt};
+
+// NEP-145 balances; amounts are yoctoNEAR strings as in the standard
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: String,
+    pub available: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: String,
+    pub max: Option<String>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct StorageAccount {
+    pub deposit: u128,
+    pub bytes_used: u64, // per-user data beyond the registration itself
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // NEP-145 storage management
+    #[payable]
+    pub fn storage_deposit(&mut self, account_id: Option<AccountId>, registration_only: Option<bool>) -> StorageBalance {
+        let amount = env::attached_deposit().as_yoctonear();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        let min_balance = self.storage_minimum_balance();
+
+        if self.storage_accounts.contains_key(&account_id) {
+            if registration_only.unwrap_or(false) {
+                // Already registered: refund the whole deposit
+                if amount > 0 {
+                    Promise::new(env::predecessor_account_id()).transfer(NearToken::from_yoctonear(amount));
+                }
+            } else {
+                let mut account = self.storage_accounts.get(&account_id).unwrap().clone();
+                account.deposit += amount;
+                self.storage_accounts.insert(account_id.clone(), account);
+            }
+        } else {
+            require!(amount >= min_balance, "The attached deposit is less than the minimum storage balance");
+
+            let deposit = if registration_only.unwrap_or(false) {
+                let refund = amount - min_balance;
+                if refund > 0 {
+                    Promise::new(env::predecessor_account_id()).transfer(NearToken::from_yoctonear(refund));
+                }
+                min_balance
+            } else {
+                amount
+            };
+            self.internal_register_storage(&account_id, deposit);
+        }
+
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self.storage_accounts.get(&account_id).expect("Account is not registered").clone();
+        let available = self.storage_available(&account);
+        let amount = amount.map_or(available, |a| a.0);
+        require!(amount <= available, "Requested amount exceeds available storage balance");
+
+        if amount > 0 {
+            account.deposit -= amount;
+            self.storage_accounts.insert(account_id.clone(), account);
+            Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(amount));
+        }
+
+        self.storage_balance_of(account_id).unwrap()
+    }
+
+    // Registered users always store a profile, so unregistering them needs force. Force releases
+    // the profile records and burns any shares; intents, trades and votes stay as history and the
+    // deposit still covering them is kept.
+    #[payable]
+    pub fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = match self.storage_accounts.get(&account_id) {
+            Some(account) => account.clone(),
+            None => return false,
+        };
+        let force = force.unwrap_or(false);
+        require!(
+            account.bytes_used == 0 || force,
+            "Can't unregister an account that still stores user data without force"
+        );
+        require!(
+            self.vault_tokens.keys().all(|token_id| self.vault_balance(&account_id, token_id) == 0),
+            "Withdraw vault balances before unregistering"
+        );
+        require!(
+            self.active_order_counts.get(&account_id).copied().unwrap_or(0) == 0,
+            "Cancel active conditional orders before unregistering"
+        );
+
+        let shares = self.share_balances.get(&account_id).copied().unwrap_or(0);
+        if shares > 0 {
+            require!(force, "Can't unregister an account with a positive share balance without force");
+            self.share_total_supply -= shares;
+            emit_ft_event("ft_burn", &account_id, None, shares, Some("storage_unregister"));
+        }
+
+        if account.bytes_used > 0 {
+            self.flush_collections();
+            let initial_storage = env::storage_usage();
+            self.release_user_records(&account_id);
+            self.flush_collections();
+            account.bytes_used = account.bytes_used.saturating_sub(initial_storage - env::storage_usage());
+        }
+        let refund = account.deposit.saturating_sub(self.storage_cost(account.bytes_used));
+
+        self.share_balances.remove(&account_id);
+        self.storage_accounts.remove(&account_id);
+        if refund > 0 {
+            Promise::new(account_id).transfer(NearToken::from_yoctonear(refund));
+        }
+        true
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        let min = self.storage_minimum_balance();
+        StorageBalanceBounds {
+            min: min.to_string(),
+            max: None,
+        }
+    }
+
+    pub fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.storage_accounts.get(&account_id).map(|account| StorageBalance {
+            total: account.deposit.to_string(),
+            available: self.storage_available(account).to_string(),
+        })
+    }
+
+    pub fn get_storage_bytes_used(&self, account_id: String) -> u64 {
+        let account_id: AccountId = account_id.parse().unwrap();
+        self.storage_accounts.get(&account_id).map_or(0, |account| account.bytes_used)
+    }
+
+    // Storage accounting helpers. Mutating user methods call begin_storage_charge
+    // first and settle_storage_charge last so the caller pays for the bytes they add.
+    pub(crate) fn begin_storage_charge(&mut self, account_id: &AccountId) -> u64 {
+        let amount = env::attached_deposit().as_yoctonear();
+        if amount > 0 {
+            match self.storage_accounts.get(account_id).cloned() {
+                Some(mut account) => {
+                    account.deposit += amount;
+                    self.storage_accounts.insert(account_id.clone(), account);
+                }
+                None => {
+                    require!(
+                        amount >= self.storage_minimum_balance(),
+                        "The attached deposit is less than the minimum storage balance"
+                    );
+                    self.internal_register_storage(account_id, amount);
+                }
+            }
+        }
+
+        self.flush_collections();
+        env::storage_usage()
+    }
+
+    pub(crate) fn settle_storage_charge(&mut self, account_id: &AccountId, initial_storage: u64) {
+        self.flush_collections();
+        let current_storage = env::storage_usage();
+        if current_storage == initial_storage {
+            return;
+        }
+
+        let mut account = match self.storage_accounts.get(account_id) {
+            Some(account) => account.clone(),
+            None if current_storage < initial_storage => return,
+            None => env::panic_str("Register with storage_deposit to pay for stored data"),
+        };
+
+        if current_storage > initial_storage {
+            account.bytes_used += current_storage - initial_storage;
+            let required = self.storage_cost(self.account_registration_bytes + account.bytes_used);
+            require!(
+                account.deposit >= required,
+                format!("Insufficient storage balance: {} yoctoNEAR required", required)
+            );
+        } else {
+            account.bytes_used = account.bytes_used.saturating_sub(initial_storage - current_storage);
+        }
+
+        self.storage_accounts.insert(account_id.clone(), account);
+    }
+
+    // Callbacks that return funds must not panic, so they record the bytes without the balance
+    // check; the call that scheduled them checks can_cover_storage up front
+    pub(crate) fn record_storage_charge(&mut self, account_id: &AccountId, initial_storage: u64) {
+        self.flush_collections();
+        let current_storage = env::storage_usage();
+        if let Some(mut account) = self.storage_accounts.get(account_id).cloned() {
+            account.bytes_used = (account.bytes_used + current_storage).saturating_sub(initial_storage);
+            self.storage_accounts.insert(account_id.clone(), account);
+        }
+    }
+
+    // For work done on a user's behalf by someone else, checked before writing so a batch never panics
+    pub(crate) fn can_cover_storage(&self, account_id: &AccountId, bytes: u64) -> bool {
+        self.storage_accounts
+            .get(account_id)
+            .is_some_and(|account| self.storage_available(account) >= self.storage_cost(bytes))
+    }
+
+    fn release_user_records(&mut self, account_id: &AccountId) {
+        if self.users.remove(account_id) {
+            self.total_users -= 1;
+        }
+        self.user_portfolios.remove(account_id);
+        self.user_preferences.remove(account_id);
+        self.user_health.remove(account_id);
+        self.health_history.remove(account_id);
+        self.user_risk_limits.remove(account_id);
+        self.daily_rebalance_volume.remove(account_id);
+        self.order_triggers.remove(account_id);
+        log!("Released stored records for {}", account_id);
+    }
+
+    fn internal_register_storage(&mut self, account_id: &AccountId, deposit: u128) {
+        self.storage_accounts.insert(account_id.clone(), StorageAccount { deposit, bytes_used: 0 });
+        self.share_balances.insert(account_id.clone(), 0);
+        log!("Storage registered for {}", account_id);
+    }
+
+    fn storage_available(&self, account: &StorageAccount) -> u128 {
+        account
+            .deposit
+            .saturating_sub(self.storage_cost(self.account_registration_bytes + account.bytes_used))
+    }
+
+    fn storage_minimum_balance(&self) -> u128 {
+        self.storage_cost(self.account_registration_bytes)
+    }
+
+    fn storage_cost(&self, bytes: u64) -> u128 {
+        env::storage_byte_cost().as_yoctonear() * bytes as u128
+    }
+
+    // Write out cached entries so env::storage_usage reflects pending changes. Every collection
+    // field is listed, so whoever triggers a write is charged for it; a test below enforces this.
+    pub(crate) fn flush_collections(&mut self) {
+        self.users.flush();
+        self.user_portfolios.flush();
+        self.user_preferences.flush();
+        self.user_health.flush();
+        self.health_history.flush();
+        self.intents.flush();
+        self.user_intents.flush();
+        self.schedules.flush();
+        self.user_schedules.flush();
+        self.schedule_queue.flush();
+        self.trades.flush();
+        self.active_rebalances.flush();
+        self.intent_trades.flush();
+        self.flagged_trades.flush();
+        self.slippage_stats.flush();
+        self.conditional_orders.flush();
+        self.user_orders.flush();
+        self.active_orders_by_asset.flush();
+        self.active_order_counts.flush();
+        self.order_triggers.flush();
+        self.approved_codehashes.flush();
+        self.worker_by_account_id.flush();
+        self.trusted_workers.flush();
+        self.proposals.flush();
+        self.proposal_votes.flush();
+        self.timelock_queue.flush();
+        self.upgrade_code.flush();
+        self.voting_stakes.flush();
+        self.delegations.flush();
+        self.voting_power_checkpoints.flush();
+        self.total_voting_power_checkpoints.flush();
+        self.fee_accounts.flush();
+        self.user_risk_limits.flush();
+        self.daily_rebalance_volume.flush();
+        self.user_daos.flush();
+        self.dao_votes.flush();
+        self.dao_delegates.flush();
+        self.dao_vote_index.flush();
+        self.user_dao_votes.flush();
+        self.proposal_analyses.flush();
+        self.dao_proposal_analyses.flush();
+        self.dao_portfolios.flush();
+        self.used_dao_proposals.flush();
+        self.vault_tokens.flush();
+        self.vault_balances.flush();
+        self.swap_pools.flush();
+        self.pool_holdings.flush();
+        self.share_balances.flush();
+        self.storage_accounts.flush();
+        self.supported_chains.flush();
+        self.supported_assets.flush();
+        self.asset_prices.flush();
+        self.asset_metadata.flush();
+        self.price_history.flush();
+    }
+
+    // Measures the bytes one registration adds, using a maximum-length account id
+    pub(crate) fn measure_account_registration_bytes(&mut self) {
+        let initial_storage = env::storage_usage();
+        let tmp_account_id: AccountId = "a".repeat(64).parse().unwrap();
+
+        self.storage_accounts.insert(tmp_account_id.clone(), StorageAccount { deposit: 0, bytes_used: 0 });
+        self.share_balances.insert(tmp_account_id.clone(), 0);
+        self.storage_accounts.flush();
+        self.share_balances.flush();
+        self.account_registration_bytes = env::storage_usage() - initial_storage;
+
+        self.storage_accounts.remove(&tmp_account_id);
+        self.share_balances.remove(&tmp_account_id);
+        self.storage_accounts.flush();
+        self.share_balances.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{AccountId, NearToken};
+
+    use crate::test_utils::*;
+    use crate::{AIPortfolioRebalancer, IntentAnalysis};
+
+    #[test]
+    fn worker_analysis_is_charged_to_the_intent_owner() {
+        let mut contract = setup();
+        contract.register_worker("codehash".to_string(), String::new(), "checksum".to_string(), Some("worker.near".to_string()));
+        register(&mut contract, "alice.near");
+        call_as("alice.near");
+        let intent_id = contract.submit_intent("rebalance into stablecoins".to_string(), None);
+        let bytes_before = contract.get_storage_bytes_used("alice.near".to_string());
+
+        call_as("worker.near");
+        contract.submit_intent_analysis(intent_id, IntentAnalysis {
+            classification: "conservative".to_string(),
+            confidence_score: 80,
+            ai_analysis: "Move half of the portfolio into stablecoins over two steps".repeat(4),
+            target_allocations: Vec::new(),
+            estimated_gas_cost: "0.01".to_string(),
+            execution_steps: vec!["Sell volatile assets".to_string(), "Buy USDC".to_string()],
+        });
+        assert!(contract.get_storage_bytes_used("alice.near".to_string()) > bytes_before);
+        assert_eq!(contract.get_storage_bytes_used("worker.near".to_string()), 0);
+    }
+
+    #[test]
+    fn worker_health_analysis_is_charged_to_the_account() {
+        let mut contract = setup();
+        contract.register_worker("codehash".to_string(), String::new(), "checksum".to_string(), Some("worker.near".to_string()));
+        register(&mut contract, "alice.near");
+        let bytes_before = contract.get_storage_bytes_used("alice.near".to_string());
+
+        call_as("worker.near");
+        contract.analyze_portfolio_health("alice.near".to_string());
+        assert!(contract.get_storage_bytes_used("alice.near".to_string()) > bytes_before);
+        assert_eq!(contract.get_storage_bytes_used("worker.near".to_string()), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the account itself, the owner or a trusted worker can analyze portfolio health")]
+    fn strangers_cannot_analyze_portfolio_health() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        call_as("bob.near");
+        contract.analyze_portfolio_health("alice.near".to_string());
+    }
+
+    // Collections cache writes until flushed, so one missing from flush_collections makes its bytes free
+    #[test]
+    fn collection_writes_are_charged_to_the_caller() {
+        type Mutation = fn(&mut AIPortfolioRebalancer, &AccountId);
+        let mutations: Vec<(&str, Mutation)> = vec![
+            ("user_intents", |c, a| { c.user_intents.insert(a.clone(), vec![1, 2, 3]); }),
+            ("user_schedules", |c, a| { c.user_schedules.insert(a.clone(), vec![1]); }),
+            ("schedule_queue", |c, _| c.schedule_queue.push((1, 1))),
+            ("active_rebalances", |c, _| { c.active_rebalances.insert(7); }),
+            ("intent_trades", |c, _| { c.intent_trades.insert(7, vec![1, 2]); }),
+            ("flagged_trades", |c, _| { c.flagged_trades.insert(7); }),
+            ("user_orders", |c, a| { c.user_orders.insert(a.clone(), vec![1]); }),
+            ("active_order_counts", |c, a| { c.active_order_counts.insert(a.clone(), 1); }),
+            ("approved_codehashes", |c, _| { c.approved_codehashes.insert("hash".to_string()); }),
+            ("voting_stakes", |c, a| { c.voting_stakes.insert(a.clone(), 1); }),
+            ("delegations", |c, a| { c.delegations.insert(a.clone(), account(OWNER)); }),
+            ("user_dao_votes", |c, a| { c.user_dao_votes.insert(a.clone(), vec![1]); }),
+            ("dao_vote_index", |c, _| { c.dao_vote_index.insert(("dao.near".to_string(), 1), 1); }),
+            ("dao_portfolios", |c, a| { c.dao_portfolios.insert(a.clone()); }),
+            ("used_dao_proposals", |c, a| { c.used_dao_proposals.insert((a.clone(), 1)); }),
+            ("vault_balances", |c, a| { c.vault_balances.insert((a.clone(), "usdc.near".to_string()), 1); }),
+            ("swap_pools", |c, _| { c.swap_pools.insert(("a".to_string(), "b".to_string()), 1); }),
+            ("pool_holdings", |c, _| { c.pool_holdings.insert("usdc.near".to_string(), 1); }),
+            ("upgrade_code", |c, _| { c.upgrade_code.insert("hash".to_string(), vec![0; 8]); }),
+            ("asset_prices", |c, _| { c.asset_prices.insert("XYZ".to_string(), "1.0".to_string()); }),
+        ];
+
+        for (field, mutate) in mutations {
+            let mut contract = setup();
+            register(&mut contract, "alice.near");
+            let alice = account("alice.near");
+            let bytes_before = contract.get_storage_bytes_used("alice.near".to_string());
+
+            call_as("alice.near");
+            let initial_storage = contract.begin_storage_charge(&alice);
+            mutate(&mut contract, &alice);
+            contract.settle_storage_charge(&alice, initial_storage);
+            assert!(contract.get_storage_bytes_used("alice.near".to_string()) > bytes_before, "{} is not flushed", field);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't unregister an account that still stores user data without force")]
+    fn unregistering_a_user_with_data_needs_force() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        call_with_deposit("alice.near", NearToken::from_yoctonear(1));
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn forced_unregister_releases_the_user_profile() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        call_as("alice.near");
+        let intent_id = contract.submit_intent("rebalance into stablecoins".to_string(), None);
+        let users_before = contract.total_users;
+
+        call_with_deposit("alice.near", NearToken::from_yoctonear(1));
+        assert!(contract.storage_unregister(Some(true)));
+        let alice = account("alice.near");
+        assert!(contract.storage_balance_of(alice.clone()).is_none());
+        assert!(!contract.users.contains(&alice));
+        assert!(contract.user_portfolios.get(&alice).is_none());
+        assert!(contract.user_preferences.get(&alice).is_none());
+        assert_eq!(contract.total_users, users_before - 1);
+        // The intent stays as history
+        assert!(contract.get_intent(intent_id).is_some());
+    }
+}
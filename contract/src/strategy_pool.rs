@@ -0,0 +1,291 @@
+use near_sdk::{
+    env, json_types::U128, log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId,
+};
+use schemars::JsonSchema;
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt, VaultBalance, SHARE_DECIMALS};
+
+// Share math values holdings in integer micro-USD
+pub const MICRO_USD: u128 = 1_000_000;
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolNav {
+    pub total_value_usd: String,
+    pub total_shares: String,
+    pub share_price_usd: String,
+    pub holdings: Vec<VaultBalance>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolPosition {
+    pub account_id: String,
+    pub shares: String,
+    pub value_usd: String,
+    pub pool_percentage: String,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Pool deposits move vault balances into the pool and mint shares at current NAV
+    #[payable]
+    pub fn pool_deposit(&mut self, token_id: String, amount: U128) -> U128 {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = self.begin_storage_charge(&account_id);
+        let amount = amount.0;
+        let token = self.vault_tokens.get(&token_id).cloned().expect("Token not supported by vault");
+        require!(amount > 0, "Deposit amount must be positive");
+        require!(
+            self.share_balances.contains_key(&account_id),
+            "Register with storage_deposit before joining the pool"
+        );
+
+        let deposit_value = self.token_value_micro_usd(&token, amount).expect("Token has no price; cannot value deposit");
+        require!(deposit_value > 0, "Deposit too small to value");
+        // Skipping an unpriced holding would understate NAV and hand new depositors extra shares
+        let nav = self.priced_pool_nav().expect("A pool holding has no price; deposits are paused");
+        self.internal_accrue_pool_fees();
+
+        let shares = if self.share_total_supply == 0 || nav == 0 {
+            // First depositor sets the share price at $1
+            mul_div(deposit_value, 10u128.pow(SHARE_DECIMALS as u32), MICRO_USD)
+        } else {
+            mul_div(self.share_total_supply, deposit_value, nav)
+        };
+        require!(shares > 0, "Deposit too small to mint shares");
+
+        self.internal_vault_withdraw(&account_id, &token_id, amount);
+        let holding = self.pool_holdings.get(&token_id).copied().unwrap_or(0);
+        self.pool_holdings.insert(token_id.clone(), holding + amount);
+        self.internal_mint_shares(&account_id, shares, "pool_deposit");
+        self.settle_storage_charge(&account_id, initial_storage);
+
+        log!("{} deposited {} of {} into the pool for {} shares", account_id, amount, token_id, shares);
+        U128(shares)
+    }
+
+    // Burning shares returns a pro-rata slice of every pool holding to the vault
+    #[payable]
+    pub fn pool_withdraw(&mut self, shares: U128) -> Vec<VaultBalance> {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = self.begin_storage_charge(&account_id);
+        let shares = shares.0;
+        require!(shares > 0, "Share amount must be positive");
+        self.internal_accrue_pool_fees();
+        let total_supply = self.share_total_supply;
+
+        self.internal_burn_shares(&account_id, shares, "pool_withdraw");
+
+        let mut returned = Vec::new();
+        let holdings: Vec<(String, u128)> = self.pool_holdings.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        for (token_id, holding) in holdings {
+            let amount = mul_div(holding, shares, total_supply);
+            if amount == 0 {
+                continue;
+            }
+            if holding == amount {
+                self.pool_holdings.remove(&token_id);
+            } else {
+                self.pool_holdings.insert(token_id.clone(), holding - amount);
+            }
+            self.internal_vault_deposit(&account_id, &token_id, amount);
+
+            let token = self.vault_tokens.get(&token_id).unwrap().clone();
+            returned.push(VaultBalance {
+                token_id: token_id.clone(),
+                symbol: token.symbol.clone(),
+                balance: amount.to_string(),
+                value_usd: format!("{:.2}", self.token_value_usd(&token, amount)),
+            });
+        }
+
+        self.settle_storage_charge(&account_id, initial_storage);
+        log!("{} redeemed {} pool shares", account_id, shares);
+        returned
+    }
+
+    // Pool views
+    pub fn get_pool_nav(&self) -> PoolNav {
+        let nav = self.pool_nav_usd();
+        PoolNav {
+            total_value_usd: format!("{:.2}", nav),
+            total_shares: self.share_total_supply.to_string(),
+            share_price_usd: format!("{:.6}", self.share_price_usd()),
+            holdings: self
+                .pool_holdings
+                .iter()
+                .filter_map(|(token_id, amount)| {
+                    let token = self.vault_tokens.get(token_id)?;
+                    Some(VaultBalance {
+                        token_id: token_id.clone(),
+                        symbol: token.symbol.clone(),
+                        balance: amount.to_string(),
+                        value_usd: format!("{:.2}", self.token_value_usd(token, *amount)),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get_share_price(&self) -> String {
+        format!("{:.6}", self.share_price_usd())
+    }
+
+    pub fn get_pool_position(&self, account_id: String) -> PoolPosition {
+        let account_id: AccountId = account_id.parse().unwrap();
+        let shares = self.share_balances.get(&account_id).copied().unwrap_or(0);
+        let fraction = if self.share_total_supply > 0 {
+            shares as f64 / self.share_total_supply as f64
+        } else {
+            0.0
+        };
+
+        PoolPosition {
+            account_id: account_id.to_string(),
+            shares: shares.to_string(),
+            value_usd: format!("{:.2}", self.pool_nav_usd() * fraction),
+            pool_percentage: format!("{:.2}", fraction * 100.0),
+        }
+    }
+
+    // Pool helpers
+    // NAV for share math, in micro-USD; None while any holding is unpriced
+    pub(crate) fn priced_pool_nav(&self) -> Option<u128> {
+        self.pool_holdings.iter().try_fold(0u128, |nav, (token_id, amount)| {
+            let token = self.vault_tokens.get(token_id)?;
+            nav.checked_add(self.token_value_micro_usd(token, *amount)?)
+        })
+    }
+
+    // Display NAV; unpriced holdings count as zero
+    pub(crate) fn pool_nav_usd(&self) -> f64 {
+        self.pool_holdings
+            .iter()
+            .filter_map(|(token_id, amount)| {
+                let token = self.vault_tokens.get(token_id)?;
+                Some(self.token_value_usd(token, *amount))
+            })
+            .sum()
+    }
+
+    fn share_price_usd(&self) -> f64 {
+        if self.share_total_supply == 0 {
+            return 1.0;
+        }
+        self.pool_nav_usd() / (self.share_total_supply as f64 / 10f64.powi(SHARE_DECIMALS as i32))
+    }
+}
+
+// amount * numerator / denominator, rounded down, with a 256-bit intermediate product.
+// Panics if the result does not fit in u128.
+pub(crate) fn mul_div(amount: u128, numerator: u128, denominator: u128) -> u128 {
+    require!(denominator > 0, "Division by zero in share math");
+    if let Some(product) = amount.checked_mul(numerator) {
+        return product / denominator;
+    }
+
+    const LOW: u128 = u64::MAX as u128;
+    let (a_high, a_low) = (amount >> 64, amount & LOW);
+    let (b_high, b_low) = (numerator >> 64, numerator & LOW);
+    let (low_low, low_high, high_low) = (a_low * b_low, a_low * b_high, a_high * b_low);
+    let middle = (low_low >> 64) + (low_high & LOW) + (high_low & LOW);
+    let low = (low_low & LOW) | (middle << 64);
+    let high = a_high * b_high + (low_high >> 64) + (high_low >> 64) + (middle >> 64);
+    require!(high < denominator, "Share math overflow");
+
+    // Long division of high:low by the denominator, one bit at a time
+    let (mut remainder, mut quotient) = (high, 0u128);
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+    quotient
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::NearToken;
+
+    use super::*;
+    use crate::test_utils::*;
+    use crate::NEAR_TOKEN_ID;
+
+    const ONE_NEAR: u128 = 10u128.pow(24);
+    const ONE_SHARE: u128 = 10u128.pow(SHARE_DECIMALS as u32);
+
+    // alice holds 2 NEAR and bob 5 USDC in the vault, at $5 and $1
+    fn setup_pool() -> AIPortfolioRebalancer {
+        let mut contract = setup();
+        contract.register_vault_token("usdc.near".to_string(), "USDC".to_string(), 6);
+        contract.update_asset_price("NEAR".to_string(), "5".to_string());
+        contract.update_asset_price("USDC".to_string(), "1".to_string());
+        register(&mut contract, "alice.near");
+        register(&mut contract, "bob.near");
+
+        call_with_deposit("alice.near", NearToken::from_near(2));
+        contract.vault_deposit_near();
+        call_as("usdc.near");
+        contract.ft_on_transfer(account("bob.near"), U128(5_000_000), String::new());
+        contract
+    }
+
+    #[test]
+    fn shares_are_minted_at_nav_with_integer_math() {
+        let mut contract = setup_pool();
+        call_as("alice.near");
+        assert_eq!(contract.pool_deposit(NEAR_TOKEN_ID.to_string(), U128(2 * ONE_NEAR)).0, 10 * ONE_SHARE);
+        call_as("bob.near");
+        assert_eq!(contract.pool_deposit("usdc.near".to_string(), U128(5_000_000)).0, 5 * ONE_SHARE);
+        assert_eq!(contract.priced_pool_nav(), Some(15 * MICRO_USD));
+        assert_eq!(contract.get_share_price(), "1.000000");
+    }
+
+    #[test]
+    #[should_panic(expected = "A pool holding has no price; deposits are paused")]
+    fn deposits_pause_while_a_holding_is_unpriced() {
+        let mut contract = setup_pool();
+        call_as("bob.near");
+        contract.pool_deposit("usdc.near".to_string(), U128(5_000_000));
+        call_as(OWNER);
+        contract.update_asset_price("USDC".to_string(), "0".to_string());
+
+        call_as("alice.near");
+        contract.pool_deposit(NEAR_TOKEN_ID.to_string(), U128(ONE_NEAR));
+    }
+
+    #[test]
+    fn withdrawals_do_not_need_prices() {
+        let mut contract = setup_pool();
+        call_as("bob.near");
+        let shares = contract.pool_deposit("usdc.near".to_string(), U128(5_000_000));
+        call_as(OWNER);
+        contract.update_asset_price("USDC".to_string(), "0".to_string());
+
+        call_as("bob.near");
+        let returned = contract.pool_withdraw(shares);
+        assert_eq!(returned[0].balance, "5000000");
+        assert_eq!(contract.ft_total_supply().0, 0);
+    }
+
+    #[test]
+    fn mul_div_is_exact_past_u128_products() {
+        assert_eq!(mul_div(7, 3, 2), 10);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(mul_div(u128::MAX, 3, 6), u128::MAX / 2);
+        assert_eq!(mul_div(10u128.pow(30), 10u128.pow(30), 10u128.pow(36)), 10u128.pow(24));
+    }
+
+    #[test]
+    #[should_panic(expected = "Share math overflow")]
+    fn mul_div_rejects_results_above_u128() {
+        mul_div(u128::MAX, 2, 1);
+    }
+}
@@ -0,0 +1,632 @@
+use near_sdk::{
+    env::{self, block_timestamp},
+    ext_contract, log, near, require,
+    json_types::U128,
+    serde::{Deserialize, Serialize},
+    AccountId, Gas, NearToken, Promise, PromiseError, PromiseOrValue,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{ext_ft, format_token_amount, AIPortfolioRebalancer, AIPortfolioRebalancerExt, Trade, VaultToken, NEAR_TOKEN_ID};
+
+const FT_TRANSFER_CALL_GAS: Gas = Gas::from_tgas(50);
+const EXCHANGE_SWAP_GAS: Gas = Gas::from_tgas(20);
+const EXCHANGE_WITHDRAW_GAS: Gas = Gas::from_tgas(50);
+const ON_SWAP_WITHDRAWN_GAS: Gas = Gas::from_tgas(20);
+const ON_SWAP_EXECUTED_GAS: Gas = Gas::from_tgas(80); // withdraw + on_swap_withdrawn
+const ON_SWAP_DEPOSITED_GAS: Gas = Gas::from_tgas(110); // swap + on_swap_executed
+// A swap whose callbacks never ran (e.g. out of gas) stops blocking new swaps after this long
+const SWAP_CREDIT_BYTES: u64 = 600; // a new vault balance entry and its portfolio holding
+pub const SWAP_LOCK_TIMEOUT_NS: u64 = 10 * 60 * 1_000_000_000;
+
+// Ref Finance exchange interface: tokens are deposited with an empty-msg ft_transfer_call,
+// swapped inside the exchange, then withdrawn back to this contract
+#[allow(dead_code)]
+#[ext_contract(ext_exchange)]
+pub trait RefExchange {
+    fn swap(&mut self, actions: Vec<ExchangeSwapAction>, referral_id: Option<AccountId>) -> U128;
+    fn withdraw(&mut self, token_id: AccountId, amount: U128, unregister: Option<bool>) -> U128;
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExchangeSwapAction {
+    pub pool_id: u64,
+    pub token_in: AccountId,
+    pub amount_in: Option<U128>,
+    pub token_out: AccountId,
+    pub min_amount_out: U128,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapConfig {
+    pub exchange_id: Option<String>,
+    pub pools: Vec<SwapPool>,
+    pub in_flight_trade_id: Option<u64>,
+    pub in_flight_since: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapPool {
+    pub token_in: String,
+    pub token_out: String,
+    pub pool_id: u64,
+}
+
+// One swap runs at a time. Every callback releases the lock; the timeout and
+// reset_swap_lock cover callbacks that never ran.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct SwapLock {
+    pub trade_id: u64,
+    pub started_at: u64,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Swap configuration
+    pub fn set_swap_exchange(&mut self, exchange_id: String) {
+        self.require_owner();
+        // In-flight callbacks withdraw from the configured exchange
+        require!(self.swap_lock.is_none(), "A swap is in progress");
+        self.swap_exchange_id = Some(exchange_id.parse().expect("Invalid exchange id"));
+    }
+
+    // The contract must be registered for storage on both tokens and on the exchange
+    pub fn set_swap_pool(&mut self, token_in: String, token_out: String, pool_id: u64) {
+        self.require_owner();
+        require!(
+            self.vault_tokens.contains_key(&token_in) && self.vault_tokens.contains_key(&token_out),
+            "Both tokens must be registered vault tokens"
+        );
+        require!(token_in != NEAR_TOKEN_ID && token_out != NEAR_TOKEN_ID, "Swaps only support NEP-141 tokens");
+        self.swap_pools.insert((token_in, token_out), pool_id);
+    }
+
+    pub fn get_swap_config(&self) -> SwapConfig {
+        SwapConfig {
+            exchange_id: self.swap_exchange_id.as_ref().map(|id| id.to_string()),
+            pools: self
+                .swap_pools
+                .iter()
+                .map(|((token_in, token_out), pool_id)| SwapPool {
+                    token_in: token_in.clone(),
+                    token_out: token_out.clone(),
+                    pool_id: *pool_id,
+                })
+                .collect(),
+            in_flight_trade_id: self.swap_lock.as_ref().map(|lock| lock.trade_id),
+            in_flight_since: self.swap_lock.as_ref().map(|lock| lock.started_at),
+        }
+    }
+
+    // Executes a NEAR-side trade from the intent owner's vault balance. The amount is the
+    // trade's own, and min_amount_out is quoted from that amount at current prices.
+    pub fn execute_swap(&mut self, trade_id: u64) -> Promise {
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.owner_id || self.trusted_workers.contains(&caller),
+            "Only owner or a trusted worker can execute swaps"
+        );
+        self.require_swap_unlocked();
+        let exchange_id = self.swap_exchange_id.clone().expect("Swap exchange not configured");
+
+        let mut trade = self.trades.get(&trade_id).cloned().expect("Trade not found");
+        require!(trade.status == "pending", "Trade is not pending");
+        let (token_in, token_out) = self.swap_tokens(&trade);
+        require!(
+            self.swap_pools.contains_key(&(token_in.token_id.clone(), token_out.token_id.clone())),
+            "No swap pool configured for this pair"
+        );
+        let amount_in = parse_token_amount(&trade.amount, token_in.decimals, false);
+        require!(amount_in > 0, "Trade amount must be positive");
+        require!(
+            self.price_of(&token_in.symbol).is_some() && self.price_of(&token_out.symbol).is_some(),
+            "Both tokens need a price to bound the swap output"
+        );
+
+        // Re-quote from the exact amount being swapped so min_output matches it
+        trade.amount = format_token_amount(amount_in, token_in.decimals);
+        let max_slippage_bps = trade.max_slippage_bps;
+        self.quote_trade(&mut trade, max_slippage_bps);
+        let min_amount_out = parse_token_amount(&trade.min_output, token_out.decimals, true);
+        require!(min_amount_out > 0, "Swap output bound rounds to zero");
+
+        // Funds leave the user's vault balance up front and come back in the callbacks, which
+        // must not fail, so the owner's storage balance is checked for their credit here
+        let user_id = self.trade_owner(&trade);
+        require!(
+            self.can_cover_storage(&user_id, SWAP_CREDIT_BYTES),
+            "Trade owner's storage balance can't cover the swap output"
+        );
+        self.flush_collections();
+        let initial_storage = env::storage_usage();
+        self.internal_vault_withdraw(&user_id, &token_in.token_id, amount_in);
+
+        trade.status = "executing".to_string();
+        self.trades.insert(trade_id, trade);
+        self.settle_storage_charge(&user_id, initial_storage);
+        self.swap_lock = Some(SwapLock { trade_id, started_at: block_timestamp() });
+
+        log!(
+            "Swapping {} {} for at least {} {} via {} (trade {})",
+            amount_in, token_in.token_id, min_amount_out, token_out.token_id, exchange_id, trade_id
+        );
+        // An empty msg deposits into this contract's exchange account
+        ext_ft::ext(token_in.token_id.parse().unwrap())
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(FT_TRANSFER_CALL_GAS)
+            .ft_transfer_call(exchange_id, U128(amount_in), Some(format!("Trade {}", trade_id)), String::new())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_SWAP_DEPOSITED_GAS)
+                    .on_swap_deposited(trade_id, U128(amount_in), U128(min_amount_out)),
+            )
+    }
+
+    #[private]
+    pub fn on_swap_deposited(
+        &mut self,
+        trade_id: u64,
+        amount_in: U128,
+        min_amount_out: U128,
+        #[callback_result] used_amount: Result<U128, PromiseError>,
+    ) -> PromiseOrValue<bool> {
+        // A failed ft_transfer_call is fully refunded by the token's resolve step
+        let deposited = used_amount.map_or(0, |used| std::cmp::min(used.0, amount_in.0));
+        if deposited == 0 {
+            return PromiseOrValue::Value(self.finish_swap(trade_id, amount_in.0, 0, 0));
+        }
+        let trade = self.trades.get(&trade_id).cloned().expect("Trade not found");
+        let (token_in, token_out) = self.swap_tokens(&trade);
+        let pool_id = self.swap_pools[&(token_in.token_id.clone(), token_out.token_id.clone())];
+        if deposited < amount_in.0 {
+            // Keep only what reached the exchange in play
+            let user_id = self.trade_owner(&trade);
+            self.flush_collections();
+            let initial_storage = env::storage_usage();
+            self.internal_vault_deposit(&user_id, &token_in.token_id, amount_in.0 - deposited);
+            self.record_storage_charge(&user_id, initial_storage);
+        }
+        let exchange_id = self.swap_exchange_id.clone().unwrap();
+
+        let action = ExchangeSwapAction {
+            pool_id,
+            token_in: token_in.token_id.parse().unwrap(),
+            amount_in: Some(U128(deposited)),
+            token_out: token_out.token_id.parse().unwrap(),
+            min_amount_out,
+        };
+        ext_exchange::ext(exchange_id)
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(EXCHANGE_SWAP_GAS)
+            .swap(vec![action], None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_SWAP_EXECUTED_GAS)
+                    .on_swap_executed(trade_id, U128(deposited)),
+            )
+            .into()
+    }
+
+    // The exchange reports the output it credited; that amount is withdrawn. A failed swap
+    // leaves the input on deposit, so the input is withdrawn instead.
+    #[private]
+    pub fn on_swap_executed(
+        &mut self,
+        trade_id: u64,
+        amount_in: U128,
+        #[callback_result] amount_out: Result<U128, PromiseError>,
+    ) -> Promise {
+        let amount_out = amount_out.map_or(0, |out| out.0);
+        let trade = self.trades.get(&trade_id).cloned().expect("Trade not found");
+        let (token_in, token_out) = self.swap_tokens(&trade);
+        if amount_out == 0 {
+            log!("Swap for trade {} failed at the exchange; withdrawing the input", trade_id);
+        }
+        let (token_id, amount) = if amount_out > 0 { (token_out, amount_out) } else { (token_in, amount_in.0) };
+        self.withdraw_from_exchange(trade_id, &token_id.token_id, amount, amount_in.0, amount_out)
+    }
+
+    #[private]
+    pub fn on_swap_withdrawn(
+        &mut self,
+        trade_id: u64,
+        amount_in: U128,
+        amount_out: U128,
+        #[callback_result] withdrawn: Result<U128, PromiseError>,
+    ) -> bool {
+        // The exchange restores the deposit and reports 0 when its transfer to us fails
+        if withdrawn.map_or(true, |amount| amount.0 == 0) {
+            // Funds stay on deposit at the exchange until the owner retries the withdrawal
+            let mut trade = self.trades.get(&trade_id).cloned().expect("Trade not found");
+            trade.status = "withdraw_pending".to_string();
+            let (token_in, token_out) = self.swap_tokens(&trade);
+            trade.amount = format_token_amount(amount_in.0, token_in.decimals);
+            trade.actual_output = format_token_amount(amount_out.0, token_out.decimals);
+            self.trades.insert(trade_id, trade);
+            self.release_swap_lock(trade_id);
+            log!("Withdrawal for trade {} failed; retry with retry_swap_withdrawal", trade_id);
+            return false;
+        }
+
+        if amount_out.0 > 0 {
+            self.finish_swap(trade_id, 0, amount_in.0, amount_out.0)
+        } else {
+            self.finish_swap(trade_id, amount_in.0, 0, 0)
+        }
+    }
+
+    // Recovery
+    pub fn retry_swap_withdrawal(&mut self, trade_id: u64) -> Promise {
+        self.require_owner();
+        self.require_swap_unlocked();
+        let trade = self.trades.get(&trade_id).cloned().expect("Trade not found");
+        require!(trade.status == "withdraw_pending", "Trade has no pending exchange withdrawal");
+        let (token_in, token_out) = self.swap_tokens(&trade);
+        let amount_in = parse_token_amount(&trade.amount, token_in.decimals, false);
+        let amount_out = parse_token_amount(&trade.actual_output, token_out.decimals, false);
+
+        let mut trade = trade;
+        trade.status = "executing".to_string();
+        self.trades.insert(trade_id, trade);
+        self.swap_lock = Some(SwapLock { trade_id, started_at: block_timestamp() });
+        let (token_id, amount) = if amount_out > 0 { (token_out, amount_out) } else { (token_in, amount_in) };
+        self.withdraw_from_exchange(trade_id, &token_id.token_id, amount, amount_in, amount_out)
+    }
+
+    // Clears a lock whose callbacks will never run; the trade is left for manual review
+    pub fn reset_swap_lock(&mut self) -> Option<u64> {
+        self.require_owner();
+        let lock = self.swap_lock.take()?;
+        log!("Swap lock for trade {} reset by owner", lock.trade_id);
+        Some(lock.trade_id)
+    }
+
+    // Swap helpers
+    fn withdraw_from_exchange(&mut self, trade_id: u64, token_id: &str, amount: u128, amount_in: u128, amount_out: u128) -> Promise {
+        ext_exchange::ext(self.swap_exchange_id.clone().expect("Swap exchange not configured"))
+            .with_attached_deposit(NearToken::from_yoctonear(1))
+            .with_static_gas(EXCHANGE_WITHDRAW_GAS)
+            .withdraw(token_id.parse().unwrap(), U128(amount), None)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_SWAP_WITHDRAWN_GAS)
+                    .on_swap_withdrawn(trade_id, U128(amount_in), U128(amount_out)),
+            )
+    }
+
+    // One swap is in flight at a time; a lock older than SWAP_LOCK_TIMEOUT_NS is taken over
+    fn require_swap_unlocked(&self) {
+        if let Some(lock) = &self.swap_lock {
+            require!(
+                block_timestamp().saturating_sub(lock.started_at) > SWAP_LOCK_TIMEOUT_NS,
+                "Another swap is in progress"
+            );
+            log!("Swap lock for trade {} timed out", lock.trade_id);
+        }
+    }
+
+    // A timed-out swap's late callbacks must not release a newer swap's lock
+    fn release_swap_lock(&mut self, trade_id: u64) {
+        if self.swap_lock.as_ref().is_some_and(|lock| lock.trade_id == trade_id) {
+            self.swap_lock = None;
+        }
+    }
+
+    // Returns `refund` of token_in to the owner's vault and credits `output` of token_out
+    fn finish_swap(&mut self, trade_id: u64, refund: u128, used_amount: u128, output: u128) -> bool {
+        self.release_swap_lock(trade_id);
+        let mut trade = self.trades.get(&trade_id).cloned().expect("Trade not found");
+        let (token_in, token_out) = self.swap_tokens(&trade);
+        let user_id = self.trade_owner(&trade);
+
+        self.flush_collections();
+        let initial_storage = env::storage_usage();
+        if refund > 0 {
+            self.internal_vault_deposit(&user_id, &token_in.token_id, refund);
+        }
+        if output > 0 {
+            self.internal_vault_deposit(&user_id, &token_out.token_id, output);
+        }
+        self.record_storage_charge(&user_id, initial_storage);
+
+        let success = used_amount > 0 && output > 0;
+        trade.amount = format_token_amount(if success { used_amount } else { refund }, token_in.decimals);
+        trade.actual_output = format_token_amount(output, token_out.decimals);
+        let violation = success && self.violates_min_output(&trade, &trade.actual_output);
+        trade.status = match (success, violation) {
+            (true, false) => "confirmed",
+            (true, true) => "flagged",
+            _ => "failed",
+        }
+        .to_string();
+        if success {
+            self.record_trade_slippage(&trade, violation);
+        }
+        if violation {
+            self.flagged_trades.insert(trade_id);
+        }
+        log!("Swap for trade {} {}: {} in, {} out", trade_id, trade.status, used_amount, output);
+        let intent_id = trade.intent_id;
+        self.trades.insert(trade_id, trade);
+        self.settle_rebalance(intent_id);
+        success
+    }
+
+    fn swap_tokens(&self, trade: &Trade) -> (VaultToken, VaultToken) {
+        require!(trade.from_chain == "near" && trade.to_chain == "near", "Only NEAR-side trades can be swapped");
+        let find = |symbol: &str| {
+            self.vault_tokens
+                .values()
+                .find(|token| token.token_id != NEAR_TOKEN_ID && (token.symbol == symbol || token.token_id == symbol))
+                .cloned()
+                .unwrap_or_else(|| env::panic_str(&format!("{} is not a NEP-141 vault token", symbol)))
+        };
+        (find(&trade.from_asset), find(&trade.to_asset))
+    }
+
+    fn trade_owner(&self, trade: &Trade) -> AccountId {
+        let intent = self.intents.get(&trade.intent_id).expect("Intent not found");
+        intent.user_id.parse().unwrap()
+    }
+}
+
+// Decimal string to raw units. Amounts to spend round down; output bounds round up so
+// they are never loosened.
+fn parse_token_amount(amount: &str, decimals: u8, round_up: bool) -> u128 {
+    let (whole, fraction) = amount.split_once('.').unwrap_or((amount, ""));
+    let whole: u128 = whole.parse().unwrap_or(0);
+    let mut raw = whole * 10u128.pow(decimals as u32);
+    let digits = fraction.len().min(decimals as usize);
+    if digits > 0 {
+        let scaled: u128 = fraction[..digits].parse().unwrap_or(0);
+        raw += scaled * 10u128.pow(decimals as u32 - digits as u32);
+    }
+    if round_up && !fraction[digits..].trim_end_matches('0').is_empty() {
+        raw + 1
+    } else {
+        raw
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::testing_env;
+
+    const ONE: u128 = 1_000_000_000_000_000_000;
+    const USER: &str = "alice.near";
+    const TOKEN_A: &str = "tka.near";
+    const TOKEN_B: &str = "tkb.near";
+
+    // Alice holds 10 TKA in the vault and has a pending 4 TKA -> TKB trade at 1 TKA = 2 TKB
+    fn swap_setup() -> (AIPortfolioRebalancer, u64) {
+        let mut contract = setup();
+        register(&mut contract, USER);
+        call_as(OWNER);
+        contract.register_vault_token(TOKEN_A.to_string(), "TKA".to_string(), 18);
+        contract.register_vault_token(TOKEN_B.to_string(), "TKB".to_string(), 18);
+        contract.update_asset_price("TKA".to_string(), "2".to_string());
+        contract.update_asset_price("TKB".to_string(), "1".to_string());
+        contract.set_swap_exchange("exchange.near".to_string());
+        contract.set_swap_pool(TOKEN_A.to_string(), TOKEN_B.to_string(), 1);
+
+        call_as(TOKEN_A);
+        let _ = contract.ft_on_transfer(account(USER), U128(10 * ONE), String::new());
+        call_as(USER);
+        let intent_id = contract.submit_intent("swap some TKA into TKB".to_string(), None);
+
+        call_as(OWNER);
+        let trade_id = contract.create_trade(
+            intent_id,
+            Trade {
+                id: 0,
+                intent_id,
+                from_asset: "TKA".to_string(),
+                to_asset: "TKB".to_string(),
+                from_chain: "near".to_string(),
+                to_chain: "near".to_string(),
+                amount: "4".to_string(),
+                expected_output: "0".to_string(),
+                actual_output: "0".to_string(),
+                status: "pending".to_string(),
+                tx_hash: String::new(),
+                timestamp: 0,
+                gas_used: "0".to_string(),
+                min_output: "0".to_string(),
+                max_slippage_bps: 0,
+            },
+        );
+        (contract, trade_id)
+    }
+
+    fn balance(contract: &AIPortfolioRebalancer, token_id: &str) -> u128 {
+        contract.get_vault_balance(USER.to_string(), token_id.to_string()).0
+    }
+
+    #[test]
+    fn swap_amount_and_bound_come_from_the_trade() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+
+        // 4 TKA at $2 quotes 8 TKB; the default 1% slippage bounds it at 7.92
+        let trade = contract.get_trade(trade_id).unwrap();
+        assert_eq!(trade.status, "executing");
+        assert_eq!(trade.min_output, "7.92000000");
+        assert_eq!(balance(&contract, TOKEN_A), 6 * ONE);
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, Some(trade_id));
+    }
+
+    #[test]
+    fn confirmed_swap_credits_the_amount_the_exchange_reported() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+
+        call_as(CONTRACT);
+        let _ = contract.on_swap_deposited(trade_id, U128(4 * ONE), U128(792 * ONE / 100), Ok(U128(4 * ONE)));
+        let _ = contract.on_swap_executed(trade_id, U128(4 * ONE), Ok(U128(8 * ONE)));
+        assert!(contract.on_swap_withdrawn(trade_id, U128(4 * ONE), U128(8 * ONE), Ok(U128(8 * ONE))));
+
+        let trade = contract.get_trade(trade_id).unwrap();
+        assert_eq!(trade.status, "confirmed");
+        assert_eq!(trade.amount, "4");
+        assert_eq!(trade.actual_output, "8");
+        assert_eq!(balance(&contract, TOKEN_A), 6 * ONE);
+        assert_eq!(balance(&contract, TOKEN_B), 8 * ONE);
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, None);
+    }
+
+    #[test]
+    fn swap_output_below_the_quote_floor_is_flagged() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+
+        // 7.9 TKB is under the 7.92 floor; the exchange only enforced its rounded bound
+        call_as(CONTRACT);
+        assert!(contract.on_swap_withdrawn(trade_id, U128(4 * ONE), U128(79 * ONE / 10), Ok(U128(79 * ONE / 10))));
+        assert_eq!(contract.get_trade(trade_id).unwrap().status, "flagged");
+        let stats = contract.get_asset_slippage_stats("TKB".to_string());
+        assert_eq!((stats.trades, stats.violations), (1, 1));
+        assert_eq!(contract.get_flagged_trades().len(), 1);
+    }
+
+    #[test]
+    fn failed_deposit_refunds_and_releases_the_lock() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+        call_as(CONTRACT);
+        let _ = contract.on_swap_deposited(trade_id, U128(4 * ONE), U128(1), Err(PromiseError::Failed));
+        assert_eq!(contract.get_trade(trade_id).unwrap().status, "failed");
+        assert_eq!(balance(&contract, TOKEN_A), 10 * ONE);
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, None);
+    }
+
+    #[test]
+    fn slippage_failure_withdraws_and_refunds_the_input() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+        call_as(CONTRACT);
+        let _ = contract.on_swap_executed(trade_id, U128(4 * ONE), Err(PromiseError::Failed));
+        assert!(!contract.on_swap_withdrawn(trade_id, U128(4 * ONE), U128(0), Ok(U128(4 * ONE))));
+        let trade = contract.get_trade(trade_id).unwrap();
+        assert_eq!(trade.status, "failed");
+        assert_eq!(trade.actual_output, "0");
+        assert_eq!(balance(&contract, TOKEN_A), 10 * ONE);
+        assert_eq!(balance(&contract, TOKEN_B), 0);
+    }
+
+    #[test]
+    fn failed_withdrawal_waits_for_an_owner_retry() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+        call_as(CONTRACT);
+        assert!(!contract.on_swap_withdrawn(trade_id, U128(4 * ONE), U128(8 * ONE), Ok(U128(0))));
+        assert_eq!(contract.get_trade(trade_id).unwrap().status, "withdraw_pending");
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, None);
+
+        call_as(OWNER);
+        let _ = contract.retry_swap_withdrawal(trade_id);
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, Some(trade_id));
+        call_as(CONTRACT);
+        assert!(contract.on_swap_withdrawn(trade_id, U128(4 * ONE), U128(8 * ONE), Ok(U128(8 * ONE))));
+        assert_eq!(contract.get_trade(trade_id).unwrap().status, "confirmed");
+        assert_eq!(balance(&contract, TOKEN_B), 8 * ONE);
+    }
+
+    #[test]
+    #[should_panic(expected = "Another swap is in progress")]
+    fn retried_withdrawal_waits_for_the_swap_lock() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+        call_as(CONTRACT);
+        assert!(!contract.on_swap_withdrawn(trade_id, U128(4 * ONE), U128(8 * ONE), Ok(U128(0))));
+
+        call_as(OWNER);
+        let mut trade = contract.get_trade(trade_id).unwrap();
+        trade.amount = "1".to_string();
+        let second = contract.create_trade(trade.intent_id, trade);
+        let _ = contract.execute_swap(second);
+        let _ = contract.retry_swap_withdrawal(trade_id);
+    }
+
+    #[test]
+    fn swap_output_is_charged_to_the_trade_owner() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+        let bytes_before = contract.get_storage_bytes_used(USER.to_string());
+
+        call_as(CONTRACT);
+        assert!(contract.on_swap_withdrawn(trade_id, U128(4 * ONE), U128(8 * ONE), Ok(U128(8 * ONE))));
+        assert!(contract.get_storage_bytes_used(USER.to_string()) > bytes_before);
+        assert_eq!(contract.get_storage_bytes_used(OWNER.to_string()), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Another swap is in progress")]
+    fn lock_blocks_a_second_swap_until_it_times_out() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+        let trade = contract.get_trade(trade_id).unwrap();
+        let second = contract.create_trade(trade.intent_id, trade);
+        let _ = contract.execute_swap(second);
+    }
+
+    #[test]
+    fn stale_lock_times_out_and_late_callbacks_keep_the_new_lock() {
+        let (mut contract, first) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(first);
+        let mut trade = contract.get_trade(first).unwrap();
+        trade.amount = "1".to_string();
+        let second = contract.create_trade(trade.intent_id, trade);
+
+        let started = contract.get_swap_config().in_flight_since.unwrap();
+        testing_env!(context(OWNER).block_timestamp(started + SWAP_LOCK_TIMEOUT_NS + 1).build());
+        let _ = contract.execute_swap(second);
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, Some(second));
+
+        // The first swap's callbacks finally run without releasing the second swap's lock
+        call_as(CONTRACT);
+        let _ = contract.on_swap_deposited(first, U128(4 * ONE), U128(1), Err(PromiseError::Failed));
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, Some(second));
+        assert_eq!(balance(&contract, TOKEN_A), 9 * ONE);
+    }
+
+    #[test]
+    fn owner_can_reset_a_stuck_lock() {
+        let (mut contract, trade_id) = swap_setup();
+        call_as(OWNER);
+        let _ = contract.execute_swap(trade_id);
+        assert_eq!(contract.reset_swap_lock(), Some(trade_id));
+        assert_eq!(contract.get_swap_config().in_flight_trade_id, None);
+
+    }
+
+    #[test]
+    #[should_panic(expected = "Only owner can call this method")]
+    fn only_the_owner_resets_the_lock() {
+        let (mut contract, _) = swap_setup();
+        call_as(USER);
+        contract.reset_swap_lock();
+    }
+
+    #[test]
+    fn output_bounds_round_up_and_inputs_round_down() {
+        assert_eq!(parse_token_amount("1.0000001", 6, false), 1_000_000);
+        assert_eq!(parse_token_amount("1.0000001", 6, true), 1_000_001);
+        assert_eq!(parse_token_amount("7.92000000", 18, true), 792 * ONE / 100);
+    }
+}
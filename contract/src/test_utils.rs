@@ -0,0 +1,52 @@
+use near_sdk::{test_utils::VMContextBuilder, testing_env, AccountId, NearToken, PromiseResult, RuntimeFeesConfig};
+
+use crate::AIPortfolioRebalancer;
+
+pub(crate) const OWNER: &str = "owner.near";
+pub(crate) const CONTRACT: &str = "rebalancer.near";
+
+pub(crate) fn account(id: &str) -> AccountId {
+    id.parse().unwrap()
+}
+
+pub(crate) fn context(predecessor: &str) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(account(CONTRACT))
+        .signer_account_id(account(predecessor))
+        .predecessor_account_id(account(predecessor))
+        .account_balance(NearToken::from_near(1_000));
+    builder
+}
+
+pub(crate) fn call_as(predecessor: &str) {
+    testing_env!(context(predecessor).build());
+}
+
+pub(crate) fn call_with_deposit(predecessor: &str, deposit: NearToken) {
+    testing_env!(context(predecessor).attached_deposit(deposit).build());
+}
+
+// Runs the next call as the contract's own callback, with `result` as the awaited promise's outcome
+pub(crate) fn callback_with(result: PromiseResult) {
+    testing_env!(
+        context(CONTRACT).build(),
+        near_sdk::test_vm_config(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![result]
+    );
+}
+
+pub(crate) fn setup() -> AIPortfolioRebalancer {
+    call_as(OWNER);
+    AIPortfolioRebalancer::new(OWNER.to_string())
+}
+
+// Registers `user` with a storage deposit large enough for any test
+pub(crate) fn register(contract: &mut AIPortfolioRebalancer, user: &str) {
+    call_with_deposit(user, NearToken::from_near(5));
+    contract.storage_deposit(None, None);
+    call_as(user);
+    contract.register_user();
+}
@@ -0,0 +1,307 @@
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, is_promise_success,
+    json_types::U128,
+    log, near, require,
+    serde::{Deserialize, Serialize},
+    AccountId, Gas, NearToken, Promise, PromiseOrValue,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+use schemars::JsonSchema;
+
+use crate::{mul_div, AIPortfolioRebalancer, AIPortfolioRebalancerExt, PortfolioAsset, MICRO_USD};
+
+pub const NEAR_TOKEN_ID: &str = "NEAR"; // uppercase so it can never collide with a token account id
+// NEAR's own precision; also keeps 10^decimals and share math well inside u128
+pub const MAX_TOKEN_DECIMALS: u8 = 24;
+const FT_TRANSFER_GAS: Gas = Gas::from_tgas(10);
+const WITHDRAW_CALLBACK_GAS: Gas = Gas::from_tgas(10);
+
+// NEP-141 interface used for withdrawals and swaps
+#[allow(dead_code)]
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+    fn ft_transfer_call(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>, msg: String) -> U128;
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+}
+
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultToken {
+    pub token_id: String, // NEP-141 contract, or "NEAR" for native NEAR
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultBalance {
+    pub token_id: String,
+    pub symbol: String,
+    pub balance: String, // raw units
+    pub value_usd: String,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Token registry
+    pub fn register_vault_token(&mut self, token_id: String, symbol: String, decimals: u8) -> String {
+        self.require_owner();
+        require!(token_id != NEAR_TOKEN_ID, "Native NEAR is always supported");
+        token_id.parse::<AccountId>().expect("Invalid token contract id");
+        require!(
+            decimals <= MAX_TOKEN_DECIMALS,
+            format!("Token decimals must be at most {}", MAX_TOKEN_DECIMALS)
+        );
+
+        self.vault_tokens.insert(token_id.clone(), VaultToken {
+            token_id: token_id.clone(),
+            symbol: symbol.clone(),
+            decimals,
+        });
+        format!("Vault token {} ({}) registered", symbol, token_id)
+    }
+
+    pub fn get_vault_tokens(&self) -> Vec<VaultToken> {
+        self.vault_tokens.values().cloned().collect()
+    }
+
+    // Deposits
+    #[payable]
+    pub fn vault_deposit_near(&mut self) -> U128 {
+        let account_id = env::predecessor_account_id();
+        let amount = env::attached_deposit().as_yoctonear();
+        require!(amount > 0, "Attach NEAR to deposit");
+        // The attached NEAR is the deposit itself, so storage comes from the NEP-145 balance
+        let initial_storage = env::storage_usage();
+
+        let balance = self.internal_vault_deposit(&account_id, NEAR_TOKEN_ID, amount);
+        self.settle_storage_charge(&account_id, initial_storage);
+        log!("{} deposited {} yoctoNEAR into vault", account_id, amount);
+        U128(balance)
+    }
+
+    // NEP-141 receiver: tokens are credited to the sender's vault balance
+    pub fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id().to_string();
+        if !self.vault_tokens.contains_key(&token_id) {
+            log!("Refunding unsupported token {}", token_id);
+            return PromiseOrValue::Value(amount);
+        }
+
+        // A sender without enough storage balance panics here and the token refunds the transfer
+        let initial_storage = env::storage_usage();
+        self.internal_vault_deposit(&sender_id, &token_id, amount.0);
+        self.settle_storage_charge(&sender_id, initial_storage);
+        log!("{} deposited {} of {} into vault (msg: {})", sender_id, amount.0, token_id, msg);
+        PromiseOrValue::Value(U128(0))
+    }
+
+    // Withdrawals are debited up front and rolled back if the transfer fails
+    #[payable]
+    pub fn vault_withdraw(&mut self, token_id: String, amount: U128) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = amount.0;
+        require!(amount > 0, "Withdraw amount must be positive");
+
+        let initial_storage = env::storage_usage();
+        self.internal_vault_withdraw(&account_id, &token_id, amount);
+        self.settle_storage_charge(&account_id, initial_storage);
+
+        let transfer = if token_id == NEAR_TOKEN_ID {
+            Promise::new(account_id.clone()).transfer(NearToken::from_yoctonear(amount))
+        } else {
+            ext_ft::ext(token_id.parse().unwrap())
+                .with_attached_deposit(NearToken::from_yoctonear(1))
+                .with_static_gas(FT_TRANSFER_GAS)
+                .ft_transfer(account_id.clone(), U128(amount), Some("Vault withdrawal".to_string()))
+        };
+
+        log!("{} withdrawing {} of {} from vault", account_id, amount, token_id);
+        transfer.then(
+            Self::ext(env::current_account_id())
+                .with_static_gas(WITHDRAW_CALLBACK_GAS)
+                .on_vault_withdraw(account_id, token_id, U128(amount)),
+        )
+    }
+
+    #[private]
+    pub fn on_vault_withdraw(&mut self, account_id: AccountId, token_id: String, amount: U128) -> bool {
+        if is_promise_success() {
+            return true;
+        }
+
+        self.internal_vault_deposit(&account_id, &token_id, amount.0);
+        log!("Withdrawal of {} {} failed, balance restored for {}", amount.0, token_id, account_id);
+        false
+    }
+
+    // Vault views
+    pub fn get_vault_balance(&self, account_id: String, token_id: String) -> U128 {
+        let account_id: AccountId = account_id.parse().unwrap();
+        U128(self.vault_balance(&account_id, &token_id))
+    }
+
+    pub fn get_vault_balances(&self, account_id: String) -> Vec<VaultBalance> {
+        let account_id: AccountId = account_id.parse().unwrap();
+        self.vault_tokens
+            .values()
+            .filter_map(|token| {
+                let balance = self.vault_balance(&account_id, &token.token_id);
+                if balance == 0 {
+                    return None;
+                }
+                Some(VaultBalance {
+                    token_id: token.token_id.clone(),
+                    symbol: token.symbol.clone(),
+                    balance: balance.to_string(),
+                    value_usd: format!("{:.2}", self.token_value_usd(token, balance)),
+                })
+            })
+            .collect()
+    }
+
+    // Vault helpers
+    pub(crate) fn vault_balance(&self, account_id: &AccountId, token_id: &str) -> u128 {
+        self.vault_balances
+            .get(&(account_id.clone(), token_id.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn internal_vault_deposit(&mut self, account_id: &AccountId, token_id: &str, amount: u128) -> u128 {
+        let balance = self.vault_balance(account_id, token_id) + amount;
+        self.vault_balances.insert((account_id.clone(), token_id.to_string()), balance);
+        self.adjust_fee_high_water_mark_for(account_id, token_id, amount as f64);
+        self.sync_vault_portfolio(account_id);
+        balance
+    }
+
+    pub(crate) fn internal_vault_withdraw(&mut self, account_id: &AccountId, token_id: &str, amount: u128) -> u128 {
+        let balance = self.vault_balance(account_id, token_id);
+        require!(balance >= amount, "Insufficient vault balance");
+
+        let key = (account_id.clone(), token_id.to_string());
+        if balance == amount {
+            self.vault_balances.remove(&key);
+        } else {
+            self.vault_balances.insert(key, balance - amount);
+        }
+        self.adjust_fee_high_water_mark_for(account_id, token_id, -(amount as f64));
+        self.sync_vault_portfolio(account_id);
+        balance - amount
+    }
+
+    pub(crate) fn token_value_usd(&self, token: &VaultToken, amount: u128) -> f64 {
+        let price: f64 = self
+            .asset_prices
+            .get(&token.symbol)
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0.0);
+        amount as f64 / 10f64.powi(token.decimals as i32) * price
+    }
+
+    // Exact valuation in micro-USD for share math; None when the token has no price
+    pub(crate) fn token_value_micro_usd(&self, token: &VaultToken, amount: u128) -> Option<u128> {
+        let price = self.asset_prices.get(&token.symbol).and_then(|p| parse_micro_usd(p))?;
+        if price == 0 {
+            return None;
+        }
+        Some(mul_div(amount, price, 10u128.pow(token.decimals as u32)))
+    }
+
+    // NEAR-side holdings always come from vault balances, never from self-reported data
+    pub(crate) fn merge_vault_assets(&self, account_id: &AccountId, portfolio: Vec<PortfolioAsset>) -> Vec<PortfolioAsset> {
+        let mut merged: Vec<PortfolioAsset> = portfolio.into_iter().filter(|a| a.chain != "near").collect();
+
+        for token in self.vault_tokens.values() {
+            let balance = self.vault_balance(account_id, &token.token_id);
+            if balance == 0 {
+                continue;
+            }
+            merged.push(PortfolioAsset {
+                token_symbol: token.symbol.clone(),
+                token_address: token.token_id.clone(),
+                balance: format_token_amount(balance, token.decimals),
+                chain: "near".to_string(),
+                value_usd: format!("{:.2}", self.token_value_usd(token, balance)),
+                percentage: "0.0".to_string(),
+            });
+        }
+
+        let total_value: f64 = merged.iter().map(|a| a.value_usd.parse::<f64>().unwrap_or(0.0)).sum();
+        if total_value > 0.0 {
+            for asset in merged.iter_mut() {
+                let value: f64 = asset.value_usd.parse().unwrap_or(0.0);
+                asset.percentage = format!("{:.2}", value / total_value * 100.0);
+            }
+        }
+
+        merged
+    }
+
+    fn adjust_fee_high_water_mark_for(&mut self, account_id: &AccountId, token_id: &str, signed_amount: f64) {
+        if let Some(token) = self.vault_tokens.get(token_id).cloned() {
+            let value = self.token_value_usd(&token, signed_amount.abs() as u128);
+            self.adjust_fee_high_water_mark(account_id, value.copysign(signed_amount));
+        }
+    }
+
+    fn sync_vault_portfolio(&mut self, account_id: &AccountId) {
+        if !self.users.contains(account_id) {
+            return;
+        }
+        let portfolio = self.user_portfolios.get(account_id).cloned().unwrap_or_default();
+        let merged = self.merge_vault_assets(account_id, portfolio);
+        self.user_portfolios.insert(account_id.clone(), merged);
+        self.update_portfolio_health(account_id.clone());
+    }
+}
+
+// "12.345678" -> 12_345_678; digits past the sixth decimal are truncated
+pub(crate) fn parse_micro_usd(price: &str) -> Option<u128> {
+    let (whole, fraction) = price.split_once('.').unwrap_or((price, ""));
+    let whole: u128 = whole.parse().ok()?;
+    let fraction = format!("{:0<6}", &fraction[..fraction.len().min(6)]);
+    whole.checked_mul(MICRO_USD)?.checked_add(fraction.parse().ok()?)
+}
+
+pub(crate) fn format_token_amount(amount: u128, decimals: u8) -> String {
+    let divisor = 10u128.pow(decimals as u32);
+    let whole = amount / divisor;
+    let fraction = amount % divisor;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let fraction = format!("{:0width$}", fraction, width = decimals as usize);
+    format!("{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn token_amounts_format_at_every_supported_precision() {
+        assert_eq!(format_token_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_token_amount(7, 0), "7");
+        assert_eq!(format_token_amount(10u128.pow(24) + 1, MAX_TOKEN_DECIMALS), "1.000000000000000000000001");
+    }
+
+    #[test]
+    fn prices_parse_to_micro_usd() {
+        assert_eq!(parse_micro_usd("5"), Some(5_000_000));
+        assert_eq!(parse_micro_usd("0.1234567"), Some(123_456));
+        assert_eq!(parse_micro_usd("3.2"), Some(3_200_000));
+        assert_eq!(parse_micro_usd("n/a"), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Token decimals must be at most 24")]
+    fn tokens_above_near_precision_are_rejected() {
+        let mut contract = setup();
+        contract.register_vault_token("big.near".to_string(), "BIG".to_string(), 39);
+    }
+}
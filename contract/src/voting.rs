@@ -0,0 +1,292 @@
+use near_sdk::{
+    env, json_types::U128, log, near, require,
+    store::Vector,
+    AccountId, NearToken, Promise,
+    borsh::{BorshDeserialize, BorshSerialize},
+};
+
+use crate::{AIPortfolioRebalancer, AIPortfolioRebalancerExt};
+
+// Voting power checkpoint, recorded whenever an account's delegated power changes
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub struct Checkpoint {
+    pub block_height: u64,
+    pub power: u128,
+}
+
+#[near]
+impl AIPortfolioRebalancer {
+    // Stake management
+    #[payable]
+    pub fn deposit_voting_stake(&mut self) -> String {
+        let account_id = env::predecessor_account_id();
+        let amount = env::attached_deposit().as_yoctonear();
+        require!(amount > 0, "Attach NEAR to deposit voting stake");
+        // The attached NEAR is stake, so storage comes from the NEP-145 balance
+        let initial_storage = env::storage_usage();
+
+        let stake = self.voting_stakes.get(&account_id).copied().unwrap_or(0);
+        self.voting_stakes.insert(account_id.clone(), stake + amount);
+
+        let delegatee = self.get_delegatee(&account_id);
+        self.move_voting_power(None, Some(&delegatee), amount);
+        self.settle_storage_charge(&account_id, initial_storage);
+
+        log!("{} deposited {} yoctoNEAR voting stake", account_id, amount);
+        format!("Voting stake is now {}", stake + amount)
+    }
+
+    pub fn withdraw_voting_stake(&mut self, amount: U128) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = amount.0;
+        let stake = self.voting_stakes.get(&account_id).copied().unwrap_or(0);
+        require!(amount > 0 && amount <= stake, "Insufficient voting stake");
+        let initial_storage = env::storage_usage();
+
+        if stake == amount {
+            self.voting_stakes.remove(&account_id);
+        } else {
+            self.voting_stakes.insert(account_id.clone(), stake - amount);
+        }
+
+        let delegatee = self.get_delegatee(&account_id);
+        self.move_voting_power(Some(&delegatee), None, amount);
+        self.settle_storage_charge(&account_id, initial_storage);
+
+        log!("{} withdrew {} yoctoNEAR voting stake", account_id, amount);
+        Promise::new(account_id).transfer(NearToken::from_yoctonear(amount))
+    }
+
+    // Delegation
+    #[payable]
+    pub fn delegate_votes(&mut self, delegatee: String) -> String {
+        let account_id = env::predecessor_account_id();
+        let initial_storage = self.begin_storage_charge(&account_id);
+        let delegatee: AccountId = delegatee.parse().unwrap();
+        let current = self.get_delegatee(&account_id);
+        require!(current != delegatee, "Already delegated to this account");
+
+        if delegatee == account_id {
+            self.delegations.remove(&account_id);
+        } else {
+            self.delegations.insert(account_id.clone(), delegatee.clone());
+        }
+
+        let stake = self.voting_stakes.get(&account_id).copied().unwrap_or(0);
+        self.move_voting_power(Some(&current), Some(&delegatee), stake);
+        self.settle_storage_charge(&account_id, initial_storage);
+
+        log!("{} delegated voting power to {}", account_id, delegatee);
+        format!("Voting power delegated to {}", delegatee)
+    }
+
+    pub fn undelegate_votes(&mut self) -> String {
+        let account_id = env::predecessor_account_id();
+        self.delegate_votes(account_id.to_string())
+    }
+
+    // Voting power views
+    pub fn get_voting_stake(&self, account_id: String) -> U128 {
+        let account_id: AccountId = account_id.parse().unwrap();
+        U128(self.voting_stakes.get(&account_id).copied().unwrap_or(0))
+    }
+
+    pub fn get_delegate(&self, account_id: String) -> String {
+        let account_id: AccountId = account_id.parse().unwrap();
+        self.get_delegatee(&account_id).to_string()
+    }
+
+    pub fn get_voting_power(&self, account_id: String) -> U128 {
+        let account_id: AccountId = account_id.parse().unwrap();
+        let power = self
+            .voting_power_checkpoints
+            .get(&account_id)
+            .and_then(|checkpoints| checkpoints.len().checked_sub(1).and_then(|last| checkpoints.get(last)))
+            .map_or(0, |c| c.power);
+        U128(power)
+    }
+
+    pub fn get_voting_power_at(&self, account_id: String, block_height: u64) -> U128 {
+        let account_id: AccountId = account_id.parse().unwrap();
+        U128(self.voting_power_before(&account_id, block_height.saturating_add(1)))
+    }
+
+    pub fn get_total_voting_power(&self) -> U128 {
+        U128(self.latest_total_voting_power())
+    }
+
+    // Voting power helpers
+    fn get_delegatee(&self, account_id: &AccountId) -> AccountId {
+        self.delegations.get(account_id).cloned().unwrap_or_else(|| account_id.clone())
+    }
+
+    fn move_voting_power(&mut self, from: Option<&AccountId>, to: Option<&AccountId>, amount: u128) {
+        if amount == 0 {
+            return;
+        }
+
+        let next_block = env::block_height().saturating_add(1);
+        if let Some(from) = from {
+            let power = self.voting_power_before(from, next_block);
+            self.write_checkpoint(from, power.checked_sub(amount).expect("Voting power underflow"));
+        }
+        if let Some(to) = to {
+            let power = self.voting_power_before(to, next_block);
+            self.write_checkpoint(to, power.checked_add(amount).expect("Voting power overflow"));
+        }
+
+        // Deposits and withdrawals change total supply; delegation only moves it
+        if from.is_none() || to.is_none() {
+            let total = self.latest_total_voting_power();
+            let total = if from.is_none() { total.checked_add(amount) } else { total.checked_sub(amount) };
+            let total = total.expect("Total voting power out of range");
+            let block_height = env::block_height();
+            let len = self.total_voting_power_checkpoints.len();
+            if len > 0 && self.total_voting_power_checkpoints[len - 1].block_height == block_height {
+                self.total_voting_power_checkpoints[len - 1].power = total;
+            } else {
+                self.total_voting_power_checkpoints.push(Checkpoint { block_height, power: total });
+            }
+        }
+    }
+
+    // Each account's history is its own Vector, so lookups read O(log n) entries however long it grows
+    fn write_checkpoint(&mut self, account_id: &AccountId, power: u128) {
+        let block_height = env::block_height();
+        if !self.voting_power_checkpoints.contains_key(account_id) {
+            let mut prefix = b"J".to_vec();
+            prefix.extend(env::sha256(account_id.as_bytes()));
+            self.voting_power_checkpoints.insert(account_id.clone(), Vector::new(prefix));
+        }
+        let checkpoints = self.voting_power_checkpoints.get_mut(account_id).unwrap();
+
+        let last = checkpoints.len().checked_sub(1);
+        match last.and_then(|index| checkpoints.get_mut(index)) {
+            Some(last) if last.block_height == block_height => last.power = power,
+            _ => checkpoints.push(Checkpoint { block_height, power }),
+        }
+        // Inner vectors are not written by the outer map's flush
+        checkpoints.flush();
+    }
+
+    // Power held by an account at the end of the last block strictly before `block_height`
+    pub(crate) fn voting_power_before(&self, account_id: &AccountId, block_height: u64) -> u128 {
+        self.voting_power_checkpoints
+            .get(account_id)
+            .map_or(0, |checkpoints| power_before(checkpoints, block_height))
+    }
+
+    pub(crate) fn total_voting_power_before(&self, block_height: u64) -> u128 {
+        power_before(&self.total_voting_power_checkpoints, block_height)
+    }
+
+    fn latest_total_voting_power(&self) -> u128 {
+        let len = self.total_voting_power_checkpoints.len();
+        if len == 0 {
+            0
+        } else {
+            self.total_voting_power_checkpoints[len - 1].power
+        }
+    }
+}
+
+fn power_before(checkpoints: &Vector<Checkpoint>, block_height: u64) -> u128 {
+    let (mut low, mut high) = (0, checkpoints.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        if checkpoints[mid].block_height < block_height {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    if low == 0 {
+        0
+    } else {
+        checkpoints[low - 1].power
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::test_utils::*;
+    use crate::{ProposalKind, Vote};
+
+    fn call_at_block(predecessor: &str, block_height: u64, deposit: NearToken) {
+        testing_env!(context(predecessor).block_height(block_height).attached_deposit(deposit).build());
+    }
+
+    fn power_at(contract: &AIPortfolioRebalancer, account_id: &str, block_height: u64) -> u128 {
+        contract.get_voting_power_at(account_id.to_string(), block_height).0
+    }
+
+    #[test]
+    fn checkpoints_record_power_per_block() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        for block_height in 1..=20 {
+            call_at_block("alice.near", block_height * 10, NearToken::from_yoctonear(100));
+            contract.deposit_voting_stake();
+        }
+
+        assert_eq!(power_at(&contract, "alice.near", 9), 0);
+        assert_eq!(power_at(&contract, "alice.near", 10), 100);
+        assert_eq!(power_at(&contract, "alice.near", 55), 500);
+        assert_eq!(power_at(&contract, "alice.near", 200), 2000);
+        assert_eq!(contract.get_voting_power("alice.near".to_string()).0, 2000);
+        assert_eq!(contract.get_total_voting_power().0, 2000);
+        assert_eq!(contract.total_voting_power_before(101), 1000);
+    }
+
+    #[test]
+    fn power_lookup_at_max_block_height_does_not_overflow() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        call_at_block("alice.near", 3, NearToken::from_yoctonear(100));
+        contract.deposit_voting_stake();
+
+        assert_eq!(power_at(&contract, "alice.near", u64::MAX), 100);
+        call_at_block("alice.near", u64::MAX, NearToken::from_yoctonear(50));
+        contract.deposit_voting_stake();
+        assert_eq!(contract.get_voting_power("alice.near".to_string()).0, 150);
+    }
+
+    #[test]
+    fn delegation_moves_power_from_the_next_block() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        register(&mut contract, "bob.near");
+        call_at_block("alice.near", 1, NearToken::from_yoctonear(300));
+        contract.deposit_voting_stake();
+
+        call_at_block("alice.near", 5, NearToken::from_yoctonear(0));
+        contract.delegate_votes("bob.near".to_string());
+        assert_eq!(contract.get_delegate("alice.near".to_string()), "bob.near");
+        assert_eq!(power_at(&contract, "alice.near", 4), 300);
+        assert_eq!(power_at(&contract, "bob.near", 4), 0);
+        assert_eq!(power_at(&contract, "alice.near", 5), 0);
+        assert_eq!(power_at(&contract, "bob.near", 5), 300);
+        // Delegation moves power without changing the total
+        assert_eq!(contract.get_total_voting_power().0, 300);
+    }
+
+    #[test]
+    #[should_panic(expected = "No voting power at proposal snapshot")]
+    fn stake_added_after_a_proposal_cannot_vote() {
+        let mut contract = setup();
+        register(&mut contract, "alice.near");
+        call_at_block("alice.near", 2, NearToken::from_yoctonear(0));
+        let proposal_id = contract.create_proposal(
+            "add chain".to_string(),
+            ProposalKind::AddSupportedChain { chain: "base".to_string() },
+        );
+
+        call_at_block("alice.near", 2, NearToken::from_yoctonear(100));
+        contract.deposit_voting_stake();
+        call_at_block("alice.near", 3, NearToken::from_yoctonear(0));
+        contract.vote_on_proposal(proposal_id, Vote::Approve);
+    }
+}
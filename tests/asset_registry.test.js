@@ -0,0 +1,31 @@
+import test from 'ava';
+import { useContract } from './helpers.js';
+
+// Asset risk tiers and depeg handling
+
+useContract(test);
+
+test('risk tiers come from the asset registry and depegged stables are avoided', async (t) => {
+    const { root, alice, contract } = t.context;
+    const usdc = await contract.view('get_asset_metadata', { symbol: 'USDC' });
+    t.is(usdc.risk_tier, 'stable');
+    t.is((await contract.view('get_asset_metadata', { symbol: 'PEPE' })).risk_tier, 'long_tail');
+
+    await root.call(contract, 'set_asset_depegged', { symbol: 'USDC', depegged: true });
+    const simulation = await contract.view('simulate_intent', {
+        user_id: alice.accountId,
+        intent_text: 'make my portfolio safer',
+    });
+    t.false(simulation.target_allocations.some((asset) => asset.token_symbol === 'USDC'));
+    t.true(simulation.target_allocations.some((asset) => asset.token_symbol === 'USDT'));
+    t.true(simulation.current_health.risk_score < 66);
+    t.true(simulation.current_health.recommendations.some((rec) => /USDC has lost its peg/.test(rec)));
+
+    const error = await t.throwsAsync(
+        alice.call(contract, 'set_asset_metadata', {
+            metadata: { symbol: 'PEPE', risk_tier: 'stable', volatility_pct: null, depegged: false },
+        }),
+    );
+    t.regex(error.message, /Only owner can call this method/);
+    await root.call(contract, 'set_asset_depegged', { symbol: 'USDC', depegged: false });
+});
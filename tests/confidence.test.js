@@ -0,0 +1,28 @@
+import test from 'ava';
+import { analyzeIntent, useContract } from './helpers.js';
+
+// Confidence scores and their factors for analyzed intents
+
+useContract(test);
+
+// [intent text, expected score, expected factor scores in order: coverage, support, feasibility, preferences]
+const CONFIDENCE_CASES = [
+    ['sell all my btc for usdc', 100, [100, 100, 100, 100]],
+    ['move 20% of my ETH to USDC on arbitrum', 96, [100, 100, 100, 75]],
+    ['buy 2 eth with usdc', 75, [100, 100, 0, 100]],
+    ['hello world', 57, [0, 100, 71, 100]],
+];
+
+for (const [text, score, factorScores] of CONFIDENCE_CASES) {
+    test(`confidence: ${text}`, async (t) => {
+        const { alice, contract } = t.context;
+        const intent = await analyzeIntent(alice, contract, text);
+        t.is(intent.confidence_score, score);
+        t.deepEqual(
+            intent.confidence_factors.map((factor) => [factor.name, factor.score]),
+            ['parse_coverage', 'supported_references', 'balance_feasibility', 'preference_compliance'].map(
+                (name, i) => [name, factorScores[i]],
+            ),
+        );
+    });
+}
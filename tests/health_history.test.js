@@ -0,0 +1,27 @@
+import test from 'ava';
+import { PORTFOLIO, createUser, useContract } from './helpers.js';
+
+// Per-user health snapshots and score trends
+
+useContract(test);
+
+test('portfolio updates append health snapshots to a paginated history', async (t) => {
+    const { root, contract } = t.context;
+    const dave = await createUser(root, contract, 'dave');
+    await dave.call(contract, 'set_user_portfolio', { portfolio: PORTFOLIO.slice(0, 2) });
+
+    const history = await contract.view('get_health_history', { user_id: dave.accountId, from_index: 0, limit: 10 });
+    t.is(history.length, 2);
+    t.is(history[0].total_value_usd, '4800.00');
+    t.is(history[1].total_value_usd, '8000.00');
+    t.true(history[0].timestamp > history[1].timestamp);
+
+    const latest = await contract.view('get_portfolio_health', { user_id: dave.accountId });
+    t.is(latest.score, history[0].score);
+    // Both snapshots are minutes apart, so there is nothing 7 or 30 days old to compare against
+    t.is(latest.score_change_7d, null);
+    t.is(latest.score_change_30d, null);
+
+    const page = await contract.view('get_health_history', { user_id: dave.accountId, from_index: 1, limit: 10 });
+    t.deepEqual(page, history.slice(1));
+});
@@ -0,0 +1,36 @@
+import test from 'ava';
+import { analyzeIntent, createUser, useContract } from './helpers.js';
+
+// Before/after health on analyzed intents and the user's health floor
+
+useContract(test);
+
+test('analysis records health before and after and enforces the health floor', async (t) => {
+    const { root, contract } = t.context;
+    const bob = await createUser(root, contract, 'bob');
+    await bob.call(contract, 'set_user_preferences', {
+        preferences: {
+            risk_tolerance: 'medium',
+            investment_horizon: 'medium',
+            preferred_chains: ['ethereum', 'near'],
+            excluded_assets: [],
+            rebalance_threshold: '5.0',
+            auto_rebalance: false,
+            max_slippage_bps: 100,
+            min_health_score: 60,
+        },
+    });
+
+    // Dropping all stablecoins takes health from 68 to 52
+    const intent = await analyzeIntent(bob, contract, 'move all my usdc to eth');
+    t.is(intent.health_before.score, 68);
+    t.is(intent.health_after.score, 52);
+    t.is(intent.health_after.grade, 'F');
+    t.is(intent.status, 'rejected');
+    t.regex(intent.rejection_reason, /below the floor of 60/);
+
+    // Raising the stable share improves health and passes
+    const safer = await analyzeIntent(bob, contract, 'move half my btc to usdc');
+    t.true(safer.health_after.score >= safer.health_before.score);
+    t.is(safer.status, 'awaiting_approval');
+});
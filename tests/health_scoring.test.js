@@ -0,0 +1,44 @@
+import test from 'ava';
+import { PORTFOLIO, createUser, useContract } from './helpers.js';
+
+// HHI diversification and governance-tunable health weights and grades
+
+useContract(test);
+
+test('diversification is value-weighted, so dust positions do not count', async (t) => {
+    const { root, contract } = t.context;
+    const dust = ['USDT', 'DAI', 'WETH', 'WBTC', 'LINK', 'UNI', 'AAVE', 'COMP', 'BTC'].map((symbol) => ({
+        token_symbol: symbol,
+        token_address: symbol.toLowerCase(),
+        balance: '0.0001',
+        chain: 'near',
+        value_usd: '1.0',
+        percentage: '0',
+    }));
+    const carol = await createUser(root, contract, 'carol', [PORTFOLIO[0], ...dust]);
+
+    const health = await contract.view('get_portfolio_health', { user_id: carol.accountId });
+    t.true(health.diversification_score < 5);
+    t.is(health.concentration_risk, 99);
+});
+
+test('governance-tunable grade thresholds regrade the same score', async (t) => {
+    const { root, alice, contract } = t.context;
+    const config = await contract.view('get_health_scoring_config', {});
+    t.deepEqual(config.grade_thresholds, { a: 90, b: 80, c: 70, d: 60 });
+
+    await root.call(contract, 'set_health_scoring_config', {
+        config: { ...config, grade_thresholds: { a: 80, b: 70, c: 60, d: 50 } },
+    });
+    const health = await contract.view('simulate_intent', { user_id: alice.accountId, intent_text: 'hello' });
+    t.is(health.current_health.score, 68);
+    t.is(health.current_health.grade, 'C');
+
+    const error = await t.throwsAsync(
+        root.call(contract, 'set_health_scoring_config', {
+            config: { ...config, risk_weight: 50 },
+        }),
+    );
+    t.regex(error.message, /Score weights must sum to 100/);
+    await root.call(contract, 'set_health_scoring_config', { config });
+});
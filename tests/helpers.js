@@ -0,0 +1,58 @@
+import { Worker, NEAR } from 'near-workspaces';
+
+// Shared sandbox setup for the intent analysis and portfolio health scenarios.
+// Build the wasm first: yarn contract:build
+
+export const CONTRACT_WASM = './contract/target/near/contract.wasm';
+
+// ETH 2800, USDC 2000, NEAR 2000 and BTC 1200 USD at the default prices
+export const PORTFOLIO = [
+    ['ETH', 'eth', '1.0', 'ethereum', '2800.0'],
+    ['USDC', 'usdc.token', '2000.0', 'ethereum', '2000.0'],
+    ['NEAR', 'near', '800.0', 'near', '2000.0'],
+    ['BTC', 'btc', '0.0285714', 'ethereum', '1200.0'],
+].map(([token_symbol, token_address, balance, chain, value_usd]) => ({
+    token_symbol,
+    token_address,
+    balance,
+    chain,
+    value_usd,
+    percentage: '0',
+}));
+
+// Creates a funded account with a storage deposit and, unless portfolio is null, a portfolio
+export async function createUser(root, contract, name, portfolio = PORTFOLIO) {
+    const user = await root.createSubAccount(name, {
+        initialBalance: NEAR.parse('20 N').toJSON(),
+    });
+    await user.call(contract, 'storage_deposit', {}, { attachedDeposit: NEAR.parse('1 N').toJSON() });
+    if (portfolio) {
+        await user.call(contract, 'set_user_portfolio', { portfolio });
+    }
+    return user;
+}
+
+// Deploys a fresh contract before the file's tests, with alice holding PORTFOLIO
+export function useContract(test) {
+    test.before(async (t) => {
+        const worker = await Worker.init();
+        const root = worker.rootAccount;
+        const contract = await root.devDeploy(CONTRACT_WASM, {
+            method: 'new',
+            args: { owner_id: root.accountId },
+        });
+        const alice = await createUser(root, contract, 'alice');
+        t.context = { worker, root, alice, contract };
+    });
+
+    test.after.always(async (t) => {
+        await t.context.worker.tearDown().catch((e) => console.log('tearDown failed', e));
+    });
+}
+
+// Submits and analyzes an intent, then returns it as stored
+export async function analyzeIntent(user, contract, intentText) {
+    const intentId = await user.call(contract, 'submit_intent', { intent_text: intentText });
+    await user.call(contract, 'analyze_intent', { intent_id: intentId });
+    return contract.view('get_intent', { intent_id: intentId });
+}
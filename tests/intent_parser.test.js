@@ -0,0 +1,23 @@
+import test from 'ava';
+import { analyzeIntent, useContract } from './helpers.js';
+
+// Analysis of structured intents; the parser's own table tests live in
+// contract/src/intent_parser.rs
+
+useContract(test);
+
+test('a negated strategy no longer selects that strategy', async (t) => {
+    const { alice, contract } = t.context;
+    const intent = await analyzeIntent(alice, contract, 'I am not conservative at all');
+    t.is(intent.classification, 'balanced');
+});
+
+test('structured intents analyze into the resolved target allocations', async (t) => {
+    const { alice, contract } = t.context;
+    const intent = await analyzeIntent(alice, contract, 'sell all my btc for usdc');
+    t.is(intent.classification, 'structured');
+    t.deepEqual(
+        intent.target_allocations.map((asset) => [asset.token_symbol, asset.value_usd]),
+        [['ETH', '2800.00'], ['USDC', '3200.00'], ['NEAR', '2000.00']],
+    );
+});
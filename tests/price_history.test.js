@@ -0,0 +1,47 @@
+import test from 'ava';
+import { useContract } from './helpers.js';
+
+// Price history, realized volatility, correlations and value-at-risk
+
+const NANOS_PER_DAY = 86_400e9;
+
+useContract(test);
+
+test('price history feeds realized volatility, correlations and value-at-risk', async (t) => {
+    const { root, alice, contract } = t.context;
+    const eth = [2800, 2850, 2790, 2900, 2870, 2950, 2920];
+    const btc = [42000, 42500, 41800, 43000, 42700, 43500, 43100];
+    for (let i = 0; i < eth.length; i++) {
+        await root.call(contract, 'update_asset_price', { asset_symbol: 'ETH', price_usd: `${eth[i]}.0` });
+        await root.call(contract, 'update_asset_price', { asset_symbol: 'BTC', price_usd: `${btc[i]}.0` });
+    }
+
+    const history = await contract.view('get_price_history', { asset_symbol: 'ETH' });
+    t.deepEqual(history.map((point) => point.price_usd), eth.map((price) => `${price}.0`));
+    t.true(history.every((point, i) => i === 0 || point.timestamp > history[i - 1].timestamp));
+    t.is(await contract.view('get_asset_volatility', { asset_symbol: 'NEAR' }), null);
+    // Each log return is scaled to one day by its own interval
+    const daily = history.slice(1).map((point, i) => {
+        const days = (point.timestamp - history[i].timestamp) / NANOS_PER_DAY;
+        return Math.log(Number(point.price_usd) / Number(history[i].price_usd)) / Math.sqrt(days);
+    });
+    const mean = daily.reduce((sum, r) => sum + r, 0) / daily.length;
+    const stdDev = Math.sqrt(daily.reduce((sum, r) => sum + (r - mean) ** 2, 0) / (daily.length - 1));
+    t.is(await contract.view('get_asset_volatility', { asset_symbol: 'ETH' }), (stdDev * 100).toFixed(2));
+
+    const correlations = await contract.view('get_asset_correlations', { user_id: alice.accountId });
+    t.is(correlations.length, 1);
+    t.like(correlations[0], { asset_a: 'BTC', asset_b: 'ETH' });
+    t.true(Number(correlations[0].correlation) > 0.99);
+    t.true(Number(correlations[0].correlation) <= 1);
+
+    // Log-normal VaR from the reported volatility, always below the portfolio value
+    const portfolio = await contract.view('get_user_portfolio', { user_id: alice.accountId });
+    const total = portfolio.reduce((sum, asset) => sum + Number(asset.value_usd), 0);
+    const { current_health: health } = await contract.view('simulate_intent', { user_id: alice.accountId, intent_text: 'hello' });
+    const volatility = Number(health.volatility_pct) / 100;
+    const valueAtRisk = Number(health.value_at_risk_usd);
+    t.true(volatility > 0);
+    t.true(valueAtRisk > 0 && valueAtRisk < total);
+    t.true(Math.abs(valueAtRisk - total * (1 - Math.exp(-1.645 * volatility))) < 1);
+});
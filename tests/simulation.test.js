@@ -0,0 +1,38 @@
+import test from 'ava';
+import { useContract } from './helpers.js';
+
+// simulate_intent dry runs over the trade planner
+
+useContract(test);
+
+test('simulate_intent previews the plan without writing state', async (t) => {
+    const { alice, contract } = t.context;
+    const intentsBefore = await contract.view('get_user_intents', { user_id: alice.accountId });
+
+    const simulation = await contract.view('simulate_intent', {
+        user_id: alice.accountId,
+        intent_text: 'sell all my btc for usdc',
+    });
+    t.is(simulation.classification, 'structured');
+    t.deepEqual(
+        simulation.target_allocations.map((asset) => [asset.token_symbol, asset.value_usd]),
+        [['ETH', '2800.00'], ['USDC', '3200.00'], ['NEAR', '2000.00']],
+    );
+    t.deepEqual(
+        simulation.trades.map((trade) => [
+            trade.from_asset,
+            trade.to_asset,
+            trade.amount,
+            trade.expected_output,
+            trade.min_output,
+        ]),
+        [['BTC', 'USDC', '0.0285714', '1199.99880000', '1187.99881200']],
+    );
+    t.is(simulation.current_health.score, 68);
+    t.is(simulation.projected_health.score, 67);
+    t.is(simulation.projected_health.concentration_risk, 40);
+    t.is(simulation.estimated_gas_cost, '39.00');
+    t.is(simulation.rejection_reason, null);
+
+    t.deepEqual(await contract.view('get_user_intents', { user_id: alice.accountId }), intentsBefore);
+});
@@ -0,0 +1,199 @@
+import test from 'ava';
+import { Worker, NEAR } from 'near-workspaces';
+
+// Swap execution against a local sandbox with mock NEP-141 tokens and a
+// Ref-style mock exchange. Build the wasm files first:
+//   yarn contract:build && yarn contract:build:mocks
+
+const CONTRACT_WASM = './contract/target/near/contract.wasm';
+const MOCK_FT_WASM = './contract/mocks/mock-ft/target/near/mock_ft.wasm';
+const MOCK_EXCHANGE_WASM =
+    './contract/mocks/mock-exchange/target/near/mock_exchange.wasm';
+
+const ONE = 10n ** 18n;
+const GAS = '300000000000000';
+
+const trade = (intentId) => ({
+    id: 0,
+    intent_id: intentId,
+    from_asset: 'TKA',
+    to_asset: 'TKB',
+    from_chain: 'near',
+    to_chain: 'near',
+    amount: '4',
+    expected_output: '0',
+    actual_output: '0',
+    status: 'pending',
+    tx_hash: '',
+    timestamp: 0,
+    gas_used: '0',
+});
+
+test.beforeEach(async (t) => {
+    const worker = await Worker.init();
+    const root = worker.rootAccount;
+
+    const tokenA = await root.devDeploy(MOCK_FT_WASM, {
+        method: 'new',
+        args: { owner_id: root.accountId, total_supply: (1000n * ONE).toString() },
+    });
+    const tokenB = await root.devDeploy(MOCK_FT_WASM, {
+        method: 'new',
+        args: { owner_id: root.accountId, total_supply: (1000n * ONE).toString() },
+    });
+    const exchange = await root.devDeploy(MOCK_EXCHANGE_WASM, {
+        method: 'new',
+        args: {},
+    });
+    const contract = await root.devDeploy(CONTRACT_WASM, {
+        method: 'new',
+        args: { owner_id: root.accountId },
+    });
+    const alice = await root.createSubAccount('alice', {
+        initialBalance: NEAR.parse('20 N').toJSON(),
+    });
+
+    for (const token of [tokenA, tokenB]) {
+        for (const account of [contract, exchange, alice]) {
+            await root.call(token, 'storage_deposit', { account_id: account.accountId });
+        }
+    }
+
+    // 1 TKA -> 2 TKB, paid out of the exchange's TKB balance
+    await root.call(exchange, 'set_rate', { pool_id: 1, numerator: '2', denominator: '1' });
+    await root.call(
+        tokenB,
+        'ft_transfer',
+        { receiver_id: exchange.accountId, amount: (500n * ONE).toString() },
+        { attachedDeposit: '1' },
+    );
+    await root.call(
+        tokenA,
+        'ft_transfer',
+        { receiver_id: alice.accountId, amount: (100n * ONE).toString() },
+        { attachedDeposit: '1' },
+    );
+
+    await root.call(contract, 'register_vault_token', {
+        token_id: tokenA.accountId,
+        symbol: 'TKA',
+        decimals: 18,
+    });
+    await root.call(contract, 'register_vault_token', {
+        token_id: tokenB.accountId,
+        symbol: 'TKB',
+        decimals: 18,
+    });
+    // 1 TKA = $2 and 1 TKB = $1, so 4 TKA quotes 8 TKB with a 7.92 floor at 1% slippage
+    await root.call(contract, 'update_asset_price', { asset_symbol: 'TKA', price_usd: '2' });
+    await root.call(contract, 'update_asset_price', { asset_symbol: 'TKB', price_usd: '1' });
+    await root.call(contract, 'set_swap_exchange', { exchange_id: exchange.accountId });
+    await root.call(contract, 'set_swap_pool', {
+        token_in: tokenA.accountId,
+        token_out: tokenB.accountId,
+        pool_id: 1,
+    });
+
+    // Alice pays for her storage, then deposits 10 TKA into the vault
+    await alice.call(contract, 'storage_deposit', {}, { attachedDeposit: NEAR.parse('1 N').toJSON() });
+    await alice.call(
+        tokenA,
+        'ft_transfer_call',
+        { receiver_id: contract.accountId, amount: (10n * ONE).toString(), msg: '' },
+        { attachedDeposit: '1', gas: GAS },
+    );
+    const intentId = await alice.call(contract, 'submit_intent', {
+        intent_text: 'swap some TKA into TKB',
+    });
+    const tradeId = await root.call(contract, 'create_trade', {
+        intent_id: intentId,
+        trade_data: trade(intentId),
+    });
+
+    t.context = { worker, root, alice, tokenA, tokenB, exchange, contract, tradeId };
+});
+
+test.afterEach.always(async (t) => {
+    await t.context.worker.tearDown().catch((e) => console.log('tearDown failed', e));
+});
+
+test('swap confirms the trade with the real output amount', async (t) => {
+    const { root, alice, tokenA, tokenB, contract, tradeId } = t.context;
+
+    await root.call(contract, 'execute_swap', { trade_id: tradeId }, { gas: GAS });
+
+    const swapped = await contract.view('get_trade', { trade_id: tradeId });
+    t.is(swapped.status, 'confirmed');
+    t.is(swapped.amount, '4');
+    t.is(swapped.actual_output, '8');
+
+    const balanceA = await contract.view('get_vault_balance', {
+        account_id: alice.accountId,
+        token_id: tokenA.accountId,
+    });
+    const balanceB = await contract.view('get_vault_balance', {
+        account_id: alice.accountId,
+        token_id: tokenB.accountId,
+    });
+    t.is(balanceA, (6n * ONE).toString());
+    t.is(balanceB, (8n * ONE).toString());
+
+    const config = await contract.view('get_swap_config', {});
+    t.is(config.in_flight_trade_id, null);
+});
+
+test('swap below min_amount_out fails and restores the vault balance', async (t) => {
+    const { root, alice, tokenA, tokenB, exchange, contract, tradeId } = t.context;
+
+    // 4 TKA now returns 4 TKB, under the quoted 7.92 floor
+    await root.call(exchange, 'set_rate', { pool_id: 1, numerator: '1', denominator: '1' });
+    await root.call(contract, 'execute_swap', { trade_id: tradeId }, { gas: GAS });
+
+    const swapped = await contract.view('get_trade', { trade_id: tradeId });
+    t.is(swapped.status, 'failed');
+    t.is(swapped.actual_output, '0');
+
+    const balanceA = await contract.view('get_vault_balance', {
+        account_id: alice.accountId,
+        token_id: tokenA.accountId,
+    });
+    const balanceB = await contract.view('get_vault_balance', {
+        account_id: alice.accountId,
+        token_id: tokenB.accountId,
+    });
+    t.is(balanceA, (10n * ONE).toString());
+    t.is(balanceB, '0');
+
+    // The input went back through the exchange and nothing is left on deposit there
+    const deposit = await exchange.view('get_deposit', {
+        account_id: contract.accountId,
+        token_id: tokenA.accountId,
+    });
+    t.is(deposit, '0');
+    const config = await contract.view('get_swap_config', {});
+    t.is(config.in_flight_trade_id, null);
+});
+
+test('only owner or a trusted worker can execute swaps', async (t) => {
+    const { alice, contract, tradeId } = t.context;
+
+    // Workers are added by the owner or governance, so alice can't make herself one
+    const registerError = await t.throwsAsync(
+        alice.call(contract, 'register_worker', {
+            quote_hex: 'codehash',
+            collateral: '',
+            checksum: 'checksum',
+        }),
+    );
+    t.regex(registerError.message, /Only owner can call this method/);
+
+    const error = await t.throwsAsync(
+        alice.call(
+            contract,
+            'execute_swap',
+            { trade_id: tradeId },
+            { gas: GAS },
+        ),
+    );
+    t.regex(error.message, /Only owner or a trusted worker can execute swaps/);
+});
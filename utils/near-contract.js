@@ -4,6 +4,10 @@ import { connect, Contract, keyStores, WalletConnection, utils } from 'near-api-
 const CONTRACT_NAME = process.env.NEXT_PUBLIC_CONTRACT_NAME || 'ai-portfolio.testnet';
 const NETWORK_ID = process.env.NEXT_PUBLIC_NETWORK_ID || 'testnet';
 
+// The contract charges each account for the bytes it stores (NEP-145). Calls that store data
+// attach nothing and draw on the storage balance, which is topped up by this much when low.
+const STORAGE_TOP_UP = utils.format.parseNearAmount('0.1');
+
 // NEAR configuration
 const nearConfig = {
     networkId: NETWORK_ID,
@@ -53,10 +57,13 @@ class PortfolioContract {
                         'get_total_volume_usd',
                         'get_user_intents',
                         'get_user_trades',
-                        'get_owner'
+                        'get_owner',
+                        'storage_balance_of',
+                        'storage_balance_bounds'
                     ],
                     changeMethods: [
-                        'set_portfolio',
+                        'storage_deposit',
+                        'set_user_portfolio',
                         'set_user_preferences',
                         'submit_intent',
                         'update_intent_analysis',
@@ -91,7 +98,7 @@ class PortfolioContract {
             this.walletConnection.requestSignIn({
                 contractId: CONTRACT_NAME,
                 methodNames: [
-                    'set_portfolio',
+                    'set_user_portfolio',
                     'set_user_preferences', 
                     'submit_intent',
                     'execute_rebalance'
@@ -110,6 +117,26 @@ class PortfolioContract {
         }
     }
 
+    // Registers the account for storage, or tops up its storage balance when it runs low.
+    // Attaching a deposit sends the user through the wallet, so this is skipped when not needed.
+    async ensureStorageBalance() {
+        const accountId = this.getAccountId();
+        if (!accountId) throw new Error('No account ID available');
+
+        const balance = await this.contract.storage_balance_of({ account_id: accountId });
+        let deposit = null;
+        if (!balance) {
+            const bounds = await this.contract.storage_balance_bounds();
+            deposit = (BigInt(bounds.min) + BigInt(STORAGE_TOP_UP)).toString();
+        } else if (BigInt(balance.available) < BigInt(STORAGE_TOP_UP) / 2n) {
+            deposit = STORAGE_TOP_UP;
+        }
+
+        if (deposit) {
+            await this.contract.storage_deposit({ account_id: accountId }, '30000000000000', deposit);
+        }
+    }
+
     // Get user's portfolio
     async getUserPortfolio(userId = null) {
         try {
@@ -135,10 +162,11 @@ class PortfolioContract {
                 throw new Error('Please sign in to update portfolio');
             }
 
-            const result = await this.contract.set_portfolio(
-                { assets },
+            await this.ensureStorageBalance();
+            const result = await this.contract.set_user_portfolio(
+                { portfolio: assets },
                 '300000000000000', // 300 TGas
-                '0' // No deposit required
+                '0' // Storage is paid from the account's storage balance
             );
 
             console.log('Portfolio updated:', result);
@@ -174,10 +202,11 @@ class PortfolioContract {
                 throw new Error('Please sign in to update preferences');
             }
 
+            await this.ensureStorageBalance();
             const result = await this.contract.set_user_preferences(
                 { preferences },
                 '300000000000000', // 300 TGas
-                '0' // No deposit required
+                '0' // Storage is paid from the account's storage balance
             );
 
             console.log('Preferences updated:', result);
@@ -195,12 +224,13 @@ class PortfolioContract {
                 throw new Error('Please sign in to submit intent');
             }
 
+            await this.ensureStorageBalance();
             const result = await this.contract.submit_intent(
                 {
                     intent_text: intentText
                 },
                 '300000000000000', // 300 TGas
-                '0' // No deposit required
+                '0' // Storage is paid from the account's storage balance
             );
 
             console.log('Intent submitted:', result);